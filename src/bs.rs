@@ -0,0 +1,294 @@
+/// bad checksum probes (same as nmap --badsum)
+use pistol::Target;
+use pistol::layer::IPV4_HEADER_SIZE;
+use pistol::layer::Layer3Match;
+use pistol::layer::Layer4MatchIcmp;
+use pistol::layer::Layer4MatchTcpUdp;
+use pistol::layer::LayerMatch;
+use pistol::layer::PayloadMatch;
+use pistol::layer::PayloadMatchIp;
+use pistol::layer::PayloadMatchTcpUdp;
+use pistol::layer::TCP_HEADER_SIZE;
+use pistol::layer::UDP_HEADER_SIZE;
+use pistol::layer::infer_addr;
+use pistol::layer::layer3_ipv4_send;
+use pnet::packet::ip::IpNextHeaderProtocols;
+use pnet::packet::ipv4;
+use pnet::packet::ipv4::Ipv4Flags;
+use pnet::packet::ipv4::MutableIpv4Packet;
+use pnet::packet::tcp;
+use pnet::packet::tcp::MutableTcpPacket;
+use pnet::packet::tcp::TcpFlags;
+use pnet::packet::udp;
+use pnet::packet::udp::MutableUdpPacket;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use crate::PROBE_DATA;
+use crate::SRC_ADDR;
+use crate::ps::PortScanningMethod;
+use crate::sport::next_source_port;
+
+const TTL: u8 = 64;
+
+/// A real tcp/ip stack drops the packets with bad checksum silently,
+/// so any response means a firewall or IDS answered without checking it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BadsumReport {
+    pub addr: IpAddr,
+    pub port: u16,
+    pub responded: bool,
+    pub rtt: Duration,
+}
+
+impl BadsumReport {
+    pub fn label(&self) -> &'static str {
+        if self.responded {
+            "responded to bad checksum (non-compliant filtering stack)"
+        } else {
+            "no response"
+        }
+    }
+}
+
+/// The tcp flags of the scan method, None means the method does not send the raw tcp probes.
+pub fn tcp_flags(ps_method: PortScanningMethod) -> Option<u8> {
+    match ps_method {
+        PortScanningMethod::TcpSyn => Some(TcpFlags::SYN),
+        PortScanningMethod::TcpFin => Some(TcpFlags::FIN),
+        PortScanningMethod::TcpNull => Some(0),
+        PortScanningMethod::TcpXmas => Some(TcpFlags::FIN | TcpFlags::PSH | TcpFlags::URG),
+        PortScanningMethod::TcpAck => Some(TcpFlags::ACK),
+        PortScanningMethod::TcpWindow => Some(TcpFlags::ACK),
+        PortScanningMethod::TcpMaimon => Some(TcpFlags::FIN | TcpFlags::ACK),
+        PortScanningMethod::TcpCustom(flags) => Some(flags),
+        _ => None,
+    }
+}
+
+/// Never returns the correct checksum, and never 0 (no checksum for udp).
+fn bad_checksum(checksum: u16) -> u16 {
+    match checksum.wrapping_add(1) {
+        0 => 1,
+        c => c,
+    }
+}
+
+fn ipv4_header(buff: &mut [u8], dst_ipv4: Ipv4Addr, src_ipv4: Ipv4Addr, udp: bool) {
+    let total_length = buff.len() as u16;
+    let mut ip_header = MutableIpv4Packet::new(buff).expect("build ipv4 packet failed");
+    ip_header.set_version(4);
+    ip_header.set_header_length(5);
+    ip_header.set_source(src_ipv4);
+    ip_header.set_destination(dst_ipv4);
+    ip_header.set_total_length(total_length);
+    ip_header.set_identification(rand::random());
+    ip_header.set_flags(Ipv4Flags::DontFragment);
+    ip_header.set_ttl(TTL);
+    if udp {
+        ip_header.set_next_level_protocol(IpNextHeaderProtocols::Udp);
+    } else {
+        ip_header.set_next_level_protocol(IpNextHeaderProtocols::Tcp);
+    }
+    // only the tcp or udp checksum is broken, the packet must be routed
+    let c = ipv4::checksum(&ip_header.to_immutable());
+    ip_header.set_checksum(c);
+}
+
+/// The tcp probe with the `flags` and the `payload`, the checksum is broken when `badsum` is set.
+pub fn build_tcp_packet(
+    dst_ipv4: Ipv4Addr,
+    dst_port: u16,
+    src_ipv4: Ipv4Addr,
+    src_port: u16,
+    flags: u8,
+    badsum: bool,
+    payload: &[u8],
+) -> Vec<u8> {
+    let mut buff = vec![0u8; IPV4_HEADER_SIZE + TCP_HEADER_SIZE + payload.len()];
+    buff[IPV4_HEADER_SIZE + TCP_HEADER_SIZE..].copy_from_slice(payload);
+    ipv4_header(&mut buff, dst_ipv4, src_ipv4, false);
+    let mut tcp_header =
+        MutableTcpPacket::new(&mut buff[IPV4_HEADER_SIZE..]).expect("build tcp packet failed");
+    tcp_header.set_source(src_port);
+    tcp_header.set_destination(dst_port);
+    tcp_header.set_sequence(rand::random());
+    tcp_header.set_acknowledgement(rand::random());
+    tcp_header.set_flags(flags);
+    tcp_header.set_window(1024);
+    tcp_header.set_data_offset(5);
+    let checksum = tcp::ipv4_checksum(&tcp_header.to_immutable(), &src_ipv4, &dst_ipv4);
+    if badsum {
+        tcp_header.set_checksum(bad_checksum(checksum));
+    } else {
+        tcp_header.set_checksum(checksum);
+    }
+    buff
+}
+
+fn build_udp_badsum_packet(
+    dst_ipv4: Ipv4Addr,
+    dst_port: u16,
+    src_ipv4: Ipv4Addr,
+    src_port: u16,
+    payload: &[u8],
+) -> Vec<u8> {
+    let mut buff = vec![0u8; IPV4_HEADER_SIZE + UDP_HEADER_SIZE + payload.len()];
+    buff[IPV4_HEADER_SIZE + UDP_HEADER_SIZE..].copy_from_slice(payload);
+    ipv4_header(&mut buff, dst_ipv4, src_ipv4, true);
+    let mut udp_header =
+        MutableUdpPacket::new(&mut buff[IPV4_HEADER_SIZE..]).expect("build udp packet failed");
+    udp_header.set_source(src_port);
+    udp_header.set_destination(dst_port);
+    udp_header.set_length((UDP_HEADER_SIZE + payload.len()) as u16);
+    let checksum = udp::ipv4_checksum(&udp_header.to_immutable(), &src_ipv4, &dst_ipv4);
+    udp_header.set_checksum(bad_checksum(checksum));
+    buff
+}
+
+fn send_badsum_probe(
+    dst_ipv4: Ipv4Addr,
+    dst_port: u16,
+    src_ipv4: Ipv4Addr,
+    ps_method: PortScanningMethod,
+    timeout: Option<Duration>,
+    payload: &[u8],
+) -> (bool, Duration) {
+    let src_port = next_source_port();
+    let packet = match ps_method {
+        PortScanningMethod::Udp => {
+            build_udp_badsum_packet(dst_ipv4, dst_port, src_ipv4, src_port, payload)
+        }
+        _ => {
+            let flags = tcp_flags(ps_method).expect("the scan method does not support badsum");
+            build_tcp_packet(dst_ipv4, dst_port, src_ipv4, src_port, flags, true, payload)
+        }
+    };
+
+    let layer3 = Layer3Match {
+        name: "badsum layer3",
+        layer2: None,
+        src_addr: Some(dst_ipv4.into()),
+        dst_addr: Some(src_ipv4.into()),
+    };
+    let layer4_tcp_udp = Layer4MatchTcpUdp {
+        name: "badsum tcp_udp",
+        layer3: Some(layer3),
+        src_port: Some(dst_port),
+        dst_port: Some(src_port),
+    };
+    let payload_ip = PayloadMatchIp {
+        src_addr: Some(src_ipv4.into()),
+        dst_addr: Some(dst_ipv4.into()),
+    };
+    let payload_tcpudp = PayloadMatchTcpUdp {
+        layer3: Some(payload_ip),
+        src_port: Some(src_port),
+        dst_port: Some(dst_port),
+    };
+    let layer4_icmp = Layer4MatchIcmp {
+        name: "badsum icmp",
+        layer3: Some(layer3),
+        icmp_type: None,
+        icmp_code: None,
+        payload: Some(PayloadMatch::PayloadMatchTcpUdp(payload_tcpudp)),
+    };
+    let layer_matchs = vec![
+        LayerMatch::Layer4MatchTcpUdp(layer4_tcp_udp),
+        LayerMatch::Layer4MatchIcmp(layer4_icmp),
+    ];
+
+    match layer3_ipv4_send(dst_ipv4, src_ipv4, &packet, layer_matchs, timeout, true) {
+        Ok((ret, rtt)) => (!ret.is_empty(), rtt),
+        Err(_) => (false, Duration::ZERO),
+    }
+}
+
+/// Returns true when the method can send the badsum probes (the tcp raw scans and udp).
+pub fn badsum_supported(ps_method: PortScanningMethod) -> bool {
+    match ps_method {
+        PortScanningMethod::Udp => true,
+        _ => tcp_flags(ps_method).is_some(),
+    }
+}
+
+/// Send one bad checksum probe to each port, only IPv4 targets are supported.
+pub fn badsum_scan(
+    targets: &[Target],
+    ps_method: PortScanningMethod,
+    timeout: Option<Duration>,
+    num_threads: usize,
+) -> Vec<BadsumReport> {
+    let src_addr = *SRC_ADDR.lock().expect("lock SRC_ADDR failed");
+    let payload = PROBE_DATA.lock().expect("lock PROBE_DATA failed").clone();
+    let payload = payload.as_slice();
+    let mut probes = Vec::new();
+    for t in targets {
+        if let IpAddr::V4(dst_ipv4) = t.addr
+            && let Ok(Some(ia)) = infer_addr(t.addr, src_addr)
+            && let IpAddr::V4(src_ipv4) = ia.src_addr
+        {
+            for &port in &t.ports {
+                probes.push((dst_ipv4, port, src_ipv4));
+            }
+        }
+    }
+
+    let chunk_size = probes.len().div_ceil(num_threads.max(1)).max(1);
+    std::thread::scope(|s| {
+        let handles: Vec<_> = probes
+            .chunks(chunk_size)
+            .map(|chunk| {
+                s.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|&(dst_ipv4, port, src_ipv4)| {
+                            let (responded, rtt) = send_badsum_probe(
+                                dst_ipv4, port, src_ipv4, ps_method, timeout, payload,
+                            );
+                            BadsumReport {
+                                addr: dst_ipv4.into(),
+                                port,
+                                responded,
+                                rtt,
+                            }
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().expect("badsum thread panicked"))
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pnet::packet::tcp::TcpPacket;
+    #[test]
+    fn test_badsum() {
+        let dst_ipv4 = Ipv4Addr::new(192, 168, 5, 5);
+        let src_ipv4 = Ipv4Addr::new(192, 168, 5, 3);
+        let flags = tcp_flags(PortScanningMethod::TcpSyn).unwrap();
+        let packet = build_tcp_packet(dst_ipv4, 80, src_ipv4, 45678, flags, true, &[]);
+        let tcp_packet = TcpPacket::new(&packet[IPV4_HEADER_SIZE..]).unwrap();
+        let correct = tcp::ipv4_checksum(&tcp_packet, &src_ipv4, &dst_ipv4);
+        assert_ne!(tcp_packet.get_checksum(), correct);
+        assert_eq!(tcp_packet.get_flags(), TcpFlags::SYN);
+
+        assert!(badsum_supported(PortScanningMethod::Udp));
+        assert!(!badsum_supported(PortScanningMethod::TcpConnect));
+
+        let report = BadsumReport {
+            addr: dst_ipv4.into(),
+            port: 80,
+            responded: true,
+            rtt: Duration::from_millis(10),
+        };
+        assert!(report.label().contains("non-compliant"));
+    }
+}
@@ -0,0 +1,74 @@
+/// the probe payload (same as nmap --data-string and --data-length)
+use crate::ps::PortScanningMethod;
+
+/// The payload and the ip and tcp headers must fit in the ethernet mtu.
+pub const MAX_DATA_LENGTH: usize = 1400;
+
+/// The `data_string` bytes, or `data_length` random bytes, the empty payload when neither is set.
+pub fn probe_data(
+    data_string: Option<&str>,
+    data_length: Option<usize>,
+) -> Result<Vec<u8>, String> {
+    let data = match (data_string, data_length) {
+        (Some(_), Some(_)) => {
+            return Err(String::from(
+                "--data-string and --data-length can not be used together",
+            ));
+        }
+        (Some(data_string), None) => data_string.as_bytes().to_vec(),
+        (None, Some(data_length)) => (0..data_length).map(|_| rand::random::<u8>()).collect(),
+        (None, None) => Vec::new(),
+    };
+    if data.len() > MAX_DATA_LENGTH {
+        return Err(format!(
+            "invalid probe data: {} bytes exceeds the max {} bytes",
+            data.len(),
+            MAX_DATA_LENGTH
+        ));
+    }
+    Ok(data)
+}
+
+/// Pistol scans do not take a payload, only the probes built by pslmap (--scanflags and --badsum) carry it.
+pub fn data_supported(ps_method: PortScanningMethod, badsum: bool) -> bool {
+    badsum || matches!(ps_method, PortScanningMethod::TcpCustom(_))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bs::build_tcp_packet;
+    use pistol::layer::IPV4_HEADER_SIZE;
+    use pnet::packet::Packet;
+    use pnet::packet::ipv4::Ipv4Packet;
+    use pnet::packet::tcp;
+    use pnet::packet::tcp::TcpFlags;
+    use pnet::packet::tcp::TcpPacket;
+    use std::net::Ipv4Addr;
+    #[test]
+    fn test_probe_data() {
+        let data = probe_data(Some("GET / HTTP/1.0"), None).unwrap();
+        assert_eq!(data, b"GET / HTTP/1.0");
+        assert_eq!(probe_data(None, Some(32)).unwrap().len(), 32);
+        assert_eq!(probe_data(None, None).unwrap(), Vec::<u8>::new());
+        assert!(probe_data(Some("a"), Some(1)).is_err());
+        assert!(probe_data(None, Some(MAX_DATA_LENGTH + 1)).is_err());
+
+        let dst_ipv4 = Ipv4Addr::new(192, 168, 5, 5);
+        let src_ipv4 = Ipv4Addr::new(192, 168, 5, 3);
+        let packet = build_tcp_packet(dst_ipv4, 80, src_ipv4, 45678, TcpFlags::SYN, false, &data);
+        let ipv4_packet = Ipv4Packet::new(&packet).unwrap();
+        assert_eq!(ipv4_packet.get_total_length() as usize, packet.len());
+        let tcp_packet = TcpPacket::new(&packet[IPV4_HEADER_SIZE..]).unwrap();
+        assert_eq!(tcp_packet.payload(), data.as_slice());
+        let checksum = tcp::ipv4_checksum(&tcp_packet, &src_ipv4, &dst_ipv4);
+        assert_eq!(tcp_packet.get_checksum(), checksum);
+
+        assert!(data_supported(
+            PortScanningMethod::TcpCustom(TcpFlags::SYN),
+            false
+        ));
+        assert!(data_supported(PortScanningMethod::TcpSyn, true));
+        assert!(!data_supported(PortScanningMethod::TcpSyn, false));
+    }
+}
@@ -0,0 +1,414 @@
+/// dns resolver with the custom servers
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use std::net::SocketAddr;
+use std::net::UdpSocket;
+use std::str::FromStr;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+
+const DNS_PORT: u16 = 53;
+const DNS_TIMEOUT: f64 = 2.0;
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const TYPE_PTR: u16 = 12;
+const CLASS_IN: u16 = 1;
+/// The servers of the system resolver, used by the reverse lookups without --dns-servers.
+const RESOLV_CONF: &str = "/etc/resolv.conf";
+
+/// Parse the dns servers list, such as `10.0.0.53,192.168.1.1:5353,[fd00::53]:53`.
+pub fn dns_servers_parser(servers: &str) -> Result<Vec<SocketAddr>, String> {
+    let mut ret = Vec::new();
+    for s in servers
+        .split(",")
+        .map(|x| x.trim())
+        .filter(|x| !x.is_empty())
+    {
+        let addr = match SocketAddr::from_str(s) {
+            Ok(addr) => addr,
+            Err(_) => match IpAddr::from_str(s) {
+                Ok(ip) => SocketAddr::new(ip, DNS_PORT),
+                Err(_) => return Err(format!("invalid dns server {}", s)),
+            },
+        };
+        ret.push(addr);
+    }
+    Ok(ret)
+}
+
+fn build_query(id: u16, hostname: &str, qtype: u16) -> Vec<u8> {
+    let mut buff = Vec::new();
+    buff.extend_from_slice(&id.to_be_bytes());
+    // standard query with recursion desired
+    buff.extend_from_slice(&0x0100u16.to_be_bytes());
+    // qdcount, ancount, nscount, arcount
+    buff.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in hostname.trim_end_matches(".").split(".") {
+        buff.push(label.len() as u8);
+        buff.extend_from_slice(label.as_bytes());
+    }
+    buff.push(0);
+    buff.extend_from_slice(&qtype.to_be_bytes());
+    buff.extend_from_slice(&CLASS_IN.to_be_bytes());
+    buff
+}
+
+fn read_u16(buff: &[u8], offset: usize) -> Result<u16, String> {
+    match buff.get(offset..offset + 2) {
+        Some(b) => Ok(u16::from_be_bytes([b[0], b[1]])),
+        None => Err(String::from("truncated dns response")),
+    }
+}
+
+/// Skip the (maybe compressed) name and returns the offset after it.
+fn skip_name(buff: &[u8], mut offset: usize) -> Result<usize, String> {
+    loop {
+        let len = *buff
+            .get(offset)
+            .ok_or(String::from("truncated dns response"))?;
+        if len == 0 {
+            return Ok(offset + 1);
+        } else if len & 0xc0 == 0xc0 {
+            // the pointer always ends the name
+            return Ok(offset + 2);
+        } else {
+            offset += 1 + len as usize;
+        }
+    }
+}
+
+fn parse_response(buff: &[u8], id: u16) -> Result<Vec<IpAddr>, String> {
+    if read_u16(buff, 0)? != id {
+        return Err(String::from("dns response id mismatch"));
+    }
+    let flags = read_u16(buff, 2)?;
+    let rcode = flags & 0x000f;
+    if rcode != 0 {
+        return Err(format!("dns server returned rcode {}", rcode));
+    }
+    let qdcount = read_u16(buff, 4)?;
+    let ancount = read_u16(buff, 6)?;
+
+    let mut offset = 12;
+    for _ in 0..qdcount {
+        // qtype and qclass
+        offset = skip_name(buff, offset)? + 4;
+    }
+
+    let mut ret = Vec::new();
+    for _ in 0..ancount {
+        offset = skip_name(buff, offset)?;
+        let rtype = read_u16(buff, offset)?;
+        let rdlength = read_u16(buff, offset + 8)? as usize;
+        offset += 10;
+        let rdata = buff
+            .get(offset..offset + rdlength)
+            .ok_or(String::from("truncated dns response"))?;
+        match (rtype, rdlength) {
+            (TYPE_A, 4) => {
+                let ip = Ipv4Addr::new(rdata[0], rdata[1], rdata[2], rdata[3]);
+                ret.push(ip.into());
+            }
+            (TYPE_AAAA, 16) => {
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(rdata);
+                ret.push(Ipv6Addr::from(octets).into());
+            }
+            // CNAME and others
+            _ => (),
+        }
+        offset += rdlength;
+    }
+    Ok(ret)
+}
+
+fn query_server(hostname: &str, server: SocketAddr, qtype: u16) -> Result<Vec<IpAddr>, String> {
+    let bind_addr: SocketAddr = match server {
+        SocketAddr::V4(_) => SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0),
+        SocketAddr::V6(_) => SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0),
+    };
+    let socket = UdpSocket::bind(bind_addr).map_err(|e| e.to_string())?;
+    socket
+        .set_read_timeout(Some(Duration::from_secs_f64(DNS_TIMEOUT)))
+        .map_err(|e| e.to_string())?;
+
+    let id: u16 = rand::random();
+    let query = build_query(id, hostname, qtype);
+    socket.send_to(&query, server).map_err(|e| e.to_string())?;
+
+    let mut buff = [0u8; 1500];
+    let (n, _) = socket.recv_from(&mut buff).map_err(|e| e.to_string())?;
+    parse_response(&buff[..n], id)
+}
+
+/// Query both the A and AAAA records from the servers in order,
+/// the next server is used only when the previous one fails.
+pub fn dns_query_with_servers(
+    hostname: &str,
+    servers: &[SocketAddr],
+) -> Result<Vec<IpAddr>, String> {
+    let mut last_error = String::from("no dns server");
+    for server in servers {
+        let ret_a = query_server(hostname, *server, TYPE_A);
+        let ret_aaaa = query_server(hostname, *server, TYPE_AAAA);
+        match (ret_a, ret_aaaa) {
+            (Err(e), Err(_)) => last_error = format!("{} ({})", e, server),
+            (ret_a, ret_aaaa) => {
+                let mut ret = ret_a.unwrap_or_default();
+                ret.extend(ret_aaaa.unwrap_or_default());
+                if !ret.is_empty() {
+                    return Ok(ret);
+                }
+                last_error = format!("no address found ({})", server);
+            }
+        }
+    }
+    Err(last_error)
+}
+
+/// The name of the reverse lookup, such as `4.3.2.1.in-addr.arpa` of 1.2.3.4.
+fn ptr_name(addr: IpAddr) -> String {
+    match addr {
+        IpAddr::V4(ipv4) => {
+            let o = ipv4.octets();
+            format!("{}.{}.{}.{}.in-addr.arpa", o[3], o[2], o[1], o[0])
+        }
+        IpAddr::V6(ipv6) => {
+            let mut labels = Vec::new();
+            for b in ipv6.octets().iter().rev() {
+                labels.push(format!("{:x}", b & 0x0f));
+                labels.push(format!("{:x}", b >> 4));
+            }
+            format!("{}.ip6.arpa", labels.join("."))
+        }
+    }
+}
+
+/// Read the (maybe compressed) name at the offset.
+fn read_name(buff: &[u8], mut offset: usize) -> Result<String, String> {
+    let mut labels = Vec::new();
+    // the pointers can not loop forever
+    for _ in 0..128 {
+        let len = *buff
+            .get(offset)
+            .ok_or(String::from("truncated dns response"))?;
+        if len == 0 {
+            return Ok(labels.join("."));
+        } else if len & 0xc0 == 0xc0 {
+            offset = (read_u16(buff, offset)? & 0x3fff) as usize;
+        } else {
+            let label = buff
+                .get(offset + 1..offset + 1 + len as usize)
+                .ok_or(String::from("truncated dns response"))?;
+            labels.push(String::from_utf8_lossy(label).to_string());
+            offset += 1 + len as usize;
+        }
+    }
+    Err(String::from("dns name pointers loop"))
+}
+
+fn parse_ptr_response(buff: &[u8], id: u16) -> Result<Option<String>, String> {
+    if read_u16(buff, 0)? != id {
+        return Err(String::from("dns response id mismatch"));
+    }
+    let rcode = read_u16(buff, 2)? & 0x000f;
+    // nxdomain, the address has no ptr record
+    if rcode == 3 {
+        return Ok(None);
+    } else if rcode != 0 {
+        return Err(format!("dns server returned rcode {}", rcode));
+    }
+    let qdcount = read_u16(buff, 4)?;
+    let ancount = read_u16(buff, 6)?;
+    let mut offset = 12;
+    for _ in 0..qdcount {
+        offset = skip_name(buff, offset)? + 4;
+    }
+    for _ in 0..ancount {
+        offset = skip_name(buff, offset)?;
+        let rtype = read_u16(buff, offset)?;
+        let rdlength = read_u16(buff, offset + 8)? as usize;
+        offset += 10;
+        if rtype == TYPE_PTR {
+            return read_name(buff, offset).map(Some);
+        }
+        offset += rdlength;
+    }
+    Ok(None)
+}
+
+fn ptr_query_server(addr: IpAddr, server: SocketAddr) -> Result<Option<String>, String> {
+    let bind_addr: SocketAddr = match server {
+        SocketAddr::V4(_) => SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0),
+        SocketAddr::V6(_) => SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0),
+    };
+    let socket = UdpSocket::bind(bind_addr).map_err(|e| e.to_string())?;
+    socket
+        .set_read_timeout(Some(Duration::from_secs_f64(DNS_TIMEOUT)))
+        .map_err(|e| e.to_string())?;
+
+    let id: u16 = rand::random();
+    let query = build_query(id, &ptr_name(addr), TYPE_PTR);
+    socket.send_to(&query, server).map_err(|e| e.to_string())?;
+
+    let mut buff = [0u8; 1500];
+    let (n, _) = socket.recv_from(&mut buff).map_err(|e| e.to_string())?;
+    parse_ptr_response(&buff[..n], id)
+}
+
+/// The `nameserver` lines of the resolv.conf.
+fn resolv_conf_parser(content: &str) -> Vec<SocketAddr> {
+    content
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("nameserver"))
+        .filter_map(|s| IpAddr::from_str(s.trim()).ok())
+        .map(|ip| SocketAddr::new(ip, DNS_PORT))
+        .collect()
+}
+
+pub fn system_dns_servers() -> Vec<SocketAddr> {
+    match std::fs::read_to_string(RESOLV_CONF) {
+        Ok(content) => resolv_conf_parser(&content),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// The ptr names of the addresses (--reverse-dns), `concurrency` lookups run at the same time
+/// and the address without the ptr record (or the failed lookup) has none.
+pub fn reverse_lookup(
+    addrs: &[IpAddr],
+    servers: &[SocketAddr],
+    concurrency: usize,
+) -> Vec<Option<String>> {
+    let lookup = |addr: IpAddr| {
+        for server in servers {
+            if let Ok(name) = ptr_query_server(addr, *server) {
+                return name;
+            }
+        }
+        None
+    };
+    let next = AtomicUsize::new(0);
+    let mut ret = vec![None; addrs.len()];
+    let results: Vec<(usize, Option<String>)> = thread::scope(|s| {
+        let workers: Vec<_> = (0..concurrency.max(1).min(addrs.len()))
+            .map(|_| {
+                s.spawn(|| {
+                    let mut ret = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::SeqCst);
+                        match addrs.get(i) {
+                            Some(addr) => ret.push((i, lookup(*addr))),
+                            None => break,
+                        }
+                    }
+                    ret
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|w| w.join().expect("reverse dns thread panicked"))
+            .collect()
+    });
+    for (i, name) in results {
+        ret[i] = name;
+    }
+    ret
+}
+
+/// Such as `1.2.3.4 [fwd: example.com | ptr: host.cloud.net]` when the ptr name is not the forward origin,
+/// the same names are shown once and nothing is shown without the ptr name.
+pub fn names_line(addr: IpAddr, origin: Option<&str>, ptr: Option<&str>) -> Option<String> {
+    let same = |a: &str, b: &str| {
+        a.trim_end_matches(".")
+            .eq_ignore_ascii_case(b.trim_end_matches("."))
+    };
+    match (origin, ptr) {
+        (Some(fwd), Some(ptr)) if same(fwd, ptr) => Some(format!("{} [{}]", addr, fwd)),
+        (Some(fwd), Some(ptr)) => Some(format!("{} [fwd: {} | ptr: {}]", addr, fwd, ptr)),
+        (None, Some(ptr)) => Some(format!("{} [ptr: {}]", addr, ptr)),
+        (_, None) => None,
+    }
+}
+
+/// A tiny dns server used by the tests, answers the A query with `answer` and nothing for others.
+#[cfg(test)]
+pub fn mock_dns_server(answer: Ipv4Addr) -> SocketAddr {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = socket.local_addr().unwrap();
+    std::thread::spawn(move || {
+        let mut buff = [0u8; 1500];
+        while let Ok((n, src)) = socket.recv_from(&mut buff) {
+            let query = &buff[..n];
+            let qtype_offset = skip_name(query, 12).unwrap();
+            let qtype = read_u16(query, qtype_offset).unwrap();
+            let mut response = query.to_vec();
+            // response, recursion available
+            response[2] = 0x81;
+            response[3] = 0x80;
+            if qtype == TYPE_A {
+                response[7] = 1;
+                response.extend_from_slice(&[0xc0, 0x0c]);
+                response.extend_from_slice(&TYPE_A.to_be_bytes());
+                response.extend_from_slice(&CLASS_IN.to_be_bytes());
+                response.extend_from_slice(&60u32.to_be_bytes());
+                response.extend_from_slice(&4u16.to_be_bytes());
+                response.extend_from_slice(&answer.octets());
+            }
+            socket.send_to(&response, src).unwrap();
+        }
+    });
+    addr
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_dns_servers_parser() {
+        let ret = dns_servers_parser("10.0.0.53, 192.168.1.1:5353,[fd00::53]:53").unwrap();
+        assert_eq!(ret[0], SocketAddr::from_str("10.0.0.53:53").unwrap());
+        assert_eq!(ret[1], SocketAddr::from_str("192.168.1.1:5353").unwrap());
+        assert_eq!(ret[2], SocketAddr::from_str("[fd00::53]:53").unwrap());
+        assert!(dns_servers_parser("not-a-server").is_err());
+    }
+    #[test]
+    fn test_reverse_names() {
+        let addr = IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4));
+        assert_eq!(ptr_name(addr), "4.3.2.1.in-addr.arpa");
+        assert!(ptr_name(IpAddr::V6(Ipv6Addr::LOCALHOST)).starts_with("1.0.0.0."));
+
+        // the ptr answer points back to the question name and then to its own labels
+        let id = 0x1234;
+        let mut response = build_query(id, &ptr_name(addr), TYPE_PTR);
+        response[2] = 0x81;
+        response[3] = 0x80;
+        response[7] = 1;
+        let rdata = [&[4u8][..], b"host", &[5], b"cloud", &[3], b"net", &[0]].concat();
+        response.extend_from_slice(&[0xc0, 0x0c]);
+        response.extend_from_slice(&TYPE_PTR.to_be_bytes());
+        response.extend_from_slice(&CLASS_IN.to_be_bytes());
+        response.extend_from_slice(&60u32.to_be_bytes());
+        response.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        response.extend_from_slice(&rdata);
+        let ptr = parse_ptr_response(&response, id).unwrap();
+        assert_eq!(ptr.as_deref(), Some("host.cloud.net"));
+
+        // the forward and the reverse names differ
+        let line = names_line(addr, Some("example.com"), ptr.as_deref()).unwrap();
+        assert_eq!(line, "1.2.3.4 [fwd: example.com | ptr: host.cloud.net]");
+        assert_eq!(
+            names_line(addr, Some("Host.cloud.net."), ptr.as_deref()),
+            Some(String::from("1.2.3.4 [Host.cloud.net.]"))
+        );
+        assert_eq!(names_line(addr, Some("example.com"), None), None);
+        assert_eq!(
+            resolv_conf_parser("# comment\nnameserver 10.0.0.53\nsearch lan\n"),
+            vec![SocketAddr::from_str("10.0.0.53:53").unwrap()]
+        );
+    }
+}
@@ -0,0 +1,200 @@
+/// icmp echo ping with the custom payload size
+use pistol::Target;
+use pistol::layer::ICMP_HEADER_SIZE;
+use pistol::layer::IPV4_HEADER_SIZE;
+use pistol::layer::Layer3Match;
+use pistol::layer::Layer4MatchIcmp;
+use pistol::layer::LayerMatch;
+use pistol::layer::infer_addr;
+use pistol::layer::layer3_ipv4_send;
+use pistol::ping::PingReport;
+use pistol::ping::PingStatus;
+use pistol::ping::PistolPings;
+use pnet::packet::Packet;
+use pnet::packet::icmp;
+use pnet::packet::icmp::IcmpCode;
+use pnet::packet::icmp::IcmpPacket;
+use pnet::packet::icmp::IcmpTypes;
+use pnet::packet::icmp::MutableIcmpPacket;
+use pnet::packet::icmp::echo_request::MutableEchoRequestPacket;
+use pnet::packet::ip::IpNextHeaderProtocols;
+use pnet::packet::ipv4;
+use pnet::packet::ipv4::Ipv4Flags;
+use pnet::packet::ipv4::Ipv4Packet;
+use pnet::packet::ipv4::MutableIpv4Packet;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+const TTL: u8 = 64;
+/// The ethernet mtu, the echo request is sent with the don't fragment bit.
+const ETHERNET_MTU: usize = 1500;
+
+/// The payload must fit in one packet, otherwise it needs fragmentation.
+pub fn payload_size_check(payload_size: usize) -> Result<(), String> {
+    let max = ETHERNET_MTU - IPV4_HEADER_SIZE - ICMP_HEADER_SIZE;
+    if payload_size > max {
+        Err(format!(
+            "icmp payload size {} needs fragmentation (max {} for mtu {})",
+            payload_size, max, ETHERNET_MTU
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+fn build_echo_packet(dst_ipv4: Ipv4Addr, src_ipv4: Ipv4Addr, payload_size: usize) -> Vec<u8> {
+    let mut buff = vec![0u8; IPV4_HEADER_SIZE + ICMP_HEADER_SIZE + payload_size];
+    let total_length = buff.len() as u16;
+    let mut ip_header = MutableIpv4Packet::new(&mut buff).expect("build ipv4 packet failed");
+    ip_header.set_version(4);
+    ip_header.set_header_length(5);
+    ip_header.set_source(src_ipv4);
+    ip_header.set_destination(dst_ipv4);
+    ip_header.set_total_length(total_length);
+    ip_header.set_identification(rand::random());
+    ip_header.set_flags(Ipv4Flags::DontFragment);
+    ip_header.set_ttl(TTL);
+    ip_header.set_next_level_protocol(IpNextHeaderProtocols::Icmp);
+    let c = ipv4::checksum(&ip_header.to_immutable());
+    ip_header.set_checksum(c);
+
+    let mut icmp_header = MutableEchoRequestPacket::new(&mut buff[IPV4_HEADER_SIZE..])
+        .expect("build icmp packet failed");
+    icmp_header.set_icmp_type(IcmpTypes::EchoRequest);
+    icmp_header.set_icmp_code(IcmpCode(0));
+    icmp_header.set_identifier(rand::random());
+    icmp_header.set_sequence_number(1);
+    // same as the ping command, the payload is filled with the increasing bytes
+    let payload: Vec<u8> = (0..payload_size).map(|i| i as u8).collect();
+    icmp_header.set_payload(&payload);
+
+    let mut icmp_header =
+        MutableIcmpPacket::new(&mut buff[IPV4_HEADER_SIZE..]).expect("build icmp packet failed");
+    let checksum = icmp::checksum(&icmp_header.to_immutable());
+    icmp_header.set_checksum(checksum);
+    buff
+}
+
+/// Returns the status and whether any reply is received.
+fn send_echo_packet(
+    dst_ipv4: Ipv4Addr,
+    src_ipv4: Ipv4Addr,
+    payload_size: usize,
+    timeout: Option<Duration>,
+) -> (PingStatus, bool, Duration) {
+    let packet = build_echo_packet(dst_ipv4, src_ipv4, payload_size);
+    let layer3 = Layer3Match {
+        name: "ping echo payload layer3",
+        layer2: None,
+        src_addr: Some(dst_ipv4.into()),
+        dst_addr: Some(src_ipv4.into()),
+    };
+    let layer4_icmp = Layer4MatchIcmp {
+        name: "ping echo payload icmp",
+        layer3: Some(layer3),
+        icmp_type: None,
+        icmp_code: None,
+        payload: None,
+    };
+    let layer_match = LayerMatch::Layer4MatchIcmp(layer4_icmp);
+
+    let (ret, rtt) = match layer3_ipv4_send(
+        dst_ipv4,
+        src_ipv4,
+        &packet,
+        vec![layer_match],
+        timeout,
+        true,
+    ) {
+        Ok(r) => r,
+        Err(_) => return (PingStatus::Error, true, Duration::ZERO),
+    };
+    if let Some(ipv4_packet) = Ipv4Packet::new(&ret)
+        && let Some(icmp_packet) = IcmpPacket::new(ipv4_packet.payload())
+    {
+        let icmp_type = icmp_packet.get_icmp_type();
+        if icmp_type == IcmpTypes::EchoReply {
+            return (PingStatus::Up, true, rtt);
+        } else if icmp_type == IcmpTypes::DestinationUnreachable {
+            return (PingStatus::Down, true, rtt);
+        }
+    }
+    (PingStatus::Down, false, rtt)
+}
+
+/// Same as the pistol icmp echo ping but with `payload_size` bytes payload,
+/// only the IPv4 targets are supported.
+pub fn icmp_echo_ping_with_payload(
+    targets: &[Target],
+    payload_size: usize,
+    num_threads: usize,
+    src_addr: Option<IpAddr>,
+    timeout: Option<Duration>,
+    max_attempts: usize,
+) -> PistolPings {
+    let mut probes = Vec::new();
+    for t in targets {
+        if let IpAddr::V4(dst_ipv4) = t.addr
+            && let Ok(Some(ia)) = infer_addr(t.addr, src_addr)
+            && let IpAddr::V4(src_ipv4) = ia.src_addr
+        {
+            probes.push((dst_ipv4, src_ipv4, t.origin.clone()));
+        }
+    }
+
+    let chunk_size = probes.len().div_ceil(num_threads.max(1)).max(1);
+    let ping_reports: Vec<PingReport> = std::thread::scope(|s| {
+        let handles: Vec<_> = probes
+            .chunks(chunk_size)
+            .map(|chunk| {
+                s.spawn(move || {
+                    let mut reports = Vec::new();
+                    for (dst_ipv4, src_ipv4, origin) in chunk {
+                        let mut ret = (PingStatus::Down, false, Duration::ZERO);
+                        for _ in 0..max_attempts {
+                            ret = send_echo_packet(*dst_ipv4, *src_ipv4, payload_size, timeout);
+                            if ret.1 {
+                                break;
+                            }
+                        }
+                        reports.push(PingReport {
+                            addr: (*dst_ipv4).into(),
+                            origin: origin.clone(),
+                            status: ret.0,
+                            cost: ret.2,
+                        });
+                    }
+                    reports
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().expect("icmp echo thread panicked"))
+            .collect()
+    });
+
+    let mut ret = PistolPings::new(max_attempts);
+    ret.finish(ping_reports);
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_payload_size() {
+        let dst_ipv4 = Ipv4Addr::new(192, 168, 5, 5);
+        let src_ipv4 = Ipv4Addr::new(192, 168, 5, 3);
+        let packet = build_echo_packet(dst_ipv4, src_ipv4, 100);
+        assert_eq!(packet.len(), IPV4_HEADER_SIZE + ICMP_HEADER_SIZE + 100);
+        let ipv4_packet = Ipv4Packet::new(&packet).unwrap();
+        assert_eq!(ipv4_packet.get_total_length() as usize, packet.len());
+        let icmp_packet = IcmpPacket::new(ipv4_packet.payload()).unwrap();
+        assert_eq!(icmp_packet.get_icmp_type(), IcmpTypes::EchoRequest);
+
+        assert!(payload_size_check(1472).is_ok());
+        assert!(payload_size_check(1473).is_err());
+    }
+}
@@ -0,0 +1,57 @@
+/// scan errors
+use pistol::error::PistolError;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum PslmapError {
+    /// The pistol scan (such as `tcp syn scan`) failed.
+    Pistol {
+        scan: &'static str,
+        e: Box<PistolError>,
+    },
+    /// The scan method does not support the option.
+    Unsupported(String),
+    /// The invalid input (such as the bad option or the unreadable file).
+    Input(String),
+}
+
+impl PslmapError {
+    pub fn pistol(scan: &'static str) -> impl FnOnce(PistolError) -> PslmapError {
+        move |e| PslmapError::Pistol {
+            scan,
+            e: Box::new(e),
+        }
+    }
+}
+
+impl fmt::Display for PslmapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PslmapError::Pistol { scan, e } => write!(f, "{} failed: {}", scan, e),
+            PslmapError::Unsupported(s) => write!(f, "{}", s),
+            PslmapError::Input(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+impl Error for PslmapError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PslmapError::Pistol { e, .. } => Some(e.as_ref()),
+            PslmapError::Unsupported(_) | PslmapError::Input(_) => None,
+        }
+    }
+}
+
+impl From<String> for PslmapError {
+    fn from(s: String) -> PslmapError {
+        PslmapError::Input(s)
+    }
+}
+
+impl From<&str> for PslmapError {
+    fn from(s: &str) -> PslmapError {
+        PslmapError::Input(s.to_string())
+    }
+}
@@ -0,0 +1,197 @@
+/// host exclusion
+use std::fs;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Default)]
+struct TrieNode {
+    children: [Option<usize>; 2],
+    /// A prefix ends at this node, all the addresses below it are excluded.
+    terminal: bool,
+}
+
+/// Binary trie of the prefix bits (longest prefix match table).
+/// The insert and the lookup walk at most 32 (IPv4) or 128 (IPv6) nodes,
+/// so filtering n targets against m prefixes costs O(n * 128) instead of O(n * m).
+#[derive(Debug, Clone)]
+struct PrefixTrie {
+    nodes: Vec<TrieNode>,
+}
+
+impl PrefixTrie {
+    fn new() -> PrefixTrie {
+        PrefixTrie {
+            nodes: vec![TrieNode::default()],
+        }
+    }
+    fn bit(octets: &[u8], i: usize) -> usize {
+        ((octets[i / 8] >> (7 - i % 8)) & 1) as usize
+    }
+    fn insert(&mut self, octets: &[u8], prefix: u8) {
+        let mut node = 0;
+        for i in 0..prefix as usize {
+            if self.nodes[node].terminal {
+                // a shorter prefix already covers it
+                return;
+            }
+            let b = Self::bit(octets, i);
+            node = match self.nodes[node].children[b] {
+                Some(child) => child,
+                None => {
+                    self.nodes.push(TrieNode::default());
+                    let child = self.nodes.len() - 1;
+                    self.nodes[node].children[b] = Some(child);
+                    child
+                }
+            };
+        }
+        self.nodes[node].terminal = true;
+    }
+    fn contains(&self, octets: &[u8]) -> bool {
+        let mut node = 0;
+        for i in 0..octets.len() * 8 {
+            if self.nodes[node].terminal {
+                return true;
+            }
+            match self.nodes[node].children[Self::bit(octets, i)] {
+                Some(child) => node = child,
+                None => return false,
+            }
+        }
+        self.nodes[node].terminal
+    }
+}
+
+/// The excluded addresses and CIDR networks (same as nmap --exclude and --excludefile).
+#[derive(Debug, Clone)]
+pub struct ExcludeTable {
+    v4: PrefixTrie,
+    v6: PrefixTrie,
+    len: usize,
+}
+
+impl Default for ExcludeTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExcludeTable {
+    pub fn new() -> ExcludeTable {
+        ExcludeTable {
+            v4: PrefixTrie::new(),
+            v6: PrefixTrie::new(),
+            len: 0,
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    /// Such as `192.168.1.1` or `10.0.0.0/8` or `fd00::/64`.
+    pub fn insert(&mut self, s: &str) -> Result<(), String> {
+        let (addr, prefix) = match s.split_once("/") {
+            Some((addr, prefix)) => {
+                let prefix: u8 = prefix
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("invalid prefix length in {}", s))?;
+                (addr.trim(), Some(prefix))
+            }
+            None => (s.trim(), None),
+        };
+        let addr = IpAddr::from_str(addr).map_err(|_| format!("invalid exclude address {}", s))?;
+        match addr {
+            IpAddr::V4(ipv4) => {
+                let prefix = prefix.unwrap_or(32);
+                if prefix > 32 {
+                    return Err(format!("invalid prefix length in {}", s));
+                }
+                self.v4.insert(&ipv4.octets(), prefix);
+            }
+            IpAddr::V6(ipv6) => {
+                let prefix = prefix.unwrap_or(128);
+                if prefix > 128 {
+                    return Err(format!("invalid prefix length in {}", s));
+                }
+                self.v6.insert(&ipv6.octets(), prefix);
+            }
+        }
+        self.len += 1;
+        Ok(())
+    }
+    /// The comma separated list.
+    pub fn insert_list(&mut self, list: &str) -> Result<(), String> {
+        for s in list.split(",").map(|x| x.trim()).filter(|x| !x.is_empty()) {
+            self.insert(s)?;
+        }
+        Ok(())
+    }
+    /// One address or network per line, the lines start with `#` are comments.
+    pub fn insert_file(&mut self, filename: &str) -> Result<(), String> {
+        let content =
+            fs::read_to_string(filename).map_err(|e| format!("read {} failed: {}", filename, e))?;
+        for line in content.lines() {
+            let line = line.trim();
+            if !line.is_empty() && !line.starts_with("#") {
+                self.insert_list(line)?;
+            }
+        }
+        Ok(())
+    }
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match addr {
+            IpAddr::V4(ipv4) => self.v4.contains(&ipv4.octets()),
+            IpAddr::V6(ipv6) => self.v6.contains(&ipv6.octets()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+    #[test]
+    fn test_exclude_table() {
+        let mut table = ExcludeTable::new();
+        table
+            .insert_list("192.168.1.1, 10.0.0.0/8, fd00::/64")
+            .unwrap();
+        assert!(table.contains(Ipv4Addr::new(192, 168, 1, 1).into()));
+        assert!(!table.contains(Ipv4Addr::new(192, 168, 1, 2).into()));
+        assert!(table.contains(Ipv4Addr::new(10, 200, 3, 4).into()));
+        assert!(table.contains(IpAddr::from_str("fd00::1").unwrap()));
+        assert!(!table.contains(IpAddr::from_str("fd00:0:0:1::1").unwrap()));
+        assert!(table.insert("10.0.0.0/33").is_err());
+
+        // hundreds of the exclude networks against a /16
+        let mut table = ExcludeTable::new();
+        let mut networks = Vec::new();
+        for i in 0..=255u8 {
+            if i % 3 == 0 {
+                networks.push((Ipv4Addr::new(172, 16, i, 0), 24));
+            }
+            networks.push((Ipv4Addr::new(172, 16, i, 128), 28));
+        }
+        for (addr, prefix) in &networks {
+            table.insert(&format!("{}/{}", addr, prefix)).unwrap();
+        }
+        assert_eq!(table.len(), networks.len());
+        let linear_contains = |addr: Ipv4Addr| {
+            networks.iter().any(|(net, prefix)| {
+                let mask = u32::MAX << (32 - prefix);
+                u32::from(addr) & mask == u32::from(*net) & mask
+            })
+        };
+        let mut excluded = 0;
+        for i in 0..=u16::MAX {
+            let addr = Ipv4Addr::new(172, 16, (i >> 8) as u8, i as u8);
+            let ret = table.contains(addr.into());
+            assert_eq!(ret, linear_contains(addr));
+            if ret {
+                excluded += 1;
+            }
+        }
+        // 86 * 256 + 170 * 16
+        assert_eq!(excluded, 86 * 256 + 170 * 16);
+    }
+}
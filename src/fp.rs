@@ -0,0 +1,149 @@
+/// response ttl and window size (lightweight passive fingerprint)
+use pistol::layer::Layer3Match;
+use pistol::layer::Layer4MatchTcpUdp;
+use pistol::layer::LayerMatch;
+use pistol::layer::infer_addr;
+use pistol::layer::layer3_ipv4_send;
+use pnet::packet::Packet;
+use pnet::packet::ip::IpNextHeaderProtocols;
+use pnet::packet::ipv4::Ipv4Packet;
+use pnet::packet::tcp::TcpPacket;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use crate::SRC_ADDR;
+use crate::bs::build_tcp_packet;
+use crate::bs::tcp_flags;
+use crate::ps::PortScanningMethod;
+use crate::sport::next_source_port;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Fingerprint {
+    pub ttl: Option<u8>,
+    pub window: Option<u16>,
+}
+
+impl Fingerprint {
+    /// Such as `[ttl=64 win=29200]`, the unavailable fields are omitted.
+    pub fn tag(&self) -> Option<String> {
+        let mut fields = Vec::new();
+        if let Some(ttl) = self.ttl {
+            fields.push(format!("ttl={}", ttl));
+        }
+        if let Some(window) = self.window {
+            fields.push(format!("win={}", window));
+        }
+        if !fields.is_empty() {
+            Some(format!("[{}]", fields.join(" ")))
+        } else {
+            None
+        }
+    }
+}
+
+/// Only the tcp syn and ack scans are supported.
+pub fn fingerprint_supported(ps_method: PortScanningMethod) -> bool {
+    matches!(
+        ps_method,
+        PortScanningMethod::TcpSyn | PortScanningMethod::TcpAck
+    )
+}
+
+/// The TTL comes from the ip header and the window size from the tcp header of the response.
+fn fingerprint_parser(buff: &[u8]) -> Fingerprint {
+    match Ipv4Packet::new(buff) {
+        Some(ipv4_packet) => {
+            let ttl = Some(ipv4_packet.get_ttl());
+            let window = if ipv4_packet.get_next_level_protocol() == IpNextHeaderProtocols::Tcp {
+                TcpPacket::new(ipv4_packet.payload()).map(|t| t.get_window())
+            } else {
+                None
+            };
+            Fingerprint { ttl, window }
+        }
+        None => Fingerprint::default(),
+    }
+}
+
+/// The pistol reports do not keep the response packets,
+/// so probe the port again with the same flags of the scan and read the response (IPv4 only).
+pub fn probe_fingerprint(
+    dst_addr: IpAddr,
+    dst_port: u16,
+    ps_method: PortScanningMethod,
+    timeout: Option<Duration>,
+) -> Fingerprint {
+    let src_addr = *SRC_ADDR.lock().expect("lock SRC_ADDR failed");
+    let flags = match tcp_flags(ps_method) {
+        Some(flags) if fingerprint_supported(ps_method) => flags,
+        _ => return Fingerprint::default(),
+    };
+    let (dst_ipv4, src_ipv4) = match infer_addr(dst_addr, src_addr) {
+        Ok(Some(ia)) => match (ia.dst_addr, ia.src_addr) {
+            (IpAddr::V4(dst_ipv4), IpAddr::V4(src_ipv4)) => (dst_ipv4, src_ipv4),
+            _ => return Fingerprint::default(),
+        },
+        _ => return Fingerprint::default(),
+    };
+
+    let src_port = next_source_port();
+    let packet = build_tcp_packet(dst_ipv4, dst_port, src_ipv4, src_port, flags, false, &[]);
+    let layer3 = Layer3Match {
+        name: "fingerprint layer3",
+        layer2: None,
+        src_addr: Some(dst_ipv4.into()),
+        dst_addr: Some(src_ipv4.into()),
+    };
+    let layer4_tcp_udp = Layer4MatchTcpUdp {
+        name: "fingerprint tcp_udp",
+        layer3: Some(layer3),
+        src_port: Some(dst_port),
+        dst_port: Some(src_port),
+    };
+    let layer_match = LayerMatch::Layer4MatchTcpUdp(layer4_tcp_udp);
+    match layer3_ipv4_send(
+        dst_ipv4,
+        src_ipv4,
+        &packet,
+        vec![layer_match],
+        timeout,
+        true,
+    ) {
+        Ok((ret, _)) => fingerprint_parser(&ret),
+        Err(_) => Fingerprint::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pnet::packet::ipv4::MutableIpv4Packet;
+    use pnet::packet::tcp::MutableTcpPacket;
+    use pnet::packet::tcp::TcpFlags;
+    use std::net::Ipv4Addr;
+    #[test]
+    fn test_fingerprint_tag() {
+        let fp = Fingerprint {
+            ttl: Some(64),
+            window: Some(29200),
+        };
+        assert_eq!(fp.tag(), Some(String::from("[ttl=64 win=29200]")));
+        let fp = Fingerprint {
+            ttl: Some(128),
+            window: None,
+        };
+        assert_eq!(fp.tag(), Some(String::from("[ttl=128]")));
+        assert_eq!(Fingerprint::default().tag(), None);
+
+        // a syn/ack response
+        let dst_ipv4 = Ipv4Addr::new(192, 168, 5, 5);
+        let src_ipv4 = Ipv4Addr::new(192, 168, 5, 3);
+        let mut buff = build_tcp_packet(src_ipv4, 45678, dst_ipv4, 80, TcpFlags::SYN, false, &[]);
+        MutableIpv4Packet::new(&mut buff).unwrap().set_ttl(52);
+        MutableTcpPacket::new(&mut buff[20..])
+            .unwrap()
+            .set_window(29200);
+        let fp = fingerprint_parser(&buff);
+        assert_eq!(fp.tag(), Some(String::from("[ttl=52 win=29200]")));
+    }
+}
@@ -1,21 +1,37 @@
 /// host discovery
-use pistol::PistolLogger;
-use pistol::PistolRunner;
 use pistol::Target;
 use pistol::icmp_address_mask_ping;
 use pistol::icmp_echo_ping;
 use pistol::icmp_timestamp_ping;
 use pistol::mac_scan;
+use pistol::ping::PingReport;
 use pistol::ping::PingStatus;
-use pistol::tcp_ack_ping;
-use pistol::tcp_syn_ping;
+use pistol::ping::PistolPings;
+use pistol::scan::PistolMacScans;
+use pistol::scan::PortReport;
+use pistol::scan::PortStatus;
+use pistol::tcp_ack_scan;
+use pistol::tcp_syn_scan;
 use pistol::udp_ping;
 use std::collections::BTreeMap;
 use std::fmt;
+use std::io::IsTerminal;
+use std::net::IpAddr;
 use std::time::Duration;
 use std::time::Instant;
 
-use crate::InfoShow;
+use crate::SRC_ADDR;
+use crate::SRC_MAC;
+use crate::echo::icmp_echo_ping_with_payload;
+use crate::error::PslmapError;
+use crate::output::Emitter;
+use crate::output::HostRecord;
+use crate::output::OutputFormat;
+use crate::output::ScanOutcome;
+use crate::output::no_data_lines;
+use crate::output::raw_debug;
+use crate::output::send_failure_warning;
+use crate::sm::mac_scan_with_source;
 
 /// Nmap Doc (https://nmap.org/book/man-host-discovery.html):
 /// The default host discovery done with -sn consists of an ICMP echo request,
@@ -24,7 +40,7 @@ use crate::InfoShow;
 /// on the target. When a privileged user tries to scan targets on a local ethernet network,
 /// ARP requests are used unless --send-ip was specified.
 #[derive(Debug, Clone, Copy, PartialEq)]
-enum HostDiscoveryStatus {
+pub enum HostDiscoveryStatus {
     Up,
     Down,
 }
@@ -39,7 +55,7 @@ impl fmt::Display for HostDiscoveryStatus {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum HostDiscoveryMethod {
     IcmpEcho,
     IcmpTimestamp,
@@ -48,180 +64,689 @@ pub enum HostDiscoveryMethod {
     TcpAck,
     Udp,
     Mac,
+    /// IPv6 neighbor discovery only, the neighbor solicitations go to the solicited-node multicast address
+    /// (pistol does not expose the unicast solicitation).
+    Ndp,
+}
+
+impl HostDiscoveryMethod {
+    /// The method name of the output records.
+    pub fn name(&self) -> &'static str {
+        match self {
+            HostDiscoveryMethod::IcmpEcho => "icmp_echo",
+            HostDiscoveryMethod::IcmpTimestamp => "icmp_timestamp",
+            HostDiscoveryMethod::IcmpAddressMask => "icmp_address_mask",
+            HostDiscoveryMethod::TcpSyn => "tcp_syn",
+            HostDiscoveryMethod::TcpAck => "tcp_ack",
+            HostDiscoveryMethod::Udp => "udp",
+            HostDiscoveryMethod::Mac => "mac",
+            HostDiscoveryMethod::Ndp => "ndp",
+        }
+    }
 }
 
+/// The combined discovery needs at least `up_threshold` methods, so it is in 1..=methods.
+pub fn up_threshold_check(up_threshold: usize, methods: usize) -> Result<usize, String> {
+    if up_threshold == 0 || up_threshold > methods {
+        return Err(format!(
+            "invalid up threshold {}: it must be in 1-{} (the number of discovery methods)",
+            up_threshold, methods
+        ));
+    }
+    Ok(up_threshold)
+}
+
+/// The options of one host discovery run (the hd subcommand).
+#[derive(Debug, Clone)]
+pub struct HostDiscoveryOptions {
+    pub up_threshold: usize,
+    pub timeout: f64,
+    pub num_threads: usize,
+    pub icmp_payload_size: Option<usize>,
+    pub discovery_ports: Vec<u16>,
+    pub group_vendor: bool,
+    pub vendor_histogram: bool,
+    pub verbose: u8,
+    pub output_format: OutputFormat,
+}
+
+/// Several ping methods (such as `-1 -s`) are combined, the mac methods are used alone.
 pub fn host_discovery(
+    targets: &[Target],
+    hd_methods: &[HostDiscoveryMethod],
+    options: &HostDiscoveryOptions,
+) -> Result<ScanOutcome, PslmapError> {
+    match hd_methods {
+        [hd_method @ (HostDiscoveryMethod::Mac | HostDiscoveryMethod::Ndp)] => {
+            host_discovery_by_mac(targets, *hd_method, options)
+        }
+        _ => {
+            if options.group_vendor {
+                eprintln!("warning: --group-vendor only works with the arp or ndp discovery");
+            }
+            if options.vendor_histogram {
+                eprintln!("warning: --vendor-histogram only works with the arp or ndp discovery");
+            }
+            host_discovery_by_ping(targets, hd_methods, options)
+        }
+    }
+}
+
+/// The text line of one host, the down host is only printed when `verbose` >= 1
+/// (with the method and the reason it failed), otherwise it is counted in `other N hosts -> down`.
+fn host_line(
+    addr: IpAddr,
+    status: HostDiscoveryStatus,
+    cost: Duration,
+    method: &str,
+    reason: &str,
+    verbose: u8,
+) -> Option<String> {
+    match status {
+        HostDiscoveryStatus::Up => Some(format!(
+            "{} -> {} ({:.2}s)",
+            addr,
+            status,
+            cost.as_secs_f64()
+        )),
+        HostDiscoveryStatus::Down if verbose >= 1 => {
+            Some(format!("{} -> {} ({}: {})", addr, status, method, reason))
+        }
+        HostDiscoveryStatus::Down => None,
+    }
+}
+
+/// The status cell is colored (green up, red down) when printed to the terminal.
+fn status_cell(status: &str, width: usize, color: bool) -> String {
+    let cell = format!("{:<width$}", status, width = width);
+    match (color, status) {
+        (true, "up") => format!("\x1b[32m{}\x1b[0m", cell),
+        (true, _) => format!("\x1b[31m{}\x1b[0m", cell),
+        (false, _) => cell,
+    }
+}
+
+/// The table output, the column widths follow the longest cell (such as the IPv6 address),
+/// the mac and vendor columns are only shown when any host has the mac.
+fn host_table(records: &[HostRecord], color: bool) -> Vec<String> {
+    let with_mac = records.iter().any(|r| r.mac.is_some());
+    let mut rows = vec![vec![
+        String::from("address"),
+        String::from("status"),
+        String::from("rtt"),
+    ]];
+    if with_mac {
+        rows[0].extend([String::from("mac"), String::from("vendor")]);
+    }
+    for r in records {
+        let mut row = vec![r.addr.clone(), r.status.clone(), format!("{:.2}s", r.cost)];
+        if with_mac {
+            row.push(r.mac.clone().unwrap_or(String::from("-")));
+            row.push(r.ouis.clone().unwrap_or(String::from("-")));
+        }
+        rows.push(row);
+    }
+
+    let mut widths = vec![0; rows[0].len()];
+    for row in &rows {
+        for (i, cell) in row.iter().enumerate() {
+            widths[i] = widths[i].max(cell.len());
+        }
+    }
+    rows.iter()
+        .enumerate()
+        .map(|(i, row)| {
+            let cells: Vec<String> = row
+                .iter()
+                .enumerate()
+                .map(|(j, cell)| match j {
+                    // the header is not colored
+                    1 if i > 0 => status_cell(cell, widths[j], color),
+                    _ => format!("{:<width$}", cell, width = widths[j]),
+                })
+                .collect();
+            cells.join("  ").trim_end().to_string()
+        })
+        .collect()
+}
+
+/// The discovery stage of the combined workflow (host discovery before port scanning).
+/// When `no_ping` is set, no probe is sent and every target is treated as up (same as nmap -Pn),
+/// this is useful when the target's firewall blocks the discovery packets.
+pub fn discovery_stage(
     targets: &[Target],
     hd_method: HostDiscoveryMethod,
-    log_level: PistolLogger,
+    no_ping: bool,
     timeout: f64,
     num_threads: usize,
-) {
+    icmp_payload_size: Option<usize>,
+) -> Result<Vec<(Target, HostDiscoveryStatus)>, PslmapError> {
+    if no_ping {
+        return Ok(targets
+            .iter()
+            .map(|t| (t.clone(), HostDiscoveryStatus::Up))
+            .collect());
+    }
+
+    let mut up_addrs = Vec::new();
     match hd_method {
-        HostDiscoveryMethod::Mac => host_discovery_by_mac(targets, log_level, timeout, num_threads),
-        _ => host_discovery_by_ping(targets, hd_method, log_level, timeout, num_threads),
+        HostDiscoveryMethod::Mac | HostDiscoveryMethod::Ndp => {
+            let (probe_targets, _) = mac_probe_targets(targets, hd_method);
+            let ret = mac_probe(&probe_targets, hd_method, timeout, num_threads)?;
+            for mr in ret.mac_reports {
+                if mr.mac.is_some() {
+                    up_addrs.push(mr.addr);
+                }
+            }
+        }
+        _ => {
+            let ret = ping_probe(
+                targets,
+                hd_method,
+                timeout,
+                num_threads,
+                icmp_payload_size,
+                &[],
+            )?;
+            for ping in ret.ping_reports {
+                if ping.status == PingStatus::Up {
+                    up_addrs.push(ping.addr);
+                }
+            }
+        }
     }
+
+    Ok(targets
+        .iter()
+        .map(|t| {
+            let status = if up_addrs.contains(&t.addr) {
+                HostDiscoveryStatus::Up
+            } else {
+                HostDiscoveryStatus::Down
+            };
+            (t.clone(), status)
+        })
+        .collect())
 }
 
-fn host_discovery_by_ping(
+/// The targets probed by the mac method and the skipped ones,
+/// the ndp method only probes the IPv6 targets (the IPv4 targets need arp).
+fn mac_probe_targets(
+    targets: &[Target],
+    hd_method: HostDiscoveryMethod,
+) -> (Vec<Target>, Vec<IpAddr>) {
+    match hd_method {
+        HostDiscoveryMethod::Ndp => {
+            let (targets_v6, targets_v4): (Vec<Target>, Vec<Target>) =
+                targets.iter().cloned().partition(|t| t.addr.is_ipv6());
+            (targets_v6, targets_v4.iter().map(|t| t.addr).collect())
+        }
+        _ => (targets.to_vec(), Vec::new()),
+    }
+}
+
+/// The arp requests are sent from the spoofed mac when --source-mac is set,
+/// the pistol mac scan sends the ndp neighbor solicitations of the IPv6 targets.
+fn mac_probe(
     targets: &[Target],
     hd_method: HostDiscoveryMethod,
-    log_level: PistolLogger,
     timeout: f64,
     num_threads: usize,
-) {
-    let start = Instant::now();
+) -> Result<PistolMacScans, PslmapError> {
+    let src_addr = *SRC_ADDR.lock().expect("lock SRC_ADDR failed");
+    let src_mac = *SRC_MAC.lock().expect("lock SRC_MAC failed");
+    let max_attempts = 2;
+    let timeout = Some(Duration::from_secs_f64(timeout));
+    match src_mac {
+        Some(src_mac) if hd_method == HostDiscoveryMethod::Mac => Ok(mac_scan_with_source(
+            targets,
+            src_mac,
+            num_threads,
+            src_addr,
+            timeout,
+            max_attempts,
+        )),
+        _ => mac_scan(targets, Some(num_threads), src_addr, timeout, max_attempts)
+            .map_err(PslmapError::pistol("mac scan")),
+    }
+}
+
+/// The targets passed from the discovery stage to the port scanning stage,
+/// the down hosts are skipped unless `scan_down_hosts` is set.
+pub fn scan_stage_targets(
+    discovered: Vec<(Target, HostDiscoveryStatus)>,
+    scan_down_hosts: bool,
+) -> Vec<Target> {
+    discovered
+        .into_iter()
+        .filter(|(_, status)| scan_down_hosts || *status == HostDiscoveryStatus::Up)
+        .map(|(t, _)| t)
+        .collect()
+}
+
+/// Same as the default port of the pistol tcp syn and ack pings.
+const TCP_PING_DEFAULT_PORT: u16 = 80;
 
-    let _pr = PistolRunner::init(log_level, None, None).expect("init pistol runner failed");
+/// The targets of the tcp syn and ack pings, each one probes the `discovery_ports` (--discovery-ports)
+/// or else the first port of the target (default 80).
+fn tcp_ping_targets(targets: &[Target], discovery_ports: &[u16]) -> Vec<Target> {
+    targets
+        .iter()
+        .map(|t| {
+            let mut t = t.clone();
+            t.ports = if !discovery_ports.is_empty() {
+                discovery_ports.to_vec()
+            } else {
+                vec![t.ports.first().copied().unwrap_or(TCP_PING_DEFAULT_PORT)]
+            };
+            t
+        })
+        .collect()
+}
 
+/// The pistol tcp syn ping only takes the syn/ack as up, while the rst of the closed port
+/// also proves the host is up (same as nmap), so the tcp pings are done by the scans of the ping ports.
+/// The host is up when any of its ports got the `up` status, the cost is of the first reply.
+fn tcp_ping_reports(port_reports: &[PortReport], up: &[PortStatus]) -> Vec<PingReport> {
+    let mut ret: Vec<PingReport> = Vec::new();
+    for r in port_reports {
+        let status = if up.contains(&r.status) {
+            PingStatus::Up
+        } else if r.status == PortStatus::Error {
+            PingStatus::Error
+        } else {
+            PingStatus::Down
+        };
+        match ret.iter_mut().find(|p| p.addr == r.addr) {
+            Some(p) => {
+                // up > down > error
+                let replace = match (p.status, status) {
+                    (PingStatus::Up, _) => false,
+                    (_, PingStatus::Up) => true,
+                    (PingStatus::Error, PingStatus::Down) => true,
+                    _ => false,
+                };
+                if replace {
+                    p.status = status;
+                    p.cost = r.cost;
+                }
+            }
+            None => ret.push(PingReport {
+                addr: r.addr,
+                origin: r.origin.clone(),
+                status,
+                cost: r.cost,
+            }),
+        }
+    }
+    ret
+}
+
+/// The `icmp_payload_size` only works with the icmp echo ping of the IPv4 targets,
+/// the timestamp and address mask requests have the fixed size.
+fn ping_probe(
+    targets: &[Target],
+    hd_method: HostDiscoveryMethod,
+    timeout: f64,
+    num_threads: usize,
+    icmp_payload_size: Option<usize>,
+    discovery_ports: &[u16],
+) -> Result<PistolPings, PslmapError> {
+    let threads_num = num_threads;
     let num_threads = Some(num_threads);
-    let src_addr = None;
+    let src_addr = *SRC_ADDR.lock().expect("lock SRC_ADDR failed");
     let src_port = None;
     let max_attempts = 2;
     let timeout = Some(Duration::from_secs_f64(timeout));
 
-    let ret = match hd_method {
+    match hd_method {
+        HostDiscoveryMethod::IcmpEcho if icmp_payload_size.is_some() => {
+            let payload_size = icmp_payload_size.expect("get icmp payload size failed");
+            let (targets_v4, targets_v6): (Vec<Target>, Vec<Target>) =
+                targets.iter().cloned().partition(|t| t.addr.is_ipv4());
+            let mut ret = icmp_echo_ping_with_payload(
+                &targets_v4,
+                payload_size,
+                threads_num,
+                src_addr,
+                timeout,
+                max_attempts,
+            );
+            if !targets_v6.is_empty() {
+                let ret6 = icmp_echo_ping(
+                    &targets_v6,
+                    num_threads,
+                    src_addr,
+                    src_port,
+                    timeout,
+                    max_attempts,
+                )
+                .map_err(PslmapError::pistol("icmp echo ping"))?;
+                ret.ping_reports.extend(ret6.ping_reports);
+            }
+            Ok(ret)
+        }
         HostDiscoveryMethod::IcmpEcho => {
             let ret = icmp_echo_ping(
-                &targets,
+                targets,
                 num_threads,
                 src_addr,
                 src_port,
                 timeout,
                 max_attempts,
             )
-            .expect("icmp echo ping failed");
-            ret
+            .map_err(PslmapError::pistol("icmp echo ping"))?;
+            Ok(ret)
         }
         HostDiscoveryMethod::IcmpTimestamp => {
             let ret = icmp_timestamp_ping(
-                &targets,
+                targets,
                 num_threads,
                 src_addr,
                 src_port,
                 timeout,
                 max_attempts,
             )
-            .expect("icmp timestamp ping failed");
-            ret
+            .map_err(PslmapError::pistol("icmp timestamp ping"))?;
+            Ok(ret)
         }
         HostDiscoveryMethod::IcmpAddressMask => {
             let ret = icmp_address_mask_ping(
-                &targets,
+                targets,
                 num_threads,
                 src_addr,
                 src_port,
                 timeout,
                 max_attempts,
             )
-            .expect("icmp address mask ping failed");
-            ret
+            .map_err(PslmapError::pistol("icmp address mask ping"))?;
+            Ok(ret)
         }
         HostDiscoveryMethod::TcpSyn => {
-            let ret = tcp_syn_ping(
-                &targets,
+            let ret = tcp_syn_scan(
+                &tcp_ping_targets(targets, discovery_ports),
                 num_threads,
                 src_addr,
                 src_port,
                 timeout,
                 max_attempts,
             )
-            .expect("tcp syn ping failed");
-            ret
+            .map_err(PslmapError::pistol("tcp syn ping"))?;
+            let up = [PortStatus::Open, PortStatus::Closed];
+            let mut pings = PistolPings::new(max_attempts);
+            pings.finish(tcp_ping_reports(&ret.port_reports, &up));
+            Ok(pings)
         }
         HostDiscoveryMethod::TcpAck => {
-            let ret = tcp_ack_ping(
-                &targets,
+            // the rst means unfiltered, same as the pistol tcp ack ping
+            let ret = tcp_ack_scan(
+                &tcp_ping_targets(targets, discovery_ports),
                 num_threads,
                 src_addr,
                 src_port,
                 timeout,
                 max_attempts,
             )
-            .expect("tcp ack ping failed");
-            ret
+            .map_err(PslmapError::pistol("tcp ack ping"))?;
+            let up = [PortStatus::Unfiltered];
+            let mut pings = PistolPings::new(max_attempts);
+            pings.finish(tcp_ping_reports(&ret.port_reports, &up));
+            Ok(pings)
         }
         HostDiscoveryMethod::Udp => {
             let ret = udp_ping(
-                &targets,
+                targets,
                 num_threads,
                 src_addr,
                 src_port,
                 timeout,
                 max_attempts,
             )
-            .expect("udps ping failed");
-            ret
+            .map_err(PslmapError::pistol("udp ping"))?;
+            Ok(ret)
         }
-        HostDiscoveryMethod::Mac => unreachable!(),
-    };
+        HostDiscoveryMethod::Mac | HostDiscoveryMethod::Ndp => unreachable!(),
+    }
+}
 
-    // sorted
-    let mut btm = BTreeMap::new();
-    for ping in ret.ping_reports {
-        btm.insert(ping.addr, ping.clone());
+/// The replies of one host from all the discovery methods.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct HostReplies {
+    /// The number of the methods that got the reply.
+    up: usize,
+    error: bool,
+    /// The fastest reply, or the last probe when no method got the reply.
+    cost: Duration,
+}
+
+impl HostReplies {
+    fn status(&self, up_threshold: usize) -> (HostDiscoveryStatus, &'static str) {
+        if self.up >= up_threshold {
+            (HostDiscoveryStatus::Up, "")
+        } else if self.up > 0 {
+            (HostDiscoveryStatus::Down, "too few methods got the reply")
+        } else if self.error {
+            (HostDiscoveryStatus::Down, "probe error")
+        } else {
+            (HostDiscoveryStatus::Down, "no response")
+        }
     }
+}
+
+/// Merge the ping reports of each method by the address (sorted).
+fn merge_pings(method_reports: &[Vec<PingReport>]) -> BTreeMap<IpAddr, HostReplies> {
+    let mut btm: BTreeMap<IpAddr, HostReplies> = BTreeMap::new();
+    for reports in method_reports {
+        for ping in reports {
+            let replies = btm.entry(ping.addr).or_default();
+            match ping.status {
+                PingStatus::Up => {
+                    if replies.up == 0 || ping.cost < replies.cost {
+                        replies.cost = ping.cost;
+                    }
+                    replies.up += 1;
+                }
+                PingStatus::Down | PingStatus::Error => {
+                    replies.error |= ping.status == PingStatus::Error;
+                    if replies.up == 0 {
+                        replies.cost = ping.cost;
+                    }
+                }
+            }
+        }
+    }
+    btm
+}
+
+fn host_discovery_by_ping(
+    targets: &[Target],
+    hd_methods: &[HostDiscoveryMethod],
+    options: &HostDiscoveryOptions,
+) -> Result<ScanOutcome, PslmapError> {
+    let &HostDiscoveryOptions {
+        up_threshold,
+        timeout,
+        num_threads,
+        icmp_payload_size,
+        ref discovery_ports,
+        verbose,
+        output_format,
+        ..
+    } = options;
+    let start = Instant::now();
+    let mut method_reports = Vec::new();
+    for &hd_method in hd_methods {
+        let ret = ping_probe(
+            targets,
+            hd_method,
+            timeout,
+            num_threads,
+            icmp_payload_size,
+            discovery_ports,
+        )?;
+        raw_debug(&ret.ping_reports);
+        method_reports.push(ret.ping_reports);
+    }
+    let failed = method_reports
+        .iter()
+        .flatten()
+        .filter(|p| p.status == PingStatus::Error)
+        .count();
+    if let Some(warning) = send_failure_warning(failed) {
+        eprintln!("warning: {}", warning);
+    }
+    let btm = merge_pings(&method_reports);
+
+    // such as IcmpEcho+TcpSyn in the text and icmp_echo+tcp_syn in the records
+    let method_line: Vec<String> = hd_methods.iter().map(|m| format!("{:?}", m)).collect();
+    let method_line = method_line.join("+");
+    let method_name: Vec<&str> = hd_methods.iter().map(|m| m.name()).collect();
+    let method_name = method_name.join("+");
 
     let mut hosts_up = 0;
     let mut hosts_not_up = 0;
-    let mut info = Vec::new();
-    for (addr, ping) in btm {
-        let new_status = match ping.status {
-            PingStatus::Up => {
+    let mut up_addrs = Vec::new();
+    let mut records = Vec::new();
+    let no_data = no_data_lines(targets, btm.keys());
+    let mut emitter = Emitter::stdout(output_format).with_method(&method_name);
+    for (addr, replies) in btm {
+        let (new_status, reason) = replies.status(up_threshold);
+        match new_status {
+            HostDiscoveryStatus::Up => {
                 hosts_up += 1;
-                HostDiscoveryStatus::Up
-            }
-            _ => {
-                hosts_not_up += 1;
-                HostDiscoveryStatus::Down
+                up_addrs.push(addr);
             }
-        };
-        if new_status == HostDiscoveryStatus::Up {
-            let line = format!(
-                "{} -> {} ({:.2}s)",
-                addr,
-                new_status,
-                ping.cost.as_secs_f64()
-            );
-            info.push(line);
+            HostDiscoveryStatus::Down => hosts_not_up += 1,
         }
+        let line = host_line(
+            addr,
+            new_status,
+            replies.cost,
+            &method_line,
+            reason,
+            verbose,
+        );
+        let record = HostRecord {
+            addr: addr.to_string(),
+            status: new_status.to_string(),
+            cost: replies.cost.as_secs_f64(),
+            mac: None,
+            ouis: None,
+        };
+        emitter.emit(line, &record);
+        records.push(record);
+    }
+    if output_format == OutputFormat::Table {
+        emitter.rows(host_table(&records, std::io::stdout().is_terminal()));
     }
 
-    if hosts_not_up > 0 {
+    if hosts_not_up > 0 && verbose == 0 {
         let line = format!(
             "other {} hosts -> {}",
             hosts_not_up,
             HostDiscoveryStatus::Down
         );
-        info.push(line);
+        emitter.text(line);
+    }
+    if verbose >= 1 {
+        for line in no_data {
+            emitter.text(line);
+        }
     }
 
-    let info = info.join("\n");
     let tail = format!(
         "pslmap done: {} ip addresses ({} hosts up) scanned in {:.2} seconds",
         targets.len(),
         hosts_up,
         start.elapsed().as_secs_f64()
     );
-    InfoShow::print(&info, &tail);
+    emitter.finish(&tail);
+    Ok(ScanOutcome {
+        up_addrs,
+        found: hosts_up,
+        ..Default::default()
+    })
+}
+
+/// The vendor without the oui match.
+const UNKNOWN_VENDOR: &str = "Unknown";
+
+/// The up hosts of each vendor of their mac, the bigger groups first (the same sizes by name).
+fn vendor_hosts(hosts: &[(IpAddr, String)]) -> Vec<(&str, Vec<String>)> {
+    let mut groups: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for (addr, ouis) in hosts {
+        let vendor = match ouis.trim() {
+            "" => UNKNOWN_VENDOR,
+            vendor => vendor,
+        };
+        groups.entry(vendor).or_default().push(addr.to_string());
+    }
+    let mut groups: Vec<(&str, Vec<String>)> = groups.into_iter().collect();
+    groups.sort_by_key(|(_, addrs)| std::cmp::Reverse(addrs.len()));
+    groups
+}
+
+/// Group the up hosts by the vendor of their mac,
+/// such as `Cisco (3): 1.2.3.4, 1.2.3.5, 1.2.3.6`.
+fn vendor_groups(hosts: &[(IpAddr, String)]) -> Vec<String> {
+    vendor_hosts(hosts)
+        .into_iter()
+        .map(|(vendor, addrs)| format!("{} ({}): {}", vendor, addrs.len(), addrs.join(", ")))
+        .collect()
+}
+
+/// The inventory of the up hosts (--vendor-histogram), such as
+/// `6 hosts up, 2 unique vendors` followed by `Cisco    3 ###`,
+/// the hosts without the vendor match are counted as Unknown but not as a vendor.
+fn vendor_histogram_lines(hosts: &[(IpAddr, String)]) -> Vec<String> {
+    let groups = vendor_hosts(hosts);
+    let vendors = groups
+        .iter()
+        .filter(|(vendor, _)| *vendor != UNKNOWN_VENDOR)
+        .count();
+    let mut lines = vec![format!(
+        "{} hosts up, {} unique vendors",
+        hosts.len(),
+        vendors
+    )];
+    let width = groups.iter().map(|(v, _)| v.len()).max().unwrap_or(0);
+    let count_width = hosts.len().to_string().len();
+    for (vendor, addrs) in groups {
+        lines.push(format!(
+            "{:<width$} {:>count_width$} {}",
+            vendor,
+            addrs.len(),
+            "#".repeat(addrs.len())
+        ));
+    }
+    lines
 }
 
 fn host_discovery_by_mac(
     targets: &[Target],
-    log_level: PistolLogger,
-    timeout: f64,
-    num_threads: usize,
-) {
+    hd_method: HostDiscoveryMethod,
+    options: &HostDiscoveryOptions,
+) -> Result<ScanOutcome, PslmapError> {
+    let &HostDiscoveryOptions {
+        timeout,
+        num_threads,
+        group_vendor,
+        vendor_histogram,
+        verbose,
+        output_format,
+        ..
+    } = options;
     let start = Instant::now();
 
-    let _pr = PistolRunner::init(log_level, None, None).expect("init pistol runner failed");
-
-    let num_threads = Some(num_threads);
-    let src_addr = None;
-    let max_attempts = 2;
-    let timeout = Some(Duration::from_secs_f64(timeout));
-    let ret =
-        mac_scan(&targets, num_threads, src_addr, timeout, max_attempts).expect("mac scan failed");
+    let (probe_targets, skipped) = mac_probe_targets(targets, hd_method);
+    if !skipped.is_empty() {
+        eprintln!(
+            "warning: {} IPv4 targets skipped, the ndp discovery only works with IPv6 (use -m for arp)",
+            skipped.len()
+        );
+    }
+    let ret = mac_probe(&probe_targets, hd_method, timeout, num_threads)?;
+    raw_debug(&ret.mac_reports);
 
     // sorted
     let mut all_ips = Vec::new();
@@ -235,40 +760,372 @@ fn host_discovery_by_mac(
 
     let mut hosts_up = 0;
     let mut hosts_not_up = 0;
-    let mut info = Vec::new();
+    let mut up_addrs = Vec::new();
+    let mut up_vendors = Vec::new();
+    let mut records = Vec::new();
+    // the skipped targets are already warned above
+    let no_data = no_data_lines(&probe_targets, btm.keys());
+    let mut emitter = Emitter::stdout(output_format).with_method(hd_method.name());
     for (addr, mr) in btm {
         match mr.mac {
             Some(mac) => {
                 hosts_up += 1;
-                let line = format!(
-                    "{} -> {} ({:.2}s) ({}) ({})",
+                up_addrs.push(addr);
+                up_vendors.push((addr, mr.ouis.clone()));
+                // the host lines are kept with --group-vendor only in the verbose mode
+                let line = if group_vendor && verbose == 0 {
+                    None
+                } else {
+                    Some(format!(
+                        "{} -> {} ({:.2}s) ({}) ({})",
+                        addr,
+                        HostDiscoveryStatus::Up,
+                        mr.rtt.as_secs_f64(),
+                        mac,
+                        mr.ouis,
+                    ))
+                };
+                let record = HostRecord {
+                    addr: addr.to_string(),
+                    status: HostDiscoveryStatus::Up.to_string(),
+                    cost: mr.rtt.as_secs_f64(),
+                    mac: Some(mac.to_string()),
+                    ouis: Some(mr.ouis.clone()),
+                };
+                emitter.emit(line, &record);
+                records.push(record);
+            }
+            _ => {
+                hosts_not_up += 1;
+                let line = host_line(
                     addr,
-                    HostDiscoveryStatus::Up,
-                    mr.rtt.as_secs_f64(),
-                    mac,
-                    mr.ouis,
+                    HostDiscoveryStatus::Down,
+                    mr.rtt,
+                    &format!("{:?}", hd_method),
+                    "no arp or ndp reply",
+                    verbose,
                 );
-                info.push(line);
+                let record = HostRecord {
+                    addr: addr.to_string(),
+                    status: HostDiscoveryStatus::Down.to_string(),
+                    cost: mr.rtt.as_secs_f64(),
+                    mac: None,
+                    ouis: None,
+                };
+                emitter.emit(line, &record);
+                records.push(record);
             }
-            _ => hosts_not_up += 1,
         };
     }
+    if output_format == OutputFormat::Table {
+        emitter.rows(host_table(&records, std::io::stdout().is_terminal()));
+    }
+    if group_vendor {
+        for line in vendor_groups(&up_vendors) {
+            emitter.text(line);
+        }
+    }
+    if vendor_histogram {
+        for line in vendor_histogram_lines(&up_vendors) {
+            emitter.text(line);
+        }
+    }
 
-    if hosts_not_up > 0 {
+    if hosts_not_up > 0 && verbose == 0 {
         let line = format!(
             "other {} hosts -> {}",
             hosts_not_up,
             HostDiscoveryStatus::Down
         );
-        info.push(line);
+        emitter.text(line);
+    }
+    if verbose >= 1 {
+        for line in no_data {
+            emitter.text(line);
+        }
     }
 
-    let info = info.join("\n");
     let tail = format!(
         "pslmap done: {} ip addresses ({} hosts up) scanned in {:.2} seconds",
         targets.len(),
         hosts_up,
         start.elapsed().as_secs_f64()
     );
-    InfoShow::print(&info, &tail);
+    emitter.finish(&tail);
+    Ok(ScanOutcome {
+        up_addrs,
+        found: hosts_up,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+    #[test]
+    fn test_vendor_groups() {
+        let host =
+            |d: u8, ouis: &str| (IpAddr::V4(Ipv4Addr::new(192, 168, 5, d)), ouis.to_string());
+        let hosts = vec![
+            host(1, "Cisco"),
+            host(2, "Apple"),
+            host(3, "Cisco"),
+            host(4, ""),
+            host(5, "Cisco"),
+            host(6, "Apple"),
+        ];
+        assert_eq!(
+            vendor_groups(&hosts),
+            vec![
+                "Cisco (3): 192.168.5.1, 192.168.5.3, 192.168.5.5",
+                "Apple (2): 192.168.5.2, 192.168.5.6",
+                "Unknown (1): 192.168.5.4",
+            ]
+        );
+    }
+    #[test]
+    fn test_vendor_histogram() {
+        let host =
+            |d: u8, ouis: &str| (IpAddr::V4(Ipv4Addr::new(192, 168, 5, d)), ouis.to_string());
+        let hosts: Vec<(IpAddr, String)> = (1..=12)
+            .map(|d| match d % 4 {
+                0 => host(d, ""),
+                1 => host(d, "Apple"),
+                _ => host(d, "Cisco"),
+            })
+            .collect();
+        assert_eq!(
+            vendor_histogram_lines(&hosts),
+            vec![
+                "12 hosts up, 2 unique vendors",
+                "Cisco    6 ######",
+                "Apple    3 ###",
+                "Unknown  3 ###",
+            ]
+        );
+        assert_eq!(
+            vendor_histogram_lines(&[]),
+            vec!["0 hosts up, 0 unique vendors"]
+        );
+    }
+    #[test]
+    fn test_host_table() {
+        let record = |addr: &str, status: HostDiscoveryStatus, mac: Option<&str>| HostRecord {
+            addr: addr.to_string(),
+            status: status.to_string(),
+            cost: 0.01,
+            mac: mac.map(String::from),
+            ouis: mac.map(|_| String::from("Intel")),
+        };
+        let records = vec![
+            record(
+                "192.168.5.5",
+                HostDiscoveryStatus::Up,
+                Some("00:11:22:33:44:55"),
+            ),
+            record("fe80::20c:29ff:fe12:3456", HostDiscoveryStatus::Down, None),
+        ];
+        let lines = host_table(&records, false);
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("address "));
+        // every column starts at the same offset
+        for column in ["status", "rtt", "mac", "vendor"] {
+            let offset = lines[0].find(column).unwrap();
+            for line in &lines[1..] {
+                assert_ne!(line.as_bytes()[offset], b' ');
+                assert_eq!(line.as_bytes()[offset - 1], b' ');
+            }
+        }
+        assert_eq!(
+            lines[2],
+            "fe80::20c:29ff:fe12:3456  down    0.01s  -                  -"
+        );
+
+        let lines = host_table(&records, true);
+        assert!(lines[1].contains("\x1b[32mup    \x1b[0m"));
+        assert!(lines[2].contains("\x1b[31mdown  \x1b[0m"));
+    }
+    #[test]
+    fn test_no_ping() {
+        let targets: Vec<Target> = (1..=5)
+            .map(|i| Target::new(Ipv4Addr::new(192, 168, 5, i).into(), Some(vec![80])))
+            .collect();
+        // no probe is sent here, otherwise this test would need the raw socket permission
+        let ret =
+            discovery_stage(&targets, HostDiscoveryMethod::IcmpEcho, true, 1.0, 4, None).unwrap();
+        assert_eq!(ret.len(), targets.len());
+        for (t, status) in ret {
+            assert_eq!(status, HostDiscoveryStatus::Up);
+            assert_eq!(t.ports, vec![80]);
+        }
+    }
+    #[test]
+    fn test_scan_down_hosts() {
+        let up = Target::new(Ipv4Addr::new(192, 168, 5, 1).into(), Some(vec![22, 80]));
+        let down = Target::new(Ipv4Addr::new(192, 168, 5, 2).into(), Some(vec![22, 80]));
+        let discovered = vec![
+            (up.clone(), HostDiscoveryStatus::Up),
+            (down.clone(), HostDiscoveryStatus::Down),
+        ];
+        let ret = scan_stage_targets(discovered.clone(), false);
+        assert_eq!(ret.len(), 1);
+        assert_eq!(ret[0].addr, up.addr);
+
+        let ret = scan_stage_targets(discovered, true);
+        assert_eq!(ret.len(), 2);
+        assert_eq!(ret[1].addr, down.addr);
+        assert_eq!(ret[1].ports, vec![22, 80]);
+    }
+    #[test]
+    fn test_ndp_targets() {
+        let targets = vec![
+            Target::new(Ipv4Addr::new(192, 168, 5, 1).into(), None),
+            Target::new("fe80::1".parse().unwrap(), None),
+            Target::new("fd00::5".parse().unwrap(), None),
+        ];
+        let (probe_targets, skipped) = mac_probe_targets(&targets, HostDiscoveryMethod::Ndp);
+        let addrs: Vec<String> = probe_targets.iter().map(|t| t.addr.to_string()).collect();
+        assert_eq!(addrs, vec!["fe80::1", "fd00::5"]);
+        assert_eq!(skipped, vec![targets[0].addr]);
+
+        // the mac method keeps probing both families
+        let (probe_targets, skipped) = mac_probe_targets(&targets, HostDiscoveryMethod::Mac);
+        assert_eq!(probe_targets.len(), 3);
+        assert!(skipped.is_empty());
+    }
+    #[test]
+    fn test_syn_ping_rst() {
+        let report = |last: u8, status: PortStatus| PortReport {
+            addr: Ipv4Addr::new(192, 168, 5, last).into(),
+            port: 80,
+            origin: None,
+            status,
+            cost: Duration::from_millis(10),
+        };
+        let reports = vec![
+            report(5, PortStatus::Closed),
+            report(6, PortStatus::Open),
+            report(7, PortStatus::Filtered),
+            report(8, PortStatus::Error),
+        ];
+        let up = [PortStatus::Open, PortStatus::Closed];
+        let statuses: Vec<PingStatus> = tcp_ping_reports(&reports, &up)
+            .into_iter()
+            .map(|p| p.status)
+            .collect();
+        // the rst only response still means up
+        assert_eq!(
+            statuses,
+            vec![
+                PingStatus::Up,
+                PingStatus::Up,
+                PingStatus::Down,
+                PingStatus::Error
+            ]
+        );
+    }
+    #[test]
+    fn test_discovery_ports() {
+        let addr: IpAddr = Ipv4Addr::new(192, 168, 5, 5).into();
+        let targets = vec![
+            Target::new(addr, Some(vec![22, 8080])),
+            Target::new(Ipv4Addr::new(192, 168, 5, 6).into(), None),
+        ];
+        let ping_targets = tcp_ping_targets(&targets, &[443, 8443]);
+        assert_eq!(ping_targets[0].ports, vec![443, 8443]);
+        assert_eq!(ping_targets[1].ports, vec![443, 8443]);
+        // without --discovery-ports
+        let ping_targets = tcp_ping_targets(&targets, &[]);
+        assert_eq!(ping_targets[0].ports, vec![22]);
+        assert_eq!(ping_targets[1].ports, vec![TCP_PING_DEFAULT_PORT]);
+
+        // up when any of the ports replied
+        let report = |port: u16, status: PortStatus| PortReport {
+            addr,
+            port,
+            origin: None,
+            status,
+            cost: Duration::from_millis(port as u64),
+        };
+        let reports = vec![
+            report(443, PortStatus::Filtered),
+            report(8443, PortStatus::Unfiltered),
+        ];
+        let pings = tcp_ping_reports(&reports, &[PortStatus::Unfiltered]);
+        assert_eq!(pings.len(), 1);
+        assert_eq!(pings[0].status, PingStatus::Up);
+        assert_eq!(pings[0].cost, Duration::from_millis(8443));
+    }
+    #[test]
+    fn test_up_threshold() {
+        let addr1 = IpAddr::V4(Ipv4Addr::new(192, 168, 5, 5));
+        let addr2 = IpAddr::V4(Ipv4Addr::new(192, 168, 5, 6));
+        let ping = |addr: IpAddr, status: PingStatus, ms: u64| PingReport {
+            addr,
+            origin: None,
+            status,
+            cost: Duration::from_millis(ms),
+        };
+        // three methods, addr1 answers two of them and addr2 only the last one
+        let method_reports = vec![
+            vec![
+                ping(addr1, PingStatus::Up, 30),
+                ping(addr2, PingStatus::Down, 1000),
+            ],
+            vec![
+                ping(addr1, PingStatus::Up, 10),
+                ping(addr2, PingStatus::Down, 1000),
+            ],
+            vec![
+                ping(addr1, PingStatus::Down, 1000),
+                ping(addr2, PingStatus::Up, 20),
+            ],
+        ];
+        let btm = merge_pings(&method_reports);
+        assert_eq!(btm[&addr1].up, 2);
+        assert_eq!(btm[&addr1].cost, Duration::from_millis(10));
+        assert_eq!(btm[&addr2].up, 1);
+
+        let threshold = up_threshold_check(2, method_reports.len()).unwrap();
+        assert_eq!(btm[&addr1].status(threshold).0, HostDiscoveryStatus::Up);
+        assert_eq!(
+            btm[&addr2].status(threshold),
+            (HostDiscoveryStatus::Down, "too few methods got the reply")
+        );
+        // the default threshold 1 keeps it
+        assert_eq!(btm[&addr2].status(1).0, HostDiscoveryStatus::Up);
+
+        assert!(up_threshold_check(0, 3).is_err());
+        assert!(up_threshold_check(4, 3).is_err());
+
+        // the target dropped by every method has no data
+        let addr3 = IpAddr::V4(Ipv4Addr::new(192, 168, 5, 7));
+        let targets: Vec<Target> = [addr1, addr2, addr3]
+            .into_iter()
+            .map(|addr| Target::new(addr, None))
+            .collect();
+        assert_eq!(
+            no_data_lines(&targets, btm.keys()),
+            vec!["192.168.5.7 -> no data"]
+        );
+    }
+    #[test]
+    fn test_verbose_down_hosts() {
+        let cost = Duration::from_millis(10);
+        let addrs: Vec<IpAddr> = (1..=3)
+            .map(|i| Ipv4Addr::new(192, 168, 5, i).into())
+            .collect();
+        let method = "IcmpEcho";
+        let down = HostDiscoveryStatus::Down;
+        for addr in &addrs {
+            assert_eq!(host_line(*addr, down, cost, method, "no response", 0), None);
+        }
+        let lines: Vec<String> = addrs
+            .iter()
+            .filter_map(|addr| host_line(*addr, down, cost, method, "no response", 1))
+            .collect();
+        assert_eq!(lines.len(), addrs.len());
+        assert_eq!(lines[0], "192.168.5.1 -> down (IcmpEcho: no response)");
+    }
 }
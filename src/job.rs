@@ -0,0 +1,137 @@
+/// scan job file (--input)
+use pistol::Target;
+use serde::Deserialize;
+use serde::Deserializer;
+use std::fs;
+
+use crate::tp::TargetParser;
+
+/// The whole scan in one file, such as
+/// ```yaml
+/// method: syn
+/// ports: 22,80
+/// timeout: 0.5
+/// threads: 16
+/// targets:
+///   - addr: 192.168.1.0/24
+///   - addr: example.com
+///     ports: 443
+/// ```
+/// or the same keys in json. The job values override the command line,
+/// the `ports` of the target overrides the `ports` of the job.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Job {
+    pub method: Option<String>,
+    #[serde(default, deserialize_with = "ports_value")]
+    pub ports: Option<String>,
+    pub timeout: Option<f64>,
+    pub threads: Option<usize>,
+    pub targets: Vec<JobTarget>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JobTarget {
+    pub addr: String,
+    #[serde(default, deserialize_with = "ports_value")]
+    pub ports: Option<String>,
+}
+
+impl Job {
+    /// The targets of the job, `ports` is used by the targets (and the job) without ports.
+    pub fn targets(&self, parser: &mut TargetParser, ports: Option<String>) -> Vec<Target> {
+        let mut targets = Vec::new();
+        for t in &self.targets {
+            let target_ports = t.ports.clone().or(self.ports.clone()).or(ports.clone());
+            targets.extend(parser.target_from_input(&t.addr, target_ports));
+        }
+        targets
+    }
+}
+
+/// The ports of yaml may be the bare number (`ports: 443`).
+fn ports_value<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Ports {
+        Text(String),
+        Number(u16),
+    }
+    let ports = Option::<Ports>::deserialize(deserializer)?;
+    Ok(ports.map(|p| match p {
+        Ports::Text(ports) => ports,
+        Ports::Number(port) => port.to_string(),
+    }))
+}
+
+/// The json job starts with `{`, the others are parsed as yaml.
+pub fn job_parser(content: &str) -> Result<Job, String> {
+    let job: Job = if content.trim_start().starts_with("{") {
+        serde_json::from_str(content).map_err(|e| format!("invalid json job: {}", e))?
+    } else {
+        serde_yaml::from_str(content).map_err(|e| format!("invalid yaml job: {}", e))?
+    };
+    if job.targets.is_empty() {
+        return Err(String::from("invalid job: no targets"));
+    }
+    Ok(job)
+}
+
+pub fn load_job(filename: &str) -> Result<Job, String> {
+    let content =
+        fs::read_to_string(filename).map_err(|e| format!("read {} failed: {}", filename, e))?;
+    job_parser(&content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::IpAddr;
+    use std::net::Ipv4Addr;
+    #[test]
+    fn test_job_parser() {
+        let content = r#"
+# nightly job
+method: connect
+ports: "22,80"
+timeout: 0.5
+threads: 16
+targets:
+  - addr: 192.168.5.5
+  - addr: 192.168.5.6-7
+    ports: 443
+"#;
+        let job = job_parser(content).unwrap();
+        assert_eq!(job.method, Some(String::from("connect")));
+        assert_eq!(job.timeout, Some(0.5));
+        assert_eq!(job.threads, Some(16));
+        assert_eq!(job.targets.len(), 2);
+
+        let targets = job.targets(&mut TargetParser::new(), Some(String::from("8080")));
+        let ret: Vec<(IpAddr, Vec<u16>)> = targets.into_iter().map(|t| (t.addr, t.ports)).collect();
+        assert_eq!(
+            ret,
+            vec![
+                (Ipv4Addr::new(192, 168, 5, 5).into(), vec![22, 80]),
+                (Ipv4Addr::new(192, 168, 5, 6).into(), vec![443]),
+                (Ipv4Addr::new(192, 168, 5, 7).into(), vec![443]),
+            ]
+        );
+
+        let json = r#"{"method": "connect", "ports": "22,80", "timeout": 0.5, "threads": 16,
+            "targets": [{"addr": "192.168.5.5"}, {"addr": "192.168.5.6-7", "ports": "443"}]}"#;
+        assert_eq!(job_parser(json).unwrap(), job);
+
+        assert!(job_parser("method: syn\nspeed: 1\ntargets:\n  - addr: 10.0.0.1").is_err());
+        assert!(job_parser("method: syn").is_err());
+        assert!(job_parser("method: syn\ntargets: []").is_err());
+        // the ports are one string, not a list
+        assert!(job_parser("ports: [22]\ntargets: [{addr: 192.168.5.5}]").is_err());
+        // the comments, the flow style list and the escapes of yaml
+        let job =
+            job_parser("method: 'syn' # fast\ntargets: [{addr: \"192.168.\\x35.5\"}]").unwrap();
+        assert_eq!(job.method, Some(String::from("syn")));
+        assert_eq!(job.targets[0].addr, "192.168.5.5");
+    }
+}
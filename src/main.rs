@@ -1,27 +1,134 @@
 use chrono::DateTime;
 use chrono::Local;
+use clap::ArgMatches;
+use clap::CommandFactory;
+use clap::FromArgMatches;
 use clap::Parser;
 use clap::Subcommand;
+use clap::parser::ValueSource;
 use pistol::PistolLogger;
+use pistol::PistolRunner;
+use pistol::Target;
+use pistol::layer::infer_addr;
+use pnet::datalink::MacAddr;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::net::IpAddr;
 use std::net::Ipv4Addr;
 use std::sync::Arc;
 use std::sync::LazyLock;
 use std::sync::Mutex;
+use std::time::Duration;
 
+mod bs;
+mod data;
+mod dns;
+mod echo;
+mod error;
+mod exclude;
+mod fp;
 mod hd;
+mod job;
+mod methods;
 mod od;
+mod output;
+mod plan;
+mod ports;
+mod pr;
+mod profile;
 mod ps;
+mod repl;
+mod rtt;
+mod saved;
 mod sd;
+mod services;
+mod sf;
+mod sm;
+mod sport;
+mod stats;
+mod syslog;
 mod tp;
+mod tr;
+mod udp;
+mod watch;
+mod zombie;
 
+use data::data_supported;
+use data::probe_data;
+use dns::dns_servers_parser;
+use dns::names_line;
+use dns::reverse_lookup;
+use dns::system_dns_servers;
+use echo::payload_size_check;
+use error::PslmapError;
+use exclude::ExcludeTable;
 use hd::HostDiscoveryMethod;
+use hd::HostDiscoveryOptions;
+use hd::discovery_stage;
 use hd::host_discovery;
+use hd::scan_stage_targets;
+use hd::up_threshold_check;
+use job::Job;
+use job::load_job;
+use methods::method_lines;
+use od::OsDetectionOptions;
 use od::os_detection;
+use od::os_ports_targets;
+use od::os_threads_parser;
+use output::OpenSink;
+use output::OutputFormat;
+use output::ScanHeader;
+use output::set_header;
+use output::set_open_sink;
+use output::set_quiet;
+use output::set_raw_debug;
+use output::set_syslog;
+use plan::discovery_plan;
+use plan::os_plan;
+use plan::scan_plan;
+use ports::EmptyPorts;
+use ports::FAST_PORTS_NUM;
+use ports::empty_ports_fill;
+use ports::shuffle_ports;
+use ports::top_ports;
+use ports::top_ports_parser;
+use profile::DEFAULT_PROFILES_FILE;
+use profile::Profile;
+use profile::load_profile;
+use ps::PortScanOptions;
 use ps::PortScanningMethod;
+use ps::RetryPolicy;
 use ps::port_scanning;
+use repl::repl;
+use rtt::DEFAULT_MAX_RTT_TIMEOUT;
+use rtt::DEFAULT_MIN_RTT_TIMEOUT;
+use rtt::RttTimeout;
+use saved::load_targets;
+use saved::save_targets;
+use sf::scanflags_parser;
+use sm::mac_parser;
+use sm::source_mac_check;
+use sport::SourcePortCycle;
+use sport::source_port_range_parser;
+use stats::set_stats;
+use syslog::Facility;
+use syslog::Syslog;
+use tp::DEFAULT_DNS_CONCURRENCY;
+use tp::DEFAULT_MAX_HOSTS;
+use tp::InputFormat;
 use tp::TargetParser;
+use tp::zone_src_addr;
+use tr::traceroute;
+use udp::set_udp_batch;
+use watch::Repeat;
+use watch::UnionSummary;
+use watch::port_diff;
+use zombie::ipid_class;
+use zombie::zombie_check;
+use zombie::zombie_ipids;
 
-#[derive(Subcommand, Debug)]
+#[derive(Subcommand, Debug, Clone)]
 enum ToolsSubcommand {
     /// Perform host discovery.
     HD {
@@ -34,7 +141,7 @@ enum ToolsSubcommand {
         /// Perform host discovery using ICMP Address Mask Ping (useful when the target's firewall blocks icmp packets).
         #[arg(short = '3', long = "p3", action, default_value_t = false)]
         ping3: bool,
-        /// Perform host discovery using TCP SYN Ping (default target port is 80).
+        /// Perform host discovery using TCP SYN Ping (default target port is 80), the SYN/ACK or RST means up.
         #[arg(short, long, action, default_value_t = false)]
         syn: bool,
         /// Perform host discovery using TCP ACK Ping (default target port is 80).
@@ -46,6 +153,24 @@ enum ToolsSubcommand {
         /// Perform host discovery using ARP (IPv4) or NDP_NS (IPv6) (this works well when the target machine are on the same subnet).
         #[arg(short, long, action, default_value_t = false)]
         mac: bool,
+        /// Perform host discovery using IPv6 neighbor discovery (NDP) only, the IPv4 targets are skipped.
+        #[arg(short, long, action, default_value_t = false)]
+        ndp: bool,
+        /// Trace the path (hops and rtt) to each host that is up after host discovery.
+        #[arg(long, action, default_value_t = false)]
+        traceroute: bool,
+        /// With several ping methods (such as -1 -s), the host is up only when at least k methods got its reply.
+        #[arg(long, value_name = "K", default_value_t = 1)]
+        up_threshold: usize,
+        /// The ports probed by the TCP SYN and ACK pings (such as 22,443), the host is up when any of them replied.
+        #[arg(long, value_name = "PORTS")]
+        discovery_ports: Option<String>,
+        /// Group the up hosts of the ARP or NDP discovery by their mac vendor with the counts (the host lines are kept with -v)
+        #[arg(long, action, default_value_t = false)]
+        group_vendor: bool,
+        /// Print the hosts up, the unique vendors and the host count of each vendor after the ARP or NDP discovery
+        #[arg(long, action, default_value_t = false)]
+        vendor_histogram: bool,
     },
     /// Perform port scanning.
     PS {
@@ -73,9 +198,48 @@ enum ToolsSubcommand {
         /// Perform port scanning using TCP Maimon scan.
         #[arg(short, long, action, default_value_t = false)]
         maimon: bool,
+        /// Perform port scanning with the custom TCP flags, such as SYNFIN or URGACKPSHRSTSYNFIN (IPv4 only).
+        #[arg(long, value_name = "FLAGS", value_parser = scanflags_parser)]
+        scanflags: Option<u8>,
         /// Perform port scanning using UDP scan.
         #[arg(short, long, action, default_value_t = false)]
         udp: bool,
+        /// Perform port scanning using SCTP INIT scan (not supported by pistol yet).
+        #[arg(long, action, default_value_t = false)]
+        sctp: bool,
+        /// Perform IP protocol scan, the ports (-p) are the protocol numbers (default all 256 protocols, IPv4 only).
+        #[arg(short = 'O', long, action, default_value_t = false)]
+        ip_proto: bool,
+        /// Perform host discovery using ICMP Echo Ping before port scanning and only scan the hosts that are up (see --no-ping).
+        #[arg(short, long, action, default_value_t = false)]
+        discover: bool,
+        /// Stop the scanning as soon as the first open port is found and only print that port.
+        #[arg(long, action, default_value_t = false)]
+        first_open: bool,
+        /// Print the not open ports of each host as compact ranges by status (such as 1-21,23-79 -> closed).
+        #[arg(long, action, default_value_t = false)]
+        closed_summary: bool,
+        /// Print the open, closed and filtered port counts of each host before the summary tail.
+        #[arg(long, action, default_value_t = false)]
+        per_host_summary: bool,
+        /// Append why each printed port got its status, such as (syn-ack) or (no response) for the udp open|filtered ports.
+        #[arg(long, action, default_value_t = false)]
+        reason: bool,
+        /// Send the probes with bad tcp/udp checksum, any response means a firewall or IDS answered without verifying it (IPv4 only).
+        #[arg(long, action, default_value_t = false)]
+        badsum: bool,
+        /// Append the response TTL and TCP window size to each open port line, such as [ttl=64 win=29200] (TCP SYN and ACK scans, IPv4 only).
+        #[arg(long, action, default_value_t = false)]
+        show_fingerprint: bool,
+        /// Append the service name guessed from the port number to each port line, such as 22 -> ssh (not the version detection).
+        #[arg(long, action, default_value_t = false)]
+        service_names: bool,
+        /// List at most n open ports of each host, the others are counted (the host with every port open is flagged as a likely tarpit).
+        #[arg(long, value_name = "N")]
+        max_open_per_host: Option<usize>,
+        /// Probe each open port n (at least 2) times and show the median and standard deviation of the rtts, such as 80/tcp -> open (median 0.021s ±0.003).
+        #[arg(long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(2..))]
+        samples: Option<usize>,
         /// Perform port scanning using TCP Idle scan.
         #[command(subcommand)]
         idle: Option<IdleSubcommand>,
@@ -85,27 +249,45 @@ enum ToolsSubcommand {
         /// Return only the top_k results.
         #[arg(short, long, default_value_t = 3)]
         top_k: usize,
-        /// Set the open_tcp_port parameter.
-        #[arg(short = '1', long)]
-        open_tcp_port: u16,
-        /// Set the close_tcp_port parameter.
-        #[arg(short = '2', long)]
-        close_tcp_port: u16,
+        /// Set the open_tcp_port parameter (found by a quick syn scan of the target ports of each host when not set).
+        #[arg(short = '1', long, requires = "close_tcp_port")]
+        open_tcp_port: Option<u16>,
+        /// Set the close_tcp_port parameter (found by a quick syn scan of the target ports of each host when not set).
+        #[arg(short = '2', long, requires = "open_tcp_port")]
+        close_tcp_port: Option<u16>,
         /// Set the close_udp_port parameter.
         #[arg(short = '3', long)]
         close_udp_port: u16,
+        /// Max number of the hosts fingerprinted at the same time (default --max-host-parallelism)
+        #[arg(long)]
+        os_threads: Option<usize>,
+        /// Group the hosts by their top OS match instead of one table per host (the tables are kept with -v)
+        #[arg(long, action, default_value_t = false)]
+        group_os: bool,
+        /// Only fingerprint the hosts answering the ICMP Echo or TCP SYN ping (to the open tcp port)
+        #[arg(long, action, default_value_t = false)]
+        alive_only: bool,
+        /// Wait at most 0.3s for each OS probe response, quicker but lower confidence guess
+        #[arg(long, action, default_value_t = false)]
+        os_short_timeout: bool,
     },
+    /// List the supported scan methods with their flags and defaults.
+    Methods,
 }
 
-#[derive(Subcommand, Debug)]
+#[derive(Subcommand, Debug, Clone)]
 enum IdleSubcommand {
+    #[allow(clippy::upper_case_acronyms)]
     IDLE {
         /// TCP Idle scan zommbie IPv4 address.
         #[arg(short, long)]
         zombie_ipv4: Ipv4Addr,
         /// TCP Idle scan zommbie IPv4 port.
-        #[arg(short, long)]
+        #[arg(short = 'p', long)]
         zombie_port: u16,
+        /// Scan even when the ip id sequence of the zombie is not incremental (only warn).
+        #[arg(long, action, default_value_t = false)]
+        force_zombie: bool,
     },
 }
 
@@ -113,29 +295,89 @@ enum IdleSubcommand {
 #[derive(Parser, Debug)]
 #[command(author = "RikoNaka", version, about, long_about = None)]
 struct Args {
-    /// Input target
+    /// Input target, each address can take its own ports (such as 10.0.0.0/24:22,80 or [::1]:22)
     #[arg(short, long)]
     target: Option<String>,
 
-    /// Input from list of hosts/networks (same as nmap -iL option)
+    /// Input from list of hosts/networks (same as nmap -iL option), can be combined with --target
     #[arg(short, long)]
     filename: Option<String>,
 
-    /// Specified ports
+    /// How the target file is read, one target per line, one column of the csv or the nmap grepable output (-oG)
+    #[arg(long, value_enum, default_value_t = InputFormat::Lines)]
+    input_format: InputFormat,
+
+    /// The address column of the csv target file, the header name or the 1-based column number (no header)
+    #[arg(long, default_value = "addr")]
+    csv_column: String,
+
+    /// Specified ports, such as 22,80-90 or the categories @web, @db and @mail (e.g. @web,9000)
     #[arg(short, long)]
     ports: Option<String>,
 
+    /// Scan the top n most common tcp ports (same as nmap --top-ports option), or the top percentage such as 5%
+    #[arg(long, conflicts_with = "ports", value_parser = top_ports_parser)]
+    top_ports: Option<usize>,
+
+    /// Scan the top 100 most common tcp ports (same as nmap -F option)
+    #[arg(short = 'F', long, action, default_value_t = false, conflicts_with_all = ["ports", "top_ports"])]
+    fast: bool,
+
+    /// What ps and od do with the targets without ports, scan the default top 1000 ports (default), the top 100 ports or stop with the error
+    #[arg(long, value_enum, default_value_t = EmptyPorts::Default)]
+    empty_ports: EmptyPorts,
+
+    /// Probe the ports of each target in random order (the output is still sorted by port)
+    #[arg(long, action, default_value_t = false)]
+    randomize_ports: bool,
+
+    /// Seed used by the randomize options, set it to reproduce the same probe order
+    #[arg(long)]
+    randomize_seed: Option<u64>,
+
     #[command(subcommand)]
-    tools: ToolsSubcommand,
+    tools: Option<ToolsSubcommand>,
+
+    /// Read the scans (such as `-t 192.168.1.1 ps --syn`) from the prompt, the pistol runner is kept across them
+    #[arg(long, action, default_value_t = false)]
+    interactive: bool,
+
+    /// Timeout, such as 500ms, 2s or 1m (a bare number is seconds), default 0.5s for the ARP/NDP discovery, 3s for the OS detection and 1s for the others
+    #[arg(long = "to", value_parser = timeout_parser)]
+    timeout: Option<f64>,
+
+    /// Lower bound (seconds) of the adaptive port scanning timeout (default 0.1 when any rtt timeout is set)
+    #[arg(long)]
+    min_rtt_timeout: Option<f64>,
 
-    /// Timeout
-    #[arg(long = "to", default_value_t = 1.0)]
-    timeout: f64,
+    /// Upper bound (seconds) of the adaptive port scanning timeout (default 10 when any rtt timeout is set)
+    #[arg(long)]
+    max_rtt_timeout: Option<f64>,
 
-    /// Threads
+    /// Starting timeout (seconds) of the adaptive port scanning timeout (default --to), then it follows the observed rtts
+    #[arg(long)]
+    initial_rtt_timeout: Option<f64>,
+
+    /// Threads, the shorthand that sets both --max-host-parallelism and --max-probe-parallelism
     #[arg(long = "nt", default_value_t = 4)]
     num_threads: usize,
 
+    /// Max number of the hosts scanned at the same time (the port scanning host groups and the os detection hosts)
+    #[arg(long)]
+    max_host_parallelism: Option<usize>,
+
+    /// Max number of the probes sent at the same time (the pistol scan threads)
+    #[arg(long)]
+    max_probe_parallelism: Option<usize>,
+
+    /// Min number of the probes sent at the same time, it raises --nt (useful on the fast local networks)
+    #[arg(long)]
+    min_parallelism: Option<usize>,
+
+    /// Print more details, such as each down host with the reason of host discovery (repeatable, -vv)
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+
     /// Display log level (debug, warn, info and none)
     #[arg(short, long, default_value = "none")]
     log: String,
@@ -147,21 +389,220 @@ struct Args {
     /// Set the IPv4 address to have the highest priority (same as above)
     #[arg(short = '4', long, action, default_value_t = false)]
     ipv4: bool,
+
+    /// Only scan the IPv4 targets, unlike -4 it also drops the literal IPv6 addresses and networks
+    #[arg(long, action, default_value_t = false, conflicts_with = "only_ipv6")]
+    only_ipv4: bool,
+
+    /// Only scan the IPv6 targets (same as above)
+    #[arg(long, action, default_value_t = false)]
+    only_ipv6: bool,
+
+    /// Refuse to scan when any target is a public address (not RFC 1918, ULA, loopback or link-local)
+    #[arg(long, action, default_value_t = false)]
+    private_only: bool,
+
+    /// Exclude these hosts or networks from the targets (e.g. 192.168.1.1,10.0.0.0/8)
+    #[arg(long)]
+    exclude: Option<String>,
+
+    /// Exclude the hosts or networks in this file (one per line)
+    #[arg(long)]
+    exclude_file: Option<String>,
+
+    /// Send the arp discovery probes (hd -m) from this source mac, such as 00:11:22:33:44:55 (on-link IPv4 targets only)
+    #[arg(long)]
+    source_mac: Option<String>,
+
+    /// Skip the octet ranges (such as 10.0-255.0-255.1) that expand to more hosts than this
+    #[arg(long, default_value_t = DEFAULT_MAX_HOSTS)]
+    max_hosts: usize,
+
+    /// Max number of the dns lookups running at the same time when resolving the domain targets
+    #[arg(long, default_value_t = DEFAULT_DNS_CONCURRENCY)]
+    dns_concurrency: usize,
+
+    /// Include the network (.0) and broadcast (.255) addresses of the /24 and larger subnets, the smaller subnets always include them
+    #[arg(long, action, default_value_t = false)]
+    include_network_broadcast: bool,
+
+    /// Use these dns servers instead of the system resolver to resolve the domain targets (e.g. 10.0.0.53,192.168.1.1:5353)
+    #[arg(long)]
+    dns_servers: Option<String>,
+
+    /// Add the dns lookup time of the hostname targets to the displayed cost of their open ports (labeled incl. dns)
+    #[arg(long, action, default_value_t = false)]
+    timing_includes_dns: bool,
+
+    /// Look up the ptr name of each target, shown with the forward name of the hostname target when they differ
+    #[arg(short = 'R', long, action, default_value_t = false)]
+    reverse_dns: bool,
+
+    /// Scan with tcp_connect instead of the raw tcp scans when there is no root or CAP_NET_RAW
+    #[arg(long, action, default_value_t = false)]
+    auto_unprivileged: bool,
+
+    /// Treat all hosts as online and skip the host discovery stage of the combined workflow (same as nmap -Pn option)
+    #[arg(long, action, default_value_t = false)]
+    no_ping: bool,
+
+    /// Still scan the ports of the hosts that the discovery stage marked down (useful when ICMP is filtered but TCP is not)
+    #[arg(long, action, default_value_t = false)]
+    scan_down_hosts: bool,
+
+    /// Payload size (bytes) of the ICMP echo request used by the host discovery (IPv4 only), the packet must fit in the mtu
+    #[arg(long)]
+    icmp_payload_size: Option<usize>,
+
+    /// When to probe a port again, on-no-response only retries the ports that got no reply (such as filtered)
+    #[arg(long, value_enum, default_value_t = RetryPolicy::OnNoResponse)]
+    retry_policy: RetryPolicy,
+
+    /// Stop retransmitting a probe once the total time (ms) spent on it exceeds the budget, whatever the attempts left
+    #[arg(long, value_name = "MS")]
+    per_probe_budget: Option<u64>,
+
+    /// Use the named profile (method, ports, timeout and threads) from the profiles file, the command line options override it
+    #[arg(long)]
+    profile: Option<String>,
+
+    /// The profiles file used by --profile
+    #[arg(long, default_value = DEFAULT_PROFILES_FILE)]
+    profile_file: String,
+
+    /// The port scanning method from the profile, used when no method is set on the command line
+    #[arg(skip)]
+    profile_method: Option<String>,
+
+    /// Read the targets, ports, method and timing from this json or yaml job file (they override the command line)
+    #[arg(long, conflicts_with_all = ["target", "filename"])]
+    input: Option<String>,
+
+    /// Save the expanded targets (address, ports and origin) to the file, reuse them with --load-targets
+    #[arg(long)]
+    save_targets: Option<String>,
+
+    /// Load the targets saved by --save-targets instead of expanding the target inputs again
+    #[arg(long, conflicts_with_all = ["target", "filename", "input"])]
+    load_targets: Option<String>,
+
+    /// The port scanning method from the job file, it overrides the method of the command line
+    #[arg(skip)]
+    job_method: Option<String>,
+
+    /// Output format (text, json and jsonl), jsonl prints one json object per line as soon as each result is ready
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Text)]
+    output_format: OutputFormat,
+
+    /// Run the same scan n times (0 means until killed), the text output shows the ports opened or closed since the previous round
+    #[arg(long)]
+    repeat: Option<usize>,
+
+    /// Seconds to wait between the --repeat rounds
+    #[arg(long, default_value_t = 60.0)]
+    interval: f64,
+
+    /// After the last --repeat round, print each port ever seen open with the count of its open/not open flaps
+    #[arg(long, action, default_value_t = false, requires = "repeat")]
+    union_summary: bool,
+
+    /// Print the pistol result objects to stderr before the formatted output (troubleshooting)
+    #[arg(long, action, default_value_t = false, hide = true)]
+    raw_debug: bool,
+
+    /// Also send each result (address, port, status and method) to the local syslog with the facility
+    #[arg(long, value_enum, value_name = "FACILITY")]
+    syslog: Option<Facility>,
+
+    /// Append the custom string to the probes (--scanflags and --badsum only, pistol scans do not take a payload)
+    #[arg(long, conflicts_with = "data_length")]
+    data_string: Option<String>,
+
+    /// Append the random bytes to the probes (--scanflags and --badsum only, pistol scans do not take a payload)
+    #[arg(long, value_name = "BYTES")]
+    data_length: Option<usize>,
+
+    /// Cycle the source port of each probe in the range, such as 40000-40999 (--scanflags, --badsum and the fingerprint probes only, pistol scans pick their own ports)
+    #[arg(long, value_name = "LO-HI")]
+    source_port_range: Option<String>,
+
+    /// Append the estimated packets and bytes sent and received to the summary tail (port scanning only)
+    #[arg(long, action, default_value_t = false)]
+    stats: bool,
+
+    /// Send the udp probes n ports per host at a time, the batches are spaced (and slowed down when the icmp responses cease) to stay under the icmp rate limit of the targets
+    #[arg(long)]
+    udp_batch: Option<usize>,
+
+    /// Print only the result lines, without the starting banner, the scan header and the summary tail
+    #[arg(short, long, action, default_value_t = false)]
+    quiet: bool,
+
+    /// Print the plan of the scan (such as the hosts, ports and estimated probes) before scanning
+    #[arg(long, action, default_value_t = false)]
+    explain: bool,
+
+    /// Only print the targets (and the plan with --explain) without scanning
+    #[arg(long, action, default_value_t = false)]
+    list: bool,
+
+    /// Also write the open port lines to the file, the stdout still gets all the results
+    #[arg(long, value_name = "PATH")]
+    open_file: Option<String>,
+
+    /// Send the results to the syslog only, not stdout
+    #[arg(long, action, default_value_t = false, requires = "syslog")]
+    syslog_only: bool,
 }
 
+const DEFAULT_TIMEOUT: f64 = 1.0;
+const MAC_DEFAULT_TIMEOUT: f64 = 0.5;
+const OD_DEFAULT_TIMEOUT: f64 = 3.0;
+
+/// The capability bit of opening the raw sockets.
+const CAP_NET_RAW: u32 = 13;
+const UNPRIVILEGED_GUIDANCE: &str =
+    "run as root or grant CAP_NET_RAW, or use --connect for an unprivileged TCP scan";
+
 static IPV6_FIRST: LazyLock<Arc<Mutex<bool>>> = LazyLock::new(|| Arc::new(Mutex::new(false)));
+/// The source address used by all the probes, it is set when scanning the link-local targets with a zone.
+static SRC_ADDR: LazyLock<Arc<Mutex<Option<IpAddr>>>> =
+    LazyLock::new(|| Arc::new(Mutex::new(None)));
+/// The payload of the raw probes built by pslmap (--data-string or --data-length).
+static PROBE_DATA: LazyLock<Arc<Mutex<Vec<u8>>>> =
+    LazyLock::new(|| Arc::new(Mutex::new(Vec::new())));
+/// The source ports of the raw probes built by pslmap (--source-port-range).
+static SOURCE_PORTS: LazyLock<Arc<Mutex<SourcePortCycle>>> =
+    LazyLock::new(|| Arc::new(Mutex::new(SourcePortCycle::default())));
+/// The spoofed source mac of the arp discovery (--source-mac).
+static SRC_MAC: LazyLock<Arc<Mutex<Option<MacAddr>>>> =
+    LazyLock::new(|| Arc::new(Mutex::new(None)));
 
-struct InfoShow;
+/// The starting banner and the summary tail around the results, the quiet mode only writes the results.
+#[derive(Debug, Clone, Copy, Default)]
+struct InfoShow {
+    quiet: bool,
+}
 
 impl InfoShow {
-    fn print(info: &str, tail: &str) {
+    fn new(quiet: bool) -> InfoShow {
+        InfoShow { quiet }
+    }
+    fn write<W: Write>(&self, w: &mut W, info: &str, tail: &str) {
+        if self.quiet {
+            if !info.is_empty() {
+                writeln!(w, "{}", info).expect("write info failed");
+            }
+            return;
+        }
         let app = env!("CARGO_PKG_NAME");
         let version = env!("CARGO_PKG_VERSION");
         let now: DateTime<Local> = Local::now();
         let formatted_time = now.format("%Y-%m-%d %H:%M:%S").to_string();
-        println!("starting {} {} at {}", app, version, formatted_time,);
-        println!("{}", info);
-        println!("{}", tail);
+        writeln!(w, "starting {} {} at {}", app, version, formatted_time,)
+            .expect("write info failed");
+        writeln!(w, "{}", info).expect("write info failed");
+        writeln!(w, "{}", tail).expect("write info failed");
     }
 }
 
@@ -176,132 +617,1435 @@ fn log_level_parser(log: &str) -> PistolLogger {
     }
 }
 
-fn main() {
-    let args = Args::parse();
-    let mut targets = Vec::new();
-
-    if args.ipv6 {
-        let mut ipv6_first = IPV6_FIRST.lock().expect("try lock IPV6_FIRST failed");
-        (*ipv6_first) = true;
-    } else if args.ipv4 {
-        let mut ipv6_first = IPV6_FIRST.lock().expect("try lock IPV6_FIRST failed");
-        (*ipv6_first) = false;
+/// The port scan (but the ip protocol scan, which scans all the protocols by default)
+/// and the os detection without the given tcp ports scan the ports of the targets.
+fn needs_ports(tools: &ToolsSubcommand, args: &Args) -> bool {
+    let method_ip_proto = [&args.job_method, &args.profile_method]
+        .into_iter()
+        .flatten()
+        .any(|m| m.eq_ignore_ascii_case("ipproto"));
+    match tools {
+        ToolsSubcommand::PS { ip_proto, .. } => !(*ip_proto || method_ip_proto),
+        ToolsSubcommand::OD { open_tcp_port, .. } => open_tcp_port.is_none(),
+        _ => false,
     }
+}
 
-    let ports = args.ports;
-    let target = args.target;
-    let filename = args.filename;
-    if let Some(target) = target {
-        let t = TargetParser::target_from_input(&target, ports);
-        targets.extend(t);
-    } else if let Some(filename) = filename {
-        let t = TargetParser::target_from_file(&filename, ports);
-        targets.extend(t);
+/// Ports from the --fast or --top-ports presets, explicit --ports conflicts with both.
+fn ports_preset(args: &Args) -> Option<Vec<u16>> {
+    if args.fast {
+        Some(top_ports(FAST_PORTS_NUM))
     } else {
-        panic!("please set target first");
+        args.top_ports.map(top_ports)
     }
+}
 
-    if targets.len() == 0 {
-        panic!("unable to parse the target");
-    }
+fn ps_method_parser(method: &str) -> Result<PortScanningMethod, String> {
+    let ps_method = match method.to_lowercase().as_str() {
+        "syn" => PortScanningMethod::TcpSyn,
+        "connect" => PortScanningMethod::TcpConnect,
+        "fin" => PortScanningMethod::TcpFin,
+        "null" => PortScanningMethod::TcpNull,
+        "xmas" => PortScanningMethod::TcpXmas,
+        "ack" => PortScanningMethod::TcpAck,
+        "window" => PortScanningMethod::TcpWindow,
+        "maimon" => PortScanningMethod::TcpMaimon,
+        "udp" => PortScanningMethod::Udp,
+        "sctp" => PortScanningMethod::SctpInit,
+        "ipproto" => PortScanningMethod::IpProto,
+        _ => return Err(format!("unknown port scanning method {}", method)),
+    };
+    Ok(ps_method)
+}
 
-    let timeout = args.timeout;
-    let log_level = log_level_parser(&args.log);
-    let num_threads = args.num_threads;
+/// The seconds of the timeout such as `500ms`, `2s` or `1m`, the bare number is seconds.
+fn timeout_parser(timeout_str: &str) -> Result<f64, String> {
+    let s = timeout_str.trim();
+    let (num, unit) = if let Some(num) = s.strip_suffix("ms") {
+        (num, 0.001)
+    } else if let Some(num) = s.strip_suffix("s") {
+        (num, 1.0)
+    } else if let Some(num) = s.strip_suffix("m") {
+        (num, 60.0)
+    } else {
+        (s, 1.0)
+    };
+    match num.trim().parse::<f64>() {
+        Ok(num) if num >= 0.0 && num.is_finite() => Ok(num * unit),
+        _ => Err(format!(
+            "invalid timeout {}: use the seconds or the ms, s and m suffixes (such as 500ms)",
+            timeout_str
+        )),
+    }
+}
 
-    match args.tools {
+/// The ARP/NDP replies come from the local network, while the OS probes wait for the slow hosts.
+fn default_timeout(tools: &ToolsSubcommand) -> f64 {
+    match tools {
         ToolsSubcommand::HD {
             ping1,
             ping2,
             ping3,
-            mac,
             syn,
             ack,
             udp,
-        } => {
-            let hd_method = if ping1 {
-                HostDiscoveryMethod::IcmpEcho
-            } else if ping2 {
-                HostDiscoveryMethod::IcmpTimestamp
-            } else if ping3 {
-                HostDiscoveryMethod::IcmpAddressMask
-            } else if syn {
-                HostDiscoveryMethod::TcpSyn
-            } else if ack {
-                HostDiscoveryMethod::TcpAck
-            } else if udp {
-                HostDiscoveryMethod::Udp
-            } else if mac {
-                HostDiscoveryMethod::Mac
+            ..
+        } if !(*ping1 || *ping2 || *ping3 || *syn || *ack || *udp) => MAC_DEFAULT_TIMEOUT,
+        ToolsSubcommand::OD { .. } => OD_DEFAULT_TIMEOUT,
+        _ => DEFAULT_TIMEOUT,
+    }
+}
+
+/// The adaptive timeout is only used when any of the rtt timeouts is set.
+fn rtt_timeout_parser(args: &Args, timeout: f64) -> Result<Option<RttTimeout>, String> {
+    if args.min_rtt_timeout.is_none()
+        && args.max_rtt_timeout.is_none()
+        && args.initial_rtt_timeout.is_none()
+    {
+        return Ok(None);
+    }
+    let min = args.min_rtt_timeout.unwrap_or(DEFAULT_MIN_RTT_TIMEOUT);
+    let max = args.max_rtt_timeout.unwrap_or(DEFAULT_MAX_RTT_TIMEOUT);
+    let initial = args.initial_rtt_timeout.unwrap_or(timeout);
+    RttTimeout::new(initial, min, max).map(Some)
+}
+
+/// The (hosts, probes) parallelism, --nt sets both and the --max-*-parallelism options override it,
+/// the probes are never below --min-parallelism.
+fn parallelism_parser(args: &Args) -> Result<(usize, usize), String> {
+    let hosts = args.max_host_parallelism.unwrap_or(args.num_threads);
+    let probes = args.max_probe_parallelism.unwrap_or(args.num_threads);
+    if hosts == 0 || probes == 0 {
+        return Err(String::from(
+            "invalid parallelism: the hosts and the probes must be greater than 0",
+        ));
+    }
+    let min = args.min_parallelism.unwrap_or(0);
+    if let Some(max) = args.max_probe_parallelism
+        && min > max
+    {
+        return Err(format!(
+            "invalid parallelism: --min-parallelism {} is greater than --max-probe-parallelism {}",
+            min, max
+        ));
+    }
+    Ok((hosts, probes.max(min)))
+}
+
+/// Fill the options that are not set on the command line from the profile.
+fn apply_profile(args: &mut Args, matches: &ArgMatches, profile: &Profile) {
+    let from_cli = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+    if args.ports.is_none() && args.top_ports.is_none() && !args.fast {
+        args.ports = profile.ports.clone();
+    }
+    if !from_cli("timeout")
+        && let Some(timeout) = profile.timeout
+    {
+        args.timeout = Some(timeout);
+    }
+    if !from_cli("num_threads")
+        && let Some(threads) = profile.threads
+    {
+        args.num_threads = threads;
+    }
+    args.profile_method = profile.method.clone();
+}
+
+/// The job file overrides the command line options.
+fn apply_job(args: &mut Args, job: &Job) {
+    if let Some(timeout) = job.timeout {
+        args.timeout = Some(timeout);
+    }
+    if let Some(threads) = job.threads {
+        args.num_threads = threads;
+    }
+    args.job_method = job.method.clone();
+}
+
+/// The scan goes on when any target is left, the skipped inputs are only counted in the warning,
+/// the problem of the sole failed input (such as the hostname without the addresses of -4/-6) is the error.
+fn targets_summary(parsed: usize, warnings: &[String]) -> Result<Option<String>, String> {
+    let failed = warnings.len();
+    if parsed == 0 && failed == 1 {
+        return Err(warnings[0].clone());
+    }
+    if parsed == 0 {
+        return Err(format!(
+            "unable to parse the target ({} inputs failed)",
+            failed
+        ));
+    }
+    if failed > 0 {
+        return Ok(Some(format!(
+            "{} target inputs failed, scanning the {} parsed targets",
+            failed, parsed
+        )));
+    }
+    Ok(None)
+}
+
+/// Keep the targets of one address family, however they were produced (literal, network or hostname).
+fn family_filter(targets: &mut Vec<Target>, only_ipv4: bool, only_ipv6: bool) {
+    if only_ipv4 {
+        targets.retain(|t| t.addr.is_ipv4());
+    } else if only_ipv6 {
+        targets.retain(|t| t.addr.is_ipv6());
+    }
+}
+
+/// The private addresses of RFC 1918 and the ULA (fc00::/7), the loopback and link-local addresses
+/// never leave the local network either.
+fn is_private(addr: IpAddr) -> bool {
+    match addr {
+        IpAddr::V4(ipv4) => ipv4.is_private() || ipv4.is_loopback() || ipv4.is_link_local(),
+        IpAddr::V6(ipv6) => {
+            ipv6.is_unique_local() || ipv6.is_loopback() || ipv6.is_unicast_link_local()
+        }
+    }
+}
+
+/// Returns the error listing the public targets (--private-only).
+fn private_only_check(targets: &[Target]) -> Result<(), String> {
+    let public: Vec<String> = targets
+        .iter()
+        .filter(|t| !is_private(t.addr))
+        .map(|t| t.addr.to_string())
+        .collect();
+    if !public.is_empty() {
+        Err(format!(
+            "--private-only refuses the public targets [{}]",
+            public.join(", ")
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Check the route of the first target of each address family before scanning,
+/// so a missing route fails up front instead of deep in the pistol stack.
+fn route_check<F: Fn(IpAddr) -> bool>(targets: &[Target], has_route: F) -> Result<(), String> {
+    for is_ipv6 in [false, true] {
+        if let Some(t) = targets.iter().find(|t| t.addr.is_ipv6() == is_ipv6)
+            && !has_route(t.addr)
+        {
+            let family = if is_ipv6 { "IPv6" } else { "IPv4" };
+            return Err(format!(
+                "no {} route available, cannot scan {}",
+                family, t.addr
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// The raw tcp probes to the loopback behave oddly on some systems while the connect scan works,
+/// so the raw tcp methods are overridden when all the targets are loopback (and warned when only some are).
+/// The badsum probes are kept since the connect scan can not send them.
+fn raw_tcp(ps_method: PortScanningMethod) -> bool {
+    matches!(
+        ps_method,
+        PortScanningMethod::TcpSyn
+            | PortScanningMethod::TcpFin
+            | PortScanningMethod::TcpNull
+            | PortScanningMethod::TcpXmas
+            | PortScanningMethod::TcpAck
+            | PortScanningMethod::TcpWindow
+            | PortScanningMethod::TcpMaimon
+            | PortScanningMethod::TcpCustom(_)
+    )
+}
+
+fn loopback_override(
+    targets: &[Target],
+    ps_method: PortScanningMethod,
+    badsum: bool,
+) -> (PortScanningMethod, Option<String>) {
+    let raw_tcp = raw_tcp(ps_method);
+    let loopback: Vec<String> = targets
+        .iter()
+        .filter(|t| t.addr.is_loopback())
+        .map(|t| t.addr.to_string())
+        .collect();
+    if !raw_tcp || badsum || loopback.is_empty() {
+        (ps_method, None)
+    } else if loopback.len() == targets.len() {
+        let warning = format!(
+            "the loopback targets [{}] are scanned with tcp_connect instead of {}",
+            loopback.join(", "),
+            ps_method.name()
+        );
+        (PortScanningMethod::TcpConnect, Some(warning))
+    } else {
+        let warning = format!(
+            "the {} results of the loopback targets [{}] may be wrong, scan them alone to use tcp_connect",
+            ps_method.name(),
+            loopback.join(", ")
+        );
+        (ps_method, Some(warning))
+    }
+}
+
+/// Whether the raw sockets can be opened, from the `CapEff` line of /proc/self/status
+/// (root has all the capabilities), None when it can not be read.
+fn raw_privileged(status: &str) -> Option<bool> {
+    let cap_eff = status.lines().find_map(|l| l.strip_prefix("CapEff:"))?;
+    let caps = u64::from_str_radix(cap_eff.trim(), 16).ok()?;
+    Some(caps & (1 << CAP_NET_RAW) != 0)
+}
+
+/// The check is skipped (the scan goes on) when the capabilities are unknown, such as not on Linux.
+fn raw_privileged_now() -> bool {
+    fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| raw_privileged(&status))
+        .unwrap_or(true)
+}
+
+fn unprivileged_error(what: &str) -> String {
+    format!("{} needs the raw sockets: {}", what, UNPRIVILEGED_GUIDANCE)
+}
+
+/// Without the raw sockets only tcp_connect works,
+/// the raw tcp scans are changed to tcp_connect by --auto-unprivileged.
+fn unprivileged_override(
+    ps_method: PortScanningMethod,
+    badsum: bool,
+    auto_unprivileged: bool,
+) -> Result<(PortScanningMethod, Option<String>), String> {
+    if ps_method == PortScanningMethod::TcpConnect {
+        Ok((ps_method, None))
+    } else if auto_unprivileged && raw_tcp(ps_method) && !badsum {
+        let warning = format!(
+            "no root or CAP_NET_RAW, the targets are scanned with tcp_connect instead of {}",
+            ps_method.name()
+        );
+        Ok((PortScanningMethod::TcpConnect, Some(warning)))
+    } else {
+        Err(unprivileged_error(ps_method.name()))
+    }
+}
+
+/// The method flags of PS are exclusive, the if/else chain would silently drop all but the first one.
+fn scan_method_conflict(flags: &[(&str, bool)]) -> Result<(), String> {
+    let names: Vec<&str> = flags
+        .iter()
+        .filter(|(_, set)| *set)
+        .map(|(name, _)| *name)
+        .collect();
+    if names.len() > 1 {
+        Err(format!(
+            "multiple scan methods specified: {} — choose one",
+            names.join(", ")
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+fn target_parser(args: &Args) -> Result<TargetParser, String> {
+    let parser = TargetParser::new()
+        .with_max_hosts(args.max_hosts)
+        .with_dns_concurrency(args.dns_concurrency)
+        .with_network_broadcast(args.include_network_broadcast)
+        .with_input_format(args.input_format, &args.csv_column);
+    match &args.dns_servers {
+        Some(dns_servers) => {
+            let dns_servers = dns_servers_parser(dns_servers)
+                .map_err(|e| format!("parse dns servers {} failed: {}", dns_servers, e))?;
+            Ok(parser.with_dns_servers(dns_servers))
+        }
+        None => Ok(parser),
+    }
+}
+
+/// One scan of the command line (or of one prompt line in the interactive mode),
+/// the globals set by the previous scan are reset first.
+fn run(mut args: Args, matches: &ArgMatches) -> Result<(), PslmapError> {
+    let tools = args
+        .tools
+        .clone()
+        .ok_or("please set the subcommand (hd, ps, od or methods)")?;
+    if let ToolsSubcommand::Methods = tools {
+        for line in method_lines() {
+            println!("{}", line);
+        }
+        return Ok(());
+    }
+    if let Some(name) = &args.profile {
+        let profile = load_profile(&args.profile_file, name)
+            .map_err(|e| format!("load profile failed: {}", e))?;
+        apply_profile(&mut args, matches, &profile);
+    }
+    let job = match &args.input {
+        Some(input) => Some(load_job(input).map_err(|e| format!("load job failed: {}", e))?),
+        None => None,
+    };
+    if let Some(job) = &job {
+        apply_job(&mut args, job);
+    }
+    let mut targets = Vec::new();
+
+    // --ipv4 is the default
+    *IPV6_FIRST.lock().expect("try lock IPV6_FIRST failed") = args.ipv6;
+    *SRC_ADDR.lock().expect("try lock SRC_ADDR failed") = None;
+    *SRC_MAC.lock().expect("try lock SRC_MAC failed") = None;
+
+    let preset = ports_preset(&args);
+    let needs_ports = needs_ports(&tools, &args);
+    // the method default when neither the command line, the profile nor the job sets it
+    let timeout = args.timeout.unwrap_or(default_timeout(&tools));
+    let rtt_timeout = rtt_timeout_parser(&args, timeout)?;
+    let (host_parallelism, num_threads) = parallelism_parser(&args)?;
+    let mut parser = target_parser(&args)?;
+    let ports = args.ports;
+    // the bad global ports stop the run instead of dropping every target
+    TargetParser::ports_parser(ports.clone()).map_err(|e| format!("invalid ports: {}", e))?;
+    let target = args.target;
+    let filename = args.filename;
+    if let Some(load) = &args.load_targets {
+        let t = load_targets(load).map_err(|e| format!("load targets failed: {}", e))?;
+        targets.extend(t);
+    } else if let Some(job) = &job {
+        let t = job.targets(&mut parser, ports);
+        targets.extend(t);
+    } else if target.is_some() || filename.is_some() {
+        let t = parser.target_from_sources(target.as_deref(), filename.as_deref(), ports);
+        targets.extend(t);
+    } else {
+        return Err("please set target first".into());
+    }
+    for warning in &parser.warnings {
+        eprintln!("warning: {}", warning);
+    }
+
+    let mut excludes = ExcludeTable::new();
+    if let Some(exclude) = &args.exclude {
+        excludes
+            .insert_list(exclude)
+            .map_err(|e| format!("parse exclude failed: {}", e))?;
+    }
+    if let Some(exclude_file) = &args.exclude_file {
+        excludes
+            .insert_file(exclude_file)
+            .map_err(|e| format!("parse exclude file failed: {}", e))?;
+    }
+    if excludes.len() > 0 {
+        targets.retain(|t| !excludes.contains(t.addr));
+    }
+    family_filter(&mut targets, args.only_ipv4, args.only_ipv6);
+
+    targets = parser.dedup(targets);
+    let dns_times = if args.timing_includes_dns {
+        parser.dns_times.clone()
+    } else {
+        HashMap::new()
+    };
+    for (addr, origins) in &parser.origins {
+        eprintln!("note: {} [{}] is scanned once", addr, origins.join(", "));
+    }
+    if args.private_only {
+        private_only_check(&targets)?;
+    }
+
+    match targets_summary(targets.len(), &parser.warnings) {
+        Ok(Some(warning)) => eprintln!("warning: {}", warning),
+        Ok(None) => (),
+        Err(e) => return Err(e.into()),
+    }
+    if args.reverse_dns {
+        let servers = match &args.dns_servers {
+            Some(dns_servers) => dns_servers_parser(dns_servers)?,
+            None => system_dns_servers(),
+        };
+        if servers.is_empty() {
+            eprintln!("warning: no dns server for the reverse lookups, use --dns-servers");
+        }
+        let addrs: Vec<IpAddr> = targets.iter().map(|t| t.addr).collect();
+        let names = reverse_lookup(&addrs, &servers, args.dns_concurrency);
+        for (t, ptr) in targets.iter().zip(names) {
+            if let Some(line) = names_line(t.addr, t.origin.as_deref(), ptr.as_deref())
+                && args.output_format == OutputFormat::Text
+            {
+                println!("{}", line);
+            }
+        }
+    }
+    if let Some(save) = &args.save_targets {
+        save_targets(save, &targets).map_err(|e| format!("save targets failed: {}", e))?;
+    }
+
+    if !parser.zones.is_empty() {
+        // all the probes are sent from the zone's interface
+        let zones: Vec<&String> = parser.zones.values().collect();
+        if zones.iter().any(|z| *z != zones[0]) || parser.zones.len() != targets.len() {
+            return Err(
+                "the link-local targets of different zones can not be mixed with other targets"
+                    .into(),
+            );
+        }
+        let src_addr = zone_src_addr(zones[0])
+            .map_err(|e| format!("resolve the zone {} failed: {}", zones[0], e))?;
+        let mut src = SRC_ADDR.lock().expect("try lock SRC_ADDR failed");
+        (*src) = Some(src_addr);
+    }
+
+    if let Some(source_mac) = &args.source_mac {
+        let mac = mac_parser(source_mac).map_err(|e| format!("parse source mac failed: {}", e))?;
+        let src_addr = *SRC_ADDR.lock().expect("lock SRC_ADDR failed");
+        source_mac_check(&targets, src_addr)
+            .map_err(|e| format!("check source mac failed: {}", e))?;
+        let mut src_mac = SRC_MAC.lock().expect("try lock SRC_MAC failed");
+        (*src_mac) = Some(mac);
+    }
+
+    if let Some(preset) = preset {
+        // keep the ports that come from the target file lines
+        for t in &mut targets {
+            if t.ports.is_empty() {
+                t.ports = preset.clone();
+            }
+        }
+    }
+    if needs_ports {
+        empty_ports_fill(&mut targets, args.empty_ports)?;
+    }
+
+    if args.randomize_ports {
+        shuffle_ports(&mut targets, args.randomize_seed);
+    }
+
+    let output_format = args.output_format;
+    let no_ping = args.no_ping;
+    let scan_down_hosts = args.scan_down_hosts;
+    set_raw_debug(args.raw_debug);
+    set_quiet(args.quiet);
+    set_stats(args.stats);
+    set_udp_batch(args.udp_batch);
+    let data = probe_data(args.data_string.as_deref(), args.data_length)?;
+    let with_data = !data.is_empty();
+    *PROBE_DATA.lock().expect("try lock PROBE_DATA failed") = data;
+    let source_ports = match &args.source_port_range {
+        Some(range_str) => {
+            let (lo, hi) = source_port_range_parser(range_str)?;
+            SourcePortCycle::new(lo, hi)
+        }
+        None => SourcePortCycle::default(),
+    };
+    *SOURCE_PORTS.lock().expect("try lock SOURCE_PORTS failed") = source_ports;
+    let open_sink = match &args.open_file {
+        Some(open_file) => Some(
+            OpenSink::create(open_file).map_err(|e| format!("create open file failed: {}", e))?,
+        ),
+        None => None,
+    };
+    set_open_sink(open_sink);
+    set_syslog(None);
+    if let Some(facility) = args.syslog {
+        match Syslog::connect(facility, args.syslog_only) {
+            Ok(syslog) => set_syslog(Some(syslog)),
+            Err(e) => eprintln!(
+                "warning: syslog is unavailable ({}), the results only go to stdout",
+                e
+            ),
+        }
+    }
+    let retry_policy = args.retry_policy;
+    let probe_budget = args.per_probe_budget.map(Duration::from_millis);
+    let icmp_payload_size = args.icmp_payload_size;
+    let verbose = args.verbose;
+    let profile_method = args.profile_method;
+    let job_method = args.job_method;
+    if let Some(size) = icmp_payload_size {
+        payload_size_check(size).map_err(|e| format!("invalid icmp payload size: {}", e))?;
+    }
+
+    let format_error = match output_format {
+        OutputFormat::Matrix if !matches!(tools, ToolsSubcommand::PS { .. }) => {
+            Some("--output-format matrix only works with port scanning")
+        }
+        OutputFormat::Table
+            if !matches!(
+                tools,
+                ToolsSubcommand::HD {
+                    traceroute: false,
+                    ..
+                }
+            ) =>
+        {
+            Some("--output-format table only works with host discovery (without --traceroute)")
+        }
+        _ => None,
+    };
+    if let Some(e) = format_error {
+        return Err(e.into());
+    }
+    if let ToolsSubcommand::PS {
+        syn,
+        connect,
+        fin,
+        null,
+        xmas,
+        ack,
+        window,
+        maimon,
+        scanflags,
+        udp,
+        sctp,
+        ip_proto,
+        idle,
+        ..
+    } = &tools
+    {
+        let flags = [
+            ("syn", *syn),
+            ("connect", *connect),
+            ("fin", *fin),
+            ("null", *null),
+            ("xmas", *xmas),
+            ("ack", *ack),
+            ("window", *window),
+            ("maimon", *maimon),
+            ("scanflags", scanflags.is_some()),
+            ("idle", idle.is_some()),
+            ("udp", *udp),
+            ("sctp", *sctp),
+            ("ip-proto", *ip_proto),
+        ];
+        scan_method_conflict(&flags)?;
+    }
+
+    let all_targets = targets;
+    let mut repeat = Repeat::new(args.repeat.unwrap_or(1), args.interval)
+        .map_err(|e| format!("invalid repeat: {}", e))?;
+    let mut prev_status = None;
+    let mut union_summary = UnionSummary::new();
+    let privileged = raw_privileged_now();
+    if args.list {
+        for t in &all_targets {
+            let ports: Vec<String> = t.ports.iter().map(|p| p.to_string()).collect();
+            if !ports.is_empty() {
+                println!("{} {}", t.addr, ports.join(","));
             } else {
-                HostDiscoveryMethod::Mac
-            };
-            host_discovery(&targets, hd_method, log_level, timeout, num_threads);
+                println!("{}", t.addr);
+            }
         }
-        ToolsSubcommand::PS {
-            syn,
-            connect,
-            fin,
-            null,
-            xmas,
-            ack,
-            window,
-            maimon,
-            udp,
-            idle,
-        } => {
-            let (ps_method, zombie_ipv4, zombie_port) = if syn {
-                (PortScanningMethod::TcpSyn, None, None)
-            } else if connect {
-                (PortScanningMethod::TcpConnect, None, None)
-            } else if fin {
-                (PortScanningMethod::TcpFin, None, None)
-            } else if null {
-                (PortScanningMethod::TcpNull, None, None)
-            } else if xmas {
-                (PortScanningMethod::TcpXmas, None, None)
-            } else if ack {
-                (PortScanningMethod::TcpAck, None, None)
-            } else if window {
-                (PortScanningMethod::TcpWindow, None, None)
-            } else if maimon {
-                (PortScanningMethod::TcpMaimon, None, None)
-            } else if let Some(idle) = idle {
-                match idle {
-                    IdleSubcommand::IDLE {
+        if !args.explain {
+            return Ok(());
+        }
+    } else {
+        // the plan-only runs (--list) never send a packet
+        let src_addr = *SRC_ADDR.lock().expect("lock SRC_ADDR failed");
+        let has_route = |addr| matches!(infer_addr(addr, src_addr), Ok(Some(_)));
+        route_check(&all_targets, has_route)?;
+    }
+    // the plan is printed once, --list stops before scanning
+    let mut explained = false;
+    let mut explain = |plan: String| -> bool {
+        if args.explain && !explained {
+            println!("{}", plan);
+            explained = true;
+        }
+        args.list
+    };
+    while repeat.next_round() {
+        let mut targets = all_targets.clone();
+        if args.repeat.is_some() && output_format == OutputFormat::Text {
+            let now: DateTime<Local> = Local::now();
+            println!("{}", repeat.round_line(&now.to_rfc3339()));
+        }
+        let ret = match tools.clone() {
+            ToolsSubcommand::HD {
+                ping1,
+                ping2,
+                ping3,
+                mac,
+                ndp,
+                syn,
+                ack,
+                udp,
+                traceroute: trace,
+                up_threshold,
+                discovery_ports,
+                group_vendor,
+                vendor_histogram,
+            } => {
+                let ping_methods: Vec<HostDiscoveryMethod> = [
+                    (ping1, HostDiscoveryMethod::IcmpEcho),
+                    (ping2, HostDiscoveryMethod::IcmpTimestamp),
+                    (ping3, HostDiscoveryMethod::IcmpAddressMask),
+                    (syn, HostDiscoveryMethod::TcpSyn),
+                    (ack, HostDiscoveryMethod::TcpAck),
+                    (udp, HostDiscoveryMethod::Udp),
+                ]
+                .into_iter()
+                .filter_map(|(set, method)| set.then_some(method))
+                .collect();
+                let hd_methods = if !ping_methods.is_empty() {
+                    ping_methods
+                } else if mac {
+                    vec![HostDiscoveryMethod::Mac]
+                } else if ndp {
+                    vec![HostDiscoveryMethod::Ndp]
+                } else {
+                    vec![HostDiscoveryMethod::Mac]
+                };
+                let up_threshold = up_threshold_check(up_threshold, hd_methods.len())?;
+                if explain(discovery_plan(&targets, &hd_methods, timeout)) {
+                    return Ok(());
+                }
+                if !privileged {
+                    return Err(unprivileged_error("host discovery").into());
+                }
+                let method_names: Vec<String> =
+                    hd_methods.iter().map(|m| format!("{:?}", m)).collect();
+                set_header(ScanHeader::new(
+                    &targets,
+                    &method_names.join("+"),
+                    timeout,
+                    num_threads,
+                ));
+                let options = HostDiscoveryOptions {
+                    up_threshold,
+                    timeout,
+                    num_threads,
+                    icmp_payload_size,
+                    discovery_ports: TargetParser::ports_parser(discovery_ports)
+                        .map_err(|e| format!("invalid discovery ports: {}", e))?,
+                    group_vendor,
+                    vendor_histogram,
+                    verbose,
+                    output_format,
+                };
+                let ret = host_discovery(&targets, &hd_methods, &options);
+                if trace && let Ok(outcome) = &ret {
+                    let up_targets: Vec<_> = targets
+                        .into_iter()
+                        .filter(|t| outcome.up_addrs.contains(&t.addr))
+                        .collect();
+                    traceroute(&up_targets, timeout, output_format);
+                }
+                ret
+            }
+            ToolsSubcommand::PS {
+                syn,
+                connect,
+                fin,
+                null,
+                xmas,
+                ack,
+                window,
+                maimon,
+                scanflags,
+                udp,
+                sctp,
+                ip_proto,
+                discover,
+                first_open,
+                closed_summary,
+                per_host_summary,
+                reason,
+                badsum,
+                show_fingerprint,
+                service_names,
+                max_open_per_host,
+                samples,
+                idle,
+            } => {
+                let force_zombie = matches!(
+                    idle,
+                    Some(IdleSubcommand::IDLE {
+                        force_zombie: true,
+                        ..
+                    })
+                );
+                let (ps_method, zombie_ipv4, zombie_port) = if let Some(method) = &job_method {
+                    (ps_method_parser(method)?, None, None)
+                } else if syn {
+                    (PortScanningMethod::TcpSyn, None, None)
+                } else if connect {
+                    (PortScanningMethod::TcpConnect, None, None)
+                } else if fin {
+                    (PortScanningMethod::TcpFin, None, None)
+                } else if null {
+                    (PortScanningMethod::TcpNull, None, None)
+                } else if xmas {
+                    (PortScanningMethod::TcpXmas, None, None)
+                } else if ack {
+                    (PortScanningMethod::TcpAck, None, None)
+                } else if window {
+                    (PortScanningMethod::TcpWindow, None, None)
+                } else if maimon {
+                    (PortScanningMethod::TcpMaimon, None, None)
+                } else if let Some(flags) = scanflags {
+                    (PortScanningMethod::TcpCustom(flags), None, None)
+                } else if let Some(idle) = idle {
+                    match idle {
+                        IdleSubcommand::IDLE {
+                            zombie_ipv4,
+                            zombie_port,
+                            ..
+                        } => (
+                            PortScanningMethod::TcpIdle,
+                            Some(zombie_ipv4),
+                            Some(zombie_port),
+                        ),
+                    }
+                } else if udp {
+                    (PortScanningMethod::Udp, None, None)
+                } else if sctp {
+                    (PortScanningMethod::SctpInit, None, None)
+                } else if ip_proto {
+                    (PortScanningMethod::IpProto, None, None)
+                } else if let Some(method) = &profile_method {
+                    (ps_method_parser(method)?, None, None)
+                } else {
+                    (PortScanningMethod::TcpSyn, None, None)
+                };
+                let (ps_method, loopback_warning) = loopback_override(&targets, ps_method, badsum);
+                if let Some(warning) = loopback_warning {
+                    eprintln!("warning: {}", warning);
+                }
+                if !privileged && discover {
+                    return Err(unprivileged_error("--discover").into());
+                }
+                let ps_method = if privileged {
+                    ps_method
+                } else {
+                    let (ps_method, warning) =
+                        unprivileged_override(ps_method, badsum, args.auto_unprivileged)?;
+                    if let Some(warning) = warning {
+                        eprintln!("warning: {}", warning);
+                    }
+                    ps_method
+                };
+                if explain(scan_plan(&targets, ps_method, timeout)) {
+                    return Ok(());
+                }
+                if with_data && !data_supported(ps_method, badsum) {
+                    return Err(PslmapError::Unsupported(String::from(
+                        "--data-string and --data-length only work with --scanflags and --badsum",
+                    )));
+                }
+                if let (Some(zombie_ipv4), Some(zombie_port)) = (zombie_ipv4, zombie_port) {
+                    let class = ipid_class(&zombie_ipids(
                         zombie_ipv4,
                         zombie_port,
-                    } => (
-                        PortScanningMethod::TcpIdle,
-                        Some(zombie_ipv4),
-                        Some(zombie_port),
-                    ),
+                        Some(Duration::from_secs_f64(timeout)),
+                    ));
+                    if output_format == OutputFormat::Text {
+                        println!(
+                            "zombie {}:{} ip id sequence {:?}",
+                            zombie_ipv4, zombie_port, class
+                        );
+                    }
+                    if let Some(warning) = zombie_check(zombie_ipv4, class, force_zombie)? {
+                        eprintln!("warning: {}", warning);
+                    }
                 }
-            } else if udp {
-                (PortScanningMethod::Udp, None, None)
-            } else {
-                (PortScanningMethod::TcpSyn, None, None)
-            };
-            port_scanning(
-                &targets,
-                ps_method,
-                zombie_ipv4,
-                zombie_port,
-                log_level,
-                timeout,
-                num_threads,
-            );
+                set_header(ScanHeader::new(
+                    &targets,
+                    &format!("{:?}", ps_method),
+                    timeout,
+                    num_threads,
+                ));
+                if discover {
+                    match discovery_stage(
+                        &targets,
+                        HostDiscoveryMethod::IcmpEcho,
+                        no_ping,
+                        timeout,
+                        num_threads,
+                        icmp_payload_size,
+                    ) {
+                        Ok(discovered) => targets = scan_stage_targets(discovered, scan_down_hosts),
+                        Err(e) => return Err(e),
+                    }
+                }
+                let options = PortScanOptions {
+                    ps_method,
+                    zombie_ipv4,
+                    zombie_port,
+                    timeout,
+                    num_threads,
+                    host_parallelism,
+                    first_open,
+                    closed_summary,
+                    per_host_summary,
+                    reason,
+                    retry_policy,
+                    probe_budget,
+                    rtt_timeout,
+                    badsum,
+                    show_fingerprint,
+                    service_names,
+                    max_open_per_host,
+                    samples,
+                    dns_times: dns_times.clone(),
+                    verbose,
+                    output_format,
+                };
+                port_scanning(&targets, options)
+            }
+            ToolsSubcommand::OD {
+                top_k,
+                open_tcp_port,
+                close_tcp_port,
+                close_udp_port,
+                os_threads,
+                group_os,
+                alive_only,
+                os_short_timeout,
+            } => {
+                if explain(os_plan(&targets, top_k, timeout)) {
+                    return Ok(());
+                }
+                if !privileged {
+                    return Err(unprivileged_error("os detection").into());
+                }
+                match (open_tcp_port, close_tcp_port) {
+                    (Some(open_tcp_port), Some(close_tcp_port)) => {
+                        for t in &mut targets {
+                            t.ports = vec![open_tcp_port, close_tcp_port, close_udp_port];
+                        }
+                    }
+                    _ => match os_ports_targets(&targets, close_udp_port, timeout, num_threads) {
+                        Ok(os_targets) => targets = os_targets,
+                        Err(e) => return Err(e),
+                    },
+                }
+                let os_threads = os_threads_parser(host_parallelism, os_threads)?;
+                set_header(ScanHeader::new(&targets, "OsDetect", timeout, os_threads));
+                let options = OsDetectionOptions {
+                    top_k,
+                    timeout,
+                    short_timeout: os_short_timeout,
+                    num_threads: os_threads,
+                    alive_only,
+                    group_os,
+                    verbose,
+                    output_format,
+                };
+                os_detection(&targets, options)
+            }
+            // listed before the targets are parsed
+            ToolsSubcommand::Methods => unreachable!(),
+        };
+        match ret {
+            Ok(outcome) => {
+                if let Some(prev) = &prev_status
+                    && output_format == OutputFormat::Text
+                {
+                    for line in port_diff(prev, &outcome.port_status) {
+                        println!("diff: {}", line);
+                    }
+                }
+                union_summary.add_round(&outcome.port_status);
+                prev_status = Some(outcome.port_status);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    if args.union_summary && output_format == OutputFormat::Text {
+        for line in union_summary.lines() {
+            println!("union: {}", line);
         }
-        ToolsSubcommand::OD {
-            top_k,
-            open_tcp_port,
-            close_tcp_port,
-            close_udp_port,
-        } => {
-            for t in &mut targets {
-                t.ports = vec![open_tcp_port, close_tcp_port, close_udp_port];
+    }
+    Ok(())
+}
+
+fn main() {
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches).expect("parse args failed");
+    let log_level = log_level_parser(&args.log);
+    // let capture = Some(String::from("scan.pcapng"));
+    let capture = None;
+    let _pr = PistolRunner::init(log_level, capture, None).expect("init pistol runner failed");
+
+    if args.interactive {
+        let stdin = std::io::stdin();
+        repl(stdin.lock(), &mut std::io::stdout(), |argv| {
+            let matches = Args::command()
+                .try_get_matches_from(argv)
+                .map_err(|e| e.to_string())?;
+            let args = Args::from_arg_matches(&matches).map_err(|e| e.to_string())?;
+            // the invalid args (such as the wrong ports) only stop this scan
+            run(args, &matches).map_err(|e| e.to_string())
+        });
+    } else if let Err(e) = run(args, &matches) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ports::DEFAULT_PORTS;
+    #[test]
+    fn test_fast_ports() {
+        let args = Args::try_parse_from(["pslmap", "-t", "192.168.5.5", "--fast", "ps"]).unwrap();
+        let preset = ports_preset(&args).unwrap();
+        assert_eq!(preset.len(), 100);
+
+        let ret = Args::try_parse_from(["pslmap", "-t", "192.168.5.5", "--fast", "-p", "80", "ps"]);
+        assert!(ret.is_err());
+        let ret = Args::try_parse_from([
+            "pslmap",
+            "-t",
+            "192.168.5.5",
+            "--fast",
+            "--top-ports",
+            "10",
+            "ps",
+        ]);
+        assert!(ret.is_err());
+    }
+    #[test]
+    fn test_only_family() {
+        let args = Args::try_parse_from([
+            "pslmap",
+            "-t",
+            "192.168.5.5,fd00::5,10.0.0.1,fd00::6",
+            "--only-ipv6",
+            "ps",
+        ])
+        .unwrap();
+        let mut targets = TargetParser::new().target_from_input(&args.target.unwrap(), None);
+        assert_eq!(targets.len(), 4);
+        family_filter(&mut targets, args.only_ipv4, args.only_ipv6);
+        let addrs: Vec<String> = targets.iter().map(|t| t.addr.to_string()).collect();
+        assert_eq!(addrs, vec!["fd00::5", "fd00::6"]);
+
+        let ret = Args::try_parse_from([
+            "pslmap",
+            "-t",
+            "192.168.5.5",
+            "--only-ipv4",
+            "--only-ipv6",
+            "ps",
+        ]);
+        assert!(ret.is_err());
+    }
+    #[test]
+    fn test_parallelism() {
+        let args =
+            Args::try_parse_from(["pslmap", "-t", "192.168.5.5", "--nt", "8", "ps"]).unwrap();
+        assert_eq!(parallelism_parser(&args), Ok((8, 8)));
+
+        let args = Args::try_parse_from([
+            "pslmap",
+            "-t",
+            "192.168.5.5",
+            "--nt",
+            "8",
+            "--max-host-parallelism",
+            "64",
+            "--max-probe-parallelism",
+            "2",
+            "ps",
+        ])
+        .unwrap();
+        // many hosts but few probes at the same time
+        assert_eq!(parallelism_parser(&args), Ok((64, 2)));
+
+        let args = Args::try_parse_from([
+            "pslmap",
+            "-t",
+            "192.168.5.5",
+            "--max-probe-parallelism",
+            "0",
+            "ps",
+        ])
+        .unwrap();
+        assert!(parallelism_parser(&args).is_err());
+    }
+    #[test]
+    fn test_needs_ports() {
+        let parse = |argv: &[&str]| {
+            let args = Args::try_parse_from(argv).unwrap();
+            needs_ports(args.tools.as_ref().unwrap(), &args)
+        };
+        assert!(parse(&["pslmap", "-t", "192.168.5.5", "ps"]));
+        assert!(!parse(&["pslmap", "-t", "192.168.5.5", "ps", "-O"]));
+        assert!(parse(&["pslmap", "-t", "192.168.5.5", "od", "-3", "9"]));
+        assert!(!parse(&[
+            "pslmap",
+            "-t",
+            "192.168.5.5",
+            "od",
+            "-1",
+            "22",
+            "-2",
+            "81",
+            "-3",
+            "9"
+        ]));
+        assert!(!parse(&["pslmap", "-t", "192.168.5.5", "hd"]));
+    }
+    #[test]
+    fn test_default_ports() {
+        let args = Args::try_parse_from(["pslmap", "-t", "192.168.5.5", "ps"]).unwrap();
+        assert!(needs_ports(args.tools.as_ref().unwrap(), &args));
+        assert!(ports_preset(&args).is_none());
+        let mut targets = vec![Target::new(Ipv4Addr::new(192, 168, 5, 5).into(), None)];
+        empty_ports_fill(&mut targets, args.empty_ports).unwrap();
+        assert_eq!(targets[0].ports, DEFAULT_PORTS.to_vec());
+    }
+    #[test]
+    fn test_samples_range() {
+        let parse =
+            |n: &str| Args::try_parse_from(["pslmap", "-t", "192.168.5.5", "ps", "--samples", n]);
+        assert!(parse("0").is_err());
+        assert!(parse("1").is_err());
+        let args = parse("5").unwrap();
+        match args.tools {
+            Some(ToolsSubcommand::PS { samples, .. }) => assert_eq!(samples, Some(5)),
+            _ => panic!("expected the ps subcommand"),
+        }
+    }
+    #[test]
+    fn test_min_parallelism() {
+        let args = Args::try_parse_from([
+            "pslmap",
+            "-t",
+            "192.168.5.5",
+            "--nt",
+            "2",
+            "--min-parallelism",
+            "16",
+            "ps",
+        ])
+        .unwrap();
+        // the hosts are not raised
+        assert_eq!(parallelism_parser(&args), Ok((2, 16)));
+        for nt in ["1", "4", "16", "64"] {
+            let args = Args::try_parse_from([
+                "pslmap",
+                "-t",
+                "192.168.5.5",
+                "--nt",
+                nt,
+                "--min-parallelism",
+                "8",
+                "ps",
+            ])
+            .unwrap();
+            let (_, probes) = parallelism_parser(&args).unwrap();
+            assert!(probes >= 8);
+        }
+
+        let args = Args::try_parse_from([
+            "pslmap",
+            "-t",
+            "192.168.5.5",
+            "--max-probe-parallelism",
+            "4",
+            "--min-parallelism",
+            "8",
+            "ps",
+        ])
+        .unwrap();
+        assert!(parallelism_parser(&args).is_err());
+    }
+    #[test]
+    fn test_partial_targets() {
+        let mut parser = TargetParser::new();
+        let targets = parser.target_from_input(
+            "192.168.5.5,300.1.1.1,fd00::zz,192.168.5.9-1,10.0.0.1-10.0.0",
+            None,
+        );
+        assert_eq!(targets.len(), 1);
+        assert_eq!(parser.warnings.len(), 4);
+        let warning = targets_summary(targets.len(), &parser.warnings).unwrap();
+        assert_eq!(
+            warning,
+            Some(String::from(
+                "4 target inputs failed, scanning the 1 parsed targets"
+            ))
+        );
+
+        let mut parser = TargetParser::new();
+        let targets = parser.target_from_input("300.1.1.1", None);
+        assert!(targets_summary(targets.len(), &parser.warnings).is_err());
+
+        // the problem of the sole input is the error itself
+        let warnings = vec![String::from(
+            "hostname v6.example.com resolved to no IPv4 addresses",
+        )];
+        assert_eq!(targets_summary(0, &warnings), Err(warnings[0].clone()));
+    }
+    #[test]
+    fn test_badsum_flag() {
+        let args =
+            Args::try_parse_from(["pslmap", "-t", "192.168.5.5", "ps", "-s", "--badsum"]).unwrap();
+        match args.tools.unwrap() {
+            ToolsSubcommand::PS { syn, badsum, .. } => {
+                assert!(syn);
+                assert!(badsum);
             }
-            os_detection(&targets, top_k, log_level, timeout, num_threads)
+            _ => panic!("parse ps subcommand failed"),
         }
     }
+    #[test]
+    fn test_icmp_payload_size() {
+        let args = Args::try_parse_from([
+            "pslmap",
+            "-t",
+            "192.168.5.5",
+            "--icmp-payload-size",
+            "100",
+            "hd",
+            "-1",
+        ])
+        .unwrap();
+        assert_eq!(args.icmp_payload_size, Some(100));
+        assert!(payload_size_check(args.icmp_payload_size.unwrap()).is_ok());
+    }
+    #[test]
+    fn test_timeout_units() {
+        assert_eq!(timeout_parser("500ms"), Ok(0.5));
+        assert_eq!(timeout_parser("2s"), Ok(2.0));
+        assert_eq!(timeout_parser("1m"), Ok(60.0));
+        assert_eq!(timeout_parser("1.5"), Ok(1.5));
+        assert!(timeout_parser("-1s").is_err());
+        assert!(timeout_parser("2h").is_err());
+
+        let args =
+            Args::try_parse_from(["pslmap", "-t", "192.168.5.5", "--to", "500ms", "ps"]).unwrap();
+        assert_eq!(args.timeout, Some(0.5));
+
+        let default = |argv: &[&str]| {
+            let args = Args::try_parse_from(argv).unwrap();
+            default_timeout(&args.tools.unwrap())
+        };
+        assert_eq!(default(&["pslmap", "-t", "192.168.5.5", "hd", "-m"]), 0.5);
+        assert_eq!(default(&["pslmap", "-t", "192.168.5.5", "hd"]), 0.5);
+        assert_eq!(default(&["pslmap", "-t", "192.168.5.5", "hd", "-1"]), 1.0);
+        assert_eq!(default(&["pslmap", "-t", "192.168.5.5", "ps"]), 1.0);
+        assert_eq!(
+            default(&["pslmap", "-t", "192.168.5.5", "od", "--close-udp-port", "9"]),
+            3.0
+        );
+    }
+    #[test]
+    fn test_profile_override() {
+        let content =
+            "[quick]\nmethod = \"connect\"\nports = \"22,80\"\ntimeout = 0.5\nthreads = 16\n";
+        let profile = profile::profiles_parser(content).unwrap()["quick"].clone();
+        let matches = Args::command().get_matches_from([
+            "pslmap",
+            "-t",
+            "192.168.5.5",
+            "--profile",
+            "quick",
+            "--to",
+            "2.0",
+            "ps",
+        ]);
+        let mut args = Args::from_arg_matches(&matches).unwrap();
+        apply_profile(&mut args, &matches, &profile);
+        // the command line wins over the profile
+        assert_eq!(args.timeout, Some(2.0));
+        assert_eq!(args.num_threads, 16);
+        assert_eq!(args.ports, Some(String::from("22,80")));
+        assert_eq!(args.profile_method, Some(String::from("connect")));
+    }
+    #[test]
+    fn test_plan_only_route() {
+        // the plan-only run succeeds even without a route to the target
+        let argv = [
+            "pslmap",
+            "-t",
+            "192.0.2.1",
+            "-p",
+            "22",
+            "--explain",
+            "--list",
+            "ps",
+        ];
+        let matches = Args::command().get_matches_from(argv);
+        let args = Args::from_arg_matches(&matches).unwrap();
+        assert!(run(args, &matches).is_ok());
+    }
+    #[test]
+    fn test_run_errors() {
+        let run_args = |argv: &[&str]| {
+            let matches = Args::command().get_matches_from(argv);
+            let args = Args::from_arg_matches(&matches).unwrap();
+            run(args, &matches).unwrap_err().to_string()
+        };
+        // the bad inputs stop the run with an error instead of a panic
+        let profile_file = std::env::temp_dir().join("pslmap_test_run_errors_missing.toml");
+        let err = run_args(&[
+            "pslmap",
+            "-t",
+            "192.168.5.5",
+            "--profile",
+            "quick",
+            "--profile-file",
+            &profile_file.to_string_lossy(),
+            "ps",
+        ]);
+        assert!(err.starts_with("load profile failed: read "));
+        let err = run_args(&["pslmap", "-t", "192.168.5.5", "-p", "0", "ps"]);
+        assert_eq!(err, "invalid ports: port 0 is not valid");
+        let err = run_args(&["pslmap", "-t", "192.168.5.5", "--nt", "0", "ps"]);
+        assert_eq!(
+            err,
+            "invalid parallelism: the hosts and the probes must be greater than 0"
+        );
+        let argv = ["--min-rtt-timeout", "2", "--max-rtt-timeout", "1"];
+        let err = run_args(&[&["pslmap", "-t", "192.168.5.5"], &argv[..], &["ps"]].concat());
+        assert!(err.starts_with("invalid rtt timeout bounds: "));
+    }
+    #[test]
+    fn test_dns_servers() {
+        let answer = Ipv4Addr::new(192, 168, 5, 5);
+        let server = dns::mock_dns_server(answer);
+        let server = server.to_string();
+        let args = Args::try_parse_from([
+            "pslmap",
+            "-t",
+            "mock.example.com",
+            "--dns-servers",
+            &server,
+            "ps",
+        ])
+        .unwrap();
+        let mut parser = target_parser(&args).unwrap();
+        let ret = parser.target_from_input("mock.example.com", None);
+        assert_eq!(parser.warnings.len(), 0);
+        assert_eq!(ret.len(), 1);
+        assert_eq!(ret[0].addr, std::net::IpAddr::V4(answer));
+    }
+    #[test]
+    fn test_route_check() {
+        let targets = vec![
+            Target::new(Ipv4Addr::new(192, 168, 5, 5).into(), Some(vec![22])),
+            Target::new("fe80::1".parse().unwrap(), Some(vec![22])),
+        ];
+        // the v4-only host
+        let ret = route_check(&targets, |addr| addr.is_ipv4());
+        assert_eq!(
+            ret,
+            Err(String::from("no IPv6 route available, cannot scan fe80::1"))
+        );
+        assert!(route_check(&targets[..1], |addr| addr.is_ipv4()).is_ok());
+        assert!(route_check(&targets, |_| true).is_ok());
+    }
+    #[test]
+    fn test_scan_method_conflict() {
+        let args =
+            Args::try_parse_from(["pslmap", "-t", "192.168.5.5", "ps", "--syn", "--connect"])
+                .unwrap();
+        let flags = match args.tools.unwrap() {
+            ToolsSubcommand::PS {
+                syn, connect, udp, ..
+            } => [("syn", syn), ("connect", connect), ("udp", udp)],
+            _ => panic!("parse ps failed"),
+        };
+        assert_eq!(
+            scan_method_conflict(&flags),
+            Err(String::from(
+                "multiple scan methods specified: syn, connect — choose one"
+            ))
+        );
+        assert!(scan_method_conflict(&[("syn", true), ("udp", false)]).is_ok());
+        assert!(scan_method_conflict(&[("syn", false), ("udp", false)]).is_ok());
+
+        let args = Args::try_parse_from(["pslmap", "-t", "192.168.5.5", "ps", "--sctp"]).unwrap();
+        assert!(matches!(
+            args.tools,
+            Some(ToolsSubcommand::PS { sctp: true, .. })
+        ));
+        assert_eq!(ps_method_parser("sctp"), Ok(PortScanningMethod::SctpInit));
+        assert_eq!(
+            ps_method_parser("fast"),
+            Err(String::from("unknown port scanning method fast"))
+        );
+    }
+    #[test]
+    fn test_private_only() {
+        let args = Args::try_parse_from([
+            "pslmap",
+            "-t",
+            "192.168.5.5,8.8.8.8,fd00::5",
+            "--private-only",
+            "ps",
+        ])
+        .unwrap();
+        assert!(args.private_only);
+        let targets = TargetParser::new().target_from_input(&args.target.unwrap(), None);
+        assert_eq!(targets.len(), 3);
+        assert_eq!(
+            private_only_check(&targets),
+            Err(String::from(
+                "--private-only refuses the public targets [8.8.8.8]"
+            ))
+        );
+        assert!(private_only_check(&targets[..1]).is_ok());
+        assert!(is_private(IpAddr::V4(Ipv4Addr::LOCALHOST)));
+        assert!(!is_private("2001:db8::1".parse().unwrap()));
+    }
+    #[test]
+    fn test_unprivileged() {
+        let status = "Name:\tpslmap\nCapInh:\t0000000000000000\nCapEff:\t0000000000000000\n";
+        assert_eq!(raw_privileged(status), Some(false));
+        assert_eq!(raw_privileged("CapEff:\t000001ffffffffff"), Some(true));
+        // only CAP_NET_RAW granted
+        assert_eq!(raw_privileged("CapEff:\t0000000000002000"), Some(true));
+        assert_eq!(raw_privileged("Name:\tpslmap"), None);
+
+        let err = unprivileged_override(PortScanningMethod::TcpSyn, false, false).unwrap_err();
+        assert_eq!(
+            err,
+            "tcp_syn needs the raw sockets: run as root or grant CAP_NET_RAW, or use --connect for an unprivileged TCP scan"
+        );
+        let (method, warning) =
+            unprivileged_override(PortScanningMethod::TcpSyn, false, true).unwrap();
+        assert_eq!(method, PortScanningMethod::TcpConnect);
+        assert!(warning.is_some());
+        // udp has no unprivileged fallback
+        assert!(unprivileged_override(PortScanningMethod::Udp, false, true).is_err());
+        assert_eq!(
+            unprivileged_override(PortScanningMethod::TcpConnect, false, false),
+            Ok((PortScanningMethod::TcpConnect, None))
+        );
+    }
+    #[test]
+    fn test_loopback_override() {
+        let args =
+            Args::try_parse_from(["pslmap", "-t", "127.0.0.1", "-p", "22", "ps", "--syn"]).unwrap();
+        let ps_method = match args.tools.unwrap() {
+            ToolsSubcommand::PS { syn: true, .. } => PortScanningMethod::TcpSyn,
+            _ => panic!("parse --syn failed"),
+        };
+        let targets = TargetParser::new().target_from_input(&args.target.unwrap(), args.ports);
+        let (method, warning) = loopback_override(&targets, ps_method, false);
+        assert_eq!(method, PortScanningMethod::TcpConnect);
+        assert_eq!(
+            warning,
+            Some(String::from(
+                "the loopback targets [127.0.0.1] are scanned with tcp_connect instead of tcp_syn"
+            ))
+        );
+
+        // the other targets keep the method
+        let mut targets = targets;
+        targets.push(Target::new(
+            Ipv4Addr::new(192, 168, 5, 5).into(),
+            Some(vec![22]),
+        ));
+        let (method, warning) = loopback_override(&targets, ps_method, false);
+        assert_eq!(method, PortScanningMethod::TcpSyn);
+        assert!(warning.is_some());
+        assert_eq!(
+            loopback_override(&targets[1..], ps_method, false),
+            (PortScanningMethod::TcpSyn, None)
+        );
+        assert_eq!(
+            loopback_override(&targets[..1], PortScanningMethod::Udp, false),
+            (PortScanningMethod::Udp, None)
+        );
+    }
 }
@@ -0,0 +1,146 @@
+/// the supported scan methods with their flags and defaults (pslmap methods)
+use crate::DEFAULT_TIMEOUT;
+use crate::MAC_DEFAULT_TIMEOUT;
+use crate::OD_DEFAULT_TIMEOUT;
+use crate::hd::HostDiscoveryMethod;
+use crate::ps::PortScanningMethod;
+
+const HD_METHODS: [HostDiscoveryMethod; 8] = [
+    HostDiscoveryMethod::IcmpEcho,
+    HostDiscoveryMethod::IcmpTimestamp,
+    HostDiscoveryMethod::IcmpAddressMask,
+    HostDiscoveryMethod::TcpSyn,
+    HostDiscoveryMethod::TcpAck,
+    HostDiscoveryMethod::Udp,
+    HostDiscoveryMethod::Mac,
+    HostDiscoveryMethod::Ndp,
+];
+
+const PS_METHODS: [PortScanningMethod; 13] = [
+    PortScanningMethod::TcpSyn,
+    PortScanningMethod::TcpConnect,
+    PortScanningMethod::TcpFin,
+    PortScanningMethod::TcpNull,
+    PortScanningMethod::TcpXmas,
+    PortScanningMethod::TcpAck,
+    PortScanningMethod::TcpWindow,
+    PortScanningMethod::TcpMaimon,
+    PortScanningMethod::TcpCustom(0),
+    PortScanningMethod::Udp,
+    PortScanningMethod::SctpInit,
+    PortScanningMethod::TcpIdle,
+    PortScanningMethod::IpProto,
+];
+
+/// One row of the listing.
+struct MethodInfo {
+    tool: &'static str,
+    name: &'static str,
+    flag: &'static str,
+    privileged: bool,
+    ports: &'static str,
+    timeout: f64,
+}
+
+fn hd_info(method: HostDiscoveryMethod) -> MethodInfo {
+    let (flag, ports, timeout) = match method {
+        HostDiscoveryMethod::IcmpEcho => ("-1, --p1", "-", DEFAULT_TIMEOUT),
+        HostDiscoveryMethod::IcmpTimestamp => ("-2, --p2", "-", DEFAULT_TIMEOUT),
+        HostDiscoveryMethod::IcmpAddressMask => ("-3, --p3", "-", DEFAULT_TIMEOUT),
+        HostDiscoveryMethod::TcpSyn => ("-s, --syn", "80", DEFAULT_TIMEOUT),
+        HostDiscoveryMethod::TcpAck => ("-a, --ack", "80", DEFAULT_TIMEOUT),
+        HostDiscoveryMethod::Udp => ("-u, --udp", "125", DEFAULT_TIMEOUT),
+        HostDiscoveryMethod::Mac => ("-m, --mac (default)", "-", MAC_DEFAULT_TIMEOUT),
+        HostDiscoveryMethod::Ndp => ("-n, --ndp", "-", MAC_DEFAULT_TIMEOUT),
+    };
+    MethodInfo {
+        tool: "hd",
+        name: method.name(),
+        flag,
+        privileged: true,
+        ports,
+        timeout,
+    }
+}
+
+fn ps_info(method: PortScanningMethod) -> MethodInfo {
+    let (flag, ports) = match method {
+        PortScanningMethod::TcpSyn => ("-s, --syn (default)", "-p"),
+        PortScanningMethod::TcpConnect => ("-c, --connect", "-p"),
+        PortScanningMethod::TcpFin => ("-f, --fin", "-p"),
+        PortScanningMethod::TcpNull => ("-n, --null", "-p"),
+        PortScanningMethod::TcpXmas => ("-x, --xmas", "-p"),
+        PortScanningMethod::TcpAck => ("-a, --ack", "-p"),
+        PortScanningMethod::TcpWindow => ("-w, --window", "-p"),
+        PortScanningMethod::TcpMaimon => ("-m, --maimon", "-p"),
+        PortScanningMethod::TcpCustom(_) => ("--scanflags FLAGS", "-p"),
+        PortScanningMethod::Udp => ("-u, --udp", "-p"),
+        PortScanningMethod::SctpInit => ("--sctp (unsupported)", "-p"),
+        PortScanningMethod::TcpIdle => ("idle --zombie-ipv4 --zombie-port", "-p"),
+        PortScanningMethod::IpProto => ("-O, --ip-proto", "0-255"),
+    };
+    MethodInfo {
+        tool: "ps",
+        name: method.name(),
+        flag,
+        // only the connect scan goes through the kernel tcp stack
+        privileged: method != PortScanningMethod::TcpConnect,
+        ports,
+        timeout: DEFAULT_TIMEOUT,
+    }
+}
+
+fn od_info() -> MethodInfo {
+    MethodInfo {
+        tool: "od",
+        name: "os_detection",
+        flag: "od -3 CLOSE_UDP_PORT",
+        privileged: true,
+        ports: "open/closed tcp found by syn scan",
+        timeout: OD_DEFAULT_TIMEOUT,
+    }
+}
+
+/// Such as `ps  tcp_connect  -c, --connect  unprivileged  ports -p  timeout 1.0s`,
+/// the ports `-p` means the ports of the targets.
+pub fn method_lines() -> Vec<String> {
+    let mut infos: Vec<MethodInfo> = HD_METHODS.into_iter().map(hd_info).collect();
+    infos.extend(PS_METHODS.into_iter().map(ps_info));
+    infos.push(od_info());
+    let name_width = infos.iter().map(|i| i.name.len()).max().unwrap_or(0);
+    let flag_width = infos.iter().map(|i| i.flag.len()).max().unwrap_or(0);
+    infos
+        .iter()
+        .map(|i| {
+            let privileged = if i.privileged { "root" } else { "unprivileged" };
+            format!(
+                "{}  {:<name_width$}  {:<flag_width$}  {:<12}  ports {}  timeout {:.1}s",
+                i.tool, i.name, i.flag, privileged, i.ports, i.timeout
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_method_lines() {
+        let lines = method_lines();
+        assert_eq!(lines.len(), HD_METHODS.len() + PS_METHODS.len() + 1);
+        for method in HD_METHODS {
+            let prefix = format!("hd  {} ", method.name());
+            assert!(lines.iter().any(|l| l.starts_with(&prefix)), "{}", prefix);
+        }
+        for method in PS_METHODS {
+            let prefix = format!("ps  {} ", method.name());
+            assert!(lines.iter().any(|l| l.starts_with(&prefix)), "{}", prefix);
+        }
+        let connect = lines
+            .iter()
+            .find(|l| l.starts_with("ps  tcp_connect "))
+            .unwrap();
+        assert!(connect.contains(" unprivileged "));
+        assert!(lines.last().unwrap().ends_with("timeout 3.0s"));
+    }
+}
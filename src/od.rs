@@ -1,33 +1,366 @@
 /// remote os detection
-use pistol::PistolLogger;
-use pistol::PistolRunner;
 use pistol::Target;
+use pistol::error::PistolError;
 use pistol::os::OsDetect;
+use pistol::os::PistolOsDetects;
 use pistol::os_detect;
+use pistol::scan::PortReport;
+use pistol::scan::PortStatus;
+use pistol::tcp_syn_scan;
 use std::collections::BTreeMap;
 use std::net::IpAddr;
 use std::time::Duration;
 use std::time::Instant;
 
-use crate::InfoShow;
+use crate::SRC_ADDR;
+use crate::error::PslmapError;
+use crate::hd::HostDiscoveryMethod;
+use crate::hd::HostDiscoveryStatus;
+use crate::hd::discovery_stage;
+use crate::output::Emitter;
+use crate::output::OsCandidate;
+use crate::output::OsRecord;
+use crate::output::OutputFormat;
+use crate::output::ScanOutcome;
+use crate::output::no_data_lines;
+use crate::output::raw_debug;
+use crate::ports::FAST_PORTS_NUM;
+use crate::ports::top_ports;
 
-pub fn os_detection(
+/// The method name of the output records.
+pub const OS_DETECT_METHOD: &str = "os_detect";
+
+/// The max probe timeout with --os-short-timeout.
+pub const OS_SHORT_TIMEOUT: f64 = 0.3;
+
+/// The probe timeout forwarded to pistol, pistol always sends the full probe set,
+/// so the short timeout only waits less for each response and the late responses lower the accuracy.
+fn os_probe_timeout(timeout: f64, short_timeout: bool) -> f64 {
+    if short_timeout {
+        timeout.min(OS_SHORT_TIMEOUT)
+    } else {
+        timeout
+    }
+}
+
+/// The cpe of both families as a list, the IPv4 detect gives one cpe per item
+/// while the IPv6 detect joins them with spaces (`cpe:/o:linux:linux_kernel:4 cpe:/o:linux:linux_kernel:5`),
+/// the nmap flags such as `auto` are dropped.
+fn cpe_list(cpe: &[&str]) -> Vec<String> {
+    let mut ret: Vec<String> = Vec::new();
+    for c in cpe.iter().flat_map(|c| c.split_whitespace()) {
+        if c.starts_with("cpe:") && !ret.iter().any(|r| r == c) {
+            ret.push(c.to_string());
+        }
+    }
+    ret
+}
+
+fn os_candidate(name: &str, accuracy: f64, cpe: &[&str]) -> OsCandidate {
+    OsCandidate {
+        rank: 0,
+        name: name.to_string(),
+        accuracy,
+        cpe: cpe_list(cpe),
+    }
+}
+
+/// Normalize the IPv4 and IPv6 detect results,
+/// the IPv4 accuracy is score/total and the IPv6 accuracy comes from the predict value.
+fn os_candidates(detect: &OsDetect) -> (Vec<OsCandidate>, Duration) {
+    let (candidates, cost) = match detect {
+        OsDetect::V4(x) => {
+            let candidates: Vec<OsCandidate> = x
+                .detects
+                .iter()
+                .map(|d| {
+                    let accuracy = if d.total > 0 {
+                        d.score as f64 / d.total as f64 * 100.0
+                    } else {
+                        0.0
+                    };
+                    let cpe: Vec<&str> = d.cpe.iter().map(|c| c.as_str()).collect();
+                    os_candidate(&d.name, accuracy, &cpe)
+                })
+                .collect();
+            (candidates, x.cost)
+        }
+        OsDetect::V6(x) => {
+            let candidates: Vec<OsCandidate> = x
+                .detects
+                .iter()
+                .map(|d| {
+                    let accuracy = (d.score * 100.0).clamp(0.0, 100.0);
+                    os_candidate(&d.name, accuracy, &[&d.cpe])
+                })
+                .collect();
+            (candidates, x.cost)
+        }
+    };
+
+    (rank_candidates(candidates), cost)
+}
+
+/// Sort the candidates by accuracy in descending order and set the rank.
+fn rank_candidates(mut candidates: Vec<OsCandidate>) -> Vec<OsCandidate> {
+    candidates.sort_by(|a, b| b.accuracy.total_cmp(&a.accuracy));
+    for (i, c) in candidates.iter_mut().enumerate() {
+        c.rank = i + 1;
+    }
+    candidates
+}
+
+/// Aligned table of the candidates of one host.
+fn os_table(
+    addr: IpAddr,
+    candidates: &[OsCandidate],
+    cost: Duration,
+    short_timeout: bool,
+) -> String {
+    let header = if short_timeout {
+        format!(
+            "{} ({:.2}s, short probe timeout: low confidence)",
+            addr,
+            cost.as_secs_f64()
+        )
+    } else {
+        format!("{} ({:.2}s)", addr, cost.as_secs_f64())
+    };
+    let mut lines = vec![header];
+    if candidates.is_empty() {
+        lines.push(String::from("  no matching results"));
+        return lines.join("\n");
+    }
+
+    let name_width = candidates
+        .iter()
+        .map(|c| c.name.len())
+        .max()
+        .unwrap_or(0)
+        .max("name".len());
+    lines.push(format!(
+        "  {:<6}{:<width$}  {:>8}  {}",
+        "rank",
+        "name",
+        "accuracy",
+        "cpe",
+        width = name_width
+    ));
+    for c in candidates {
+        let accuracy_str = format!("{:.1}%", c.accuracy);
+        lines.push(format!(
+            "  {:<6}{:<width$}  {:>8}  {}",
+            format!("#{}", c.rank),
+            c.name,
+            accuracy_str,
+            c.cpe.join(","),
+            width = name_width
+        ));
+    }
+    lines.join("\n")
+}
+
+/// Group the hosts by the name of their top candidate, such as `Linux 5.x: 1.2.3.4, 1.2.3.5`.
+fn os_groups(btm: &BTreeMap<IpAddr, Vec<OsCandidate>>) -> Vec<String> {
+    let mut groups: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for (addr, candidates) in btm {
+        let name = match candidates.first() {
+            Some(c) => c.name.as_str(),
+            None => "no matching results",
+        };
+        groups.entry(name).or_default().push(addr.to_string());
+    }
+    groups
+        .into_iter()
+        .map(|(name, addrs)| format!("{}: {}", name, addrs.join(", ")))
+        .collect()
+}
+
+/// The lowest open and the lowest closed tcp port of each scanned host,
+/// the host without any open (or closed) port can not be fingerprinted.
+fn derive_os_ports(reports: &[PortReport]) -> BTreeMap<IpAddr, Result<(u16, u16), &'static str>> {
+    let mut found: BTreeMap<IpAddr, (Option<u16>, Option<u16>)> = BTreeMap::new();
+    for report in reports {
+        let (open, closed) = found.entry(report.addr).or_default();
+        let port = match report.status {
+            PortStatus::Open => open,
+            PortStatus::Closed => closed,
+            _ => continue,
+        };
+        *port = Some(port.map_or(report.port, |p| p.min(report.port)));
+    }
+    // the hosts with only the filtered ports
+    for report in reports {
+        found.entry(report.addr).or_default();
+    }
+    found
+        .into_iter()
+        .map(|(addr, ports)| {
+            let ret = match ports {
+                (Some(open), Some(closed)) => Ok((open, closed)),
+                (None, _) => Err("no open tcp port found"),
+                (Some(_), None) => Err("no closed tcp port found"),
+            };
+            (addr, ret)
+        })
+        .collect()
+}
+
+/// Without --open-tcp-port and --close-tcp-port, the tcp ports of each host come from a quick syn scan
+/// of the target ports (or the top ports), the hosts without them are skipped with a note.
+pub fn os_ports_targets(
+    targets: &[Target],
+    close_udp_port: u16,
+    timeout: f64,
+    num_threads: usize,
+) -> Result<Vec<Target>, PslmapError> {
+    let scan_targets: Vec<Target> = targets
+        .iter()
+        .map(|t| {
+            let mut t = t.clone();
+            if t.ports.is_empty() {
+                t.ports = top_ports(FAST_PORTS_NUM);
+            }
+            t
+        })
+        .collect();
+    let src_addr = *SRC_ADDR.lock().expect("lock SRC_ADDR failed");
+    let timeout = Some(Duration::from_secs_f64(timeout));
+    let ret = tcp_syn_scan(&scan_targets, Some(num_threads), src_addr, None, timeout, 2)
+        .map_err(PslmapError::pistol("tcp syn scan"))?;
+    let derived = derive_os_ports(&ret.port_reports);
+
+    let mut os_targets = Vec::new();
+    for t in targets {
+        match derived.get(&t.addr) {
+            Some(Ok((open, closed))) => {
+                let mut t = t.clone();
+                t.ports = vec![*open, *closed, close_udp_port];
+                os_targets.push(t);
+            }
+            Some(Err(e)) => eprintln!("note: skip the os detection of {}: {}", t.addr, e),
+            None => eprintln!("note: skip the os detection of {}: not scanned", t.addr),
+        }
+    }
+    Ok(os_targets)
+}
+
+/// The os fingerprinting sends many probes per host,
+/// so `--os-threads` bounds it apart from the global host parallelism.
+pub fn os_threads_parser(num_threads: usize, os_threads: Option<usize>) -> Result<usize, String> {
+    let threads = os_threads.unwrap_or(num_threads);
+    if threads == 0 {
+        return Err(String::from(
+            "invalid os detection threads: it must be greater than 0",
+        ));
+    }
+    Ok(threads)
+}
+
+/// Same as the pistol `os_detect`, replaced in the tests.
+type OsDetector = fn(
+    &[Target],
+    Option<usize>,
+    Option<IpAddr>,
+    usize,
+    Option<Duration>,
+) -> Result<PistolOsDetects, PistolError>;
+
+fn detect_reports(
+    detector: OsDetector,
     targets: &[Target],
     top_k: usize,
-    log_level: PistolLogger,
     timeout: f64,
+    short_timeout: bool,
     num_threads: usize,
-) {
-    let start = Instant::now();
+) -> Result<PistolOsDetects, PslmapError> {
+    let src_addr = *SRC_ADDR.lock().expect("lock SRC_ADDR failed");
+    let timeout = Some(Duration::from_secs_f64(os_probe_timeout(
+        timeout,
+        short_timeout,
+    )));
+    detector(targets, Some(num_threads), src_addr, top_k, timeout)
+        .map_err(PslmapError::pistol("os detect"))
+}
+
+/// The hosts answering the icmp echo ping, or else the tcp syn ping to the os detection ports.
+/// The `discover` is the `discovery_stage` of the method, replaced in the tests.
+fn alive_targets<F>(targets: &[Target], mut discover: F) -> Result<Vec<Target>, PslmapError>
+where
+    F: FnMut(
+        &[Target],
+        HostDiscoveryMethod,
+    ) -> Result<Vec<(Target, HostDiscoveryStatus)>, PslmapError>,
+{
+    let mut alive = Vec::new();
+    let mut pending = targets.to_vec();
+    for hd_method in [HostDiscoveryMethod::IcmpEcho, HostDiscoveryMethod::TcpSyn] {
+        if pending.is_empty() {
+            break;
+        }
+        let discovered = discover(&pending, hd_method)?;
+        pending = Vec::new();
+        for (t, status) in discovered {
+            match status {
+                HostDiscoveryStatus::Up => alive.push(t),
+                HostDiscoveryStatus::Down => pending.push(t),
+            }
+        }
+    }
+    if !pending.is_empty() {
+        eprintln!(
+            "note: {} hosts are down, skip the os detection of them",
+            pending.len()
+        );
+    }
+    Ok(alive)
+}
 
-    let _pr = PistolRunner::init(log_level, None, None).expect("init pistol runner failed");
+/// The options of one os detection run (the od subcommand).
+#[derive(Debug, Clone)]
+pub struct OsDetectionOptions {
+    pub top_k: usize,
+    pub timeout: f64,
+    pub short_timeout: bool,
+    pub num_threads: usize,
+    pub alive_only: bool,
+    pub group_os: bool,
+    pub verbose: u8,
+    pub output_format: OutputFormat,
+}
 
-    let num_threads = Some(num_threads);
-    let src_addr = None;
-    let timeout = Some(Duration::from_secs_f64(timeout));
+pub fn os_detection(
+    targets: &[Target],
+    options: OsDetectionOptions,
+) -> Result<ScanOutcome, PslmapError> {
+    let start = Instant::now();
+    let OsDetectionOptions {
+        top_k,
+        timeout,
+        short_timeout,
+        num_threads,
+        alive_only,
+        group_os,
+        verbose,
+        output_format,
+    } = options;
 
-    let ret = os_detect(targets, num_threads, src_addr, top_k, timeout).expect("os detect failed");
-    println!("{}", ret);
+    let targets = if alive_only {
+        alive_targets(targets, |targets, hd_method| {
+            discovery_stage(targets, hd_method, false, timeout, num_threads, None)
+        })?
+    } else {
+        targets.to_vec()
+    };
+    let ret = detect_reports(
+        os_detect,
+        &targets,
+        top_k,
+        timeout,
+        short_timeout,
+        num_threads,
+    )?;
+    raw_debug(&ret.os_detects);
 
     // sorted
     let mut btm: BTreeMap<IpAddr, OsDetect> = BTreeMap::new();
@@ -35,41 +368,253 @@ pub fn os_detection(
         btm.insert(report.addr(), report.clone());
     }
 
-    let mut info = Vec::new();
+    let found = btm.len();
+    let mut emitter = Emitter::stdout(output_format).with_method(OS_DETECT_METHOD);
+    let mut host_candidates = BTreeMap::new();
     for (addr, detect) in btm {
-        let line = match detect {
-            OsDetect::V4(x) => {
-                let names_vec: Vec<String> = x.detects.iter().map(|x| x.name.clone()).collect();
-                let cpe_vec: Vec<String> = x.detects.iter().map(|x| x.cpe.join(",")).collect();
-                let line = format!(
-                    "{} -> {} {} ({:.2}s)",
-                    addr,
-                    names_vec.join("|"),
-                    cpe_vec.join("|"),
-                    x.cost.as_secs_f64(),
-                );
-                line
-            }
-            OsDetect::V6(x) => {
-                let names_vec: Vec<String> = x.detects.iter().map(|x| x.name.clone()).collect();
-                let cpe_vec: Vec<String> = x.detects.iter().map(|x| x.cpe.clone()).collect();
-                let line = format!(
-                    "{} -> {} {} ({:.2}s)",
-                    addr,
-                    names_vec.join("|"),
-                    cpe_vec.join("|"),
-                    x.cost.as_secs_f64(),
-                );
-                line
-            }
+        let (candidates, cost) = os_candidates(&detect);
+        // the per host tables are kept with --group-os only in the verbose mode
+        let line = if group_os && verbose == 0 {
+            None
+        } else {
+            Some(os_table(addr, &candidates, cost, short_timeout))
         };
-        info.push(line);
+        host_candidates.insert(addr, candidates.clone());
+        let confidence = short_timeout.then(|| String::from("low"));
+        let record = OsRecord {
+            addr: addr.to_string(),
+            candidates,
+            confidence,
+            cost: cost.as_secs_f64(),
+        };
+        emitter.emit(line, &record);
+    }
+    if group_os {
+        for line in os_groups(&host_candidates) {
+            emitter.text(line);
+        }
+    }
+    if verbose >= 1 {
+        for line in no_data_lines(&targets, host_candidates.keys()) {
+            emitter.text(line);
+        }
     }
 
-    let info = info.join("\n");
     let tail = format!(
         "pslmap done: scanned in {:.2} seconds",
         start.elapsed().as_secs_f64()
     );
-    InfoShow::print(&info, &tail);
+    emitter.finish(&tail);
+    Ok(ScanOutcome {
+        up_addrs: Vec::new(),
+        found,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+    use std::sync::Mutex;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+    #[test]
+    fn test_os_table() {
+        let candidates = vec![
+            OsCandidate {
+                rank: 0,
+                name: String::from("Linux 4.15"),
+                accuracy: 88.0,
+                cpe: vec![String::from("cpe:/o:linux:linux_kernel:4.15")],
+            },
+            OsCandidate {
+                rank: 0,
+                name: String::from("Linux 5.0 - 5.14"),
+                accuracy: 96.5,
+                cpe: vec![String::from("cpe:/o:linux:linux_kernel:5")],
+            },
+        ];
+        let candidates = rank_candidates(candidates);
+        let addr = IpAddr::V4(Ipv4Addr::new(192, 168, 5, 5));
+        let table = os_table(addr, &candidates, Duration::from_secs(1), false);
+        println!("{}", table);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[2].contains("#1") && lines[2].contains("Linux 5.0 - 5.14"));
+        assert!(lines[2].contains("96.5%"));
+        assert!(lines[3].contains("#2") && lines[3].contains("Linux 4.15"));
+        assert!(lines[3].contains("88.0%"));
+        // the accuracy column is aligned
+        assert_eq!(lines[2].find("96.5%"), lines[3].find("88.0%"));
+    }
+    #[test]
+    fn test_cpe_list() {
+        let name = "Linux 4.15 - 5.x";
+        // the IPv4 detect
+        let v4 = os_candidate(
+            name,
+            90.0,
+            &[
+                "cpe:/o:linux:linux_kernel:4 auto",
+                "cpe:/o:linux:linux_kernel:5",
+            ],
+        );
+        // the IPv6 detect
+        let v6 = os_candidate(
+            name,
+            90.0,
+            &["cpe:/o:linux:linux_kernel:4 auto cpe:/o:linux:linux_kernel:5"],
+        );
+        assert_eq!(
+            v4.cpe,
+            vec!["cpe:/o:linux:linux_kernel:4", "cpe:/o:linux:linux_kernel:5"]
+        );
+        assert_eq!(v6.cpe, v4.cpe);
+        let addr = IpAddr::V4(Ipv4Addr::new(192, 168, 5, 5));
+        assert_eq!(
+            os_table(addr, &rank_candidates(vec![v4]), Duration::ZERO, false),
+            os_table(addr, &rank_candidates(vec![v6]), Duration::ZERO, false)
+        );
+        assert_eq!(os_candidate(name, 90.0, &[""]).cpe, Vec::<String>::new());
+    }
+    #[test]
+    fn test_os_groups() {
+        let candidate = |name: &str| OsCandidate {
+            rank: 1,
+            name: name.to_string(),
+            accuracy: 90.0,
+            cpe: Vec::new(),
+        };
+        let mut btm = BTreeMap::new();
+        btm.insert(
+            IpAddr::V4(Ipv4Addr::new(192, 168, 5, 5)),
+            vec![candidate("Linux 5.x"), candidate("Linux 4.x")],
+        );
+        btm.insert(
+            IpAddr::V4(Ipv4Addr::new(192, 168, 5, 6)),
+            vec![candidate("Windows 10")],
+        );
+        btm.insert(
+            IpAddr::V4(Ipv4Addr::new(192, 168, 5, 7)),
+            vec![candidate("Linux 5.x")],
+        );
+        assert_eq!(
+            os_groups(&btm),
+            vec![
+                "Linux 5.x: 192.168.5.5, 192.168.5.7",
+                "Windows 10: 192.168.5.6",
+            ]
+        );
+    }
+    #[test]
+    fn test_derive_os_ports() {
+        let host1 = IpAddr::V4(Ipv4Addr::new(192, 168, 5, 5));
+        let host2 = IpAddr::V4(Ipv4Addr::new(192, 168, 5, 6));
+        let host3 = IpAddr::V4(Ipv4Addr::new(192, 168, 5, 7));
+        let report = |addr: IpAddr, port: u16, status: PortStatus| PortReport {
+            addr,
+            port,
+            origin: None,
+            status,
+            cost: Duration::from_millis(10),
+        };
+        let reports = vec![
+            report(host1, 443, PortStatus::Open),
+            report(host1, 22, PortStatus::Open),
+            report(host1, 80, PortStatus::Closed),
+            report(host1, 21, PortStatus::Closed),
+            report(host2, 22, PortStatus::Closed),
+            report(host2, 80, PortStatus::Filtered),
+            report(host3, 22, PortStatus::Open),
+            report(host3, 80, PortStatus::Filtered),
+        ];
+        let derived = derive_os_ports(&reports);
+        assert_eq!(derived[&host1], Ok((22, 21)));
+        assert_eq!(derived[&host2], Err("no open tcp port found"));
+        assert_eq!(derived[&host3], Err("no closed tcp port found"));
+    }
+    #[test]
+    #[allow(clippy::result_large_err)] // the PistolError of the mock detector
+    fn test_os_threads() {
+        static NUM_THREADS: AtomicUsize = AtomicUsize::new(0);
+        let detector: OsDetector = |_, num_threads, _, _, _| {
+            NUM_THREADS.store(num_threads.unwrap_or(0), Ordering::SeqCst);
+            Ok(PistolOsDetects::new())
+        };
+        let threads = os_threads_parser(8, Some(2)).unwrap();
+        let targets = vec![Target::new(Ipv4Addr::new(192, 168, 5, 5).into(), None)];
+        detect_reports(detector, &targets, 3, 1.0, false, threads).unwrap();
+        assert_eq!(NUM_THREADS.load(Ordering::SeqCst), 2);
+
+        // falls back to the global threads
+        assert_eq!(os_threads_parser(8, None), Ok(8));
+        assert!(os_threads_parser(8, Some(0)).is_err());
+    }
+    #[test]
+    #[allow(clippy::result_large_err)] // the PistolError of the mock detector
+    fn test_alive_only() {
+        static DETECTED: Mutex<Vec<IpAddr>> = Mutex::new(Vec::new());
+        let up = IpAddr::V4(Ipv4Addr::new(192, 168, 5, 5));
+        let syn_up = IpAddr::V4(Ipv4Addr::new(192, 168, 5, 6));
+        let down = IpAddr::V4(Ipv4Addr::new(192, 168, 5, 7));
+        let targets: Vec<Target> = [up, syn_up, down]
+            .into_iter()
+            .map(|addr| Target::new(addr, Some(vec![22, 54532, 34098])))
+            .collect();
+        let mut probed = Vec::new();
+        let alive = alive_targets(&targets, |targets, hd_method| {
+            probed.push((hd_method, targets.len()));
+            Ok(targets
+                .iter()
+                .map(|t| {
+                    let status = if t.addr == up
+                        || (t.addr == syn_up && hd_method == HostDiscoveryMethod::TcpSyn)
+                    {
+                        HostDiscoveryStatus::Up
+                    } else {
+                        HostDiscoveryStatus::Down
+                    };
+                    (t.clone(), status)
+                })
+                .collect())
+        })
+        .unwrap();
+        // only the hosts missed by the echo ping are probed again
+        assert_eq!(
+            probed,
+            vec![
+                (HostDiscoveryMethod::IcmpEcho, 3),
+                (HostDiscoveryMethod::TcpSyn, 2)
+            ]
+        );
+
+        let detector: OsDetector = |targets, _, _, _, _| {
+            let mut detected = DETECTED.lock().unwrap();
+            detected.extend(targets.iter().map(|t| t.addr));
+            Ok(PistolOsDetects::new())
+        };
+        detect_reports(detector, &alive, 3, 1.0, false, 2).unwrap();
+        assert_eq!(*DETECTED.lock().unwrap(), vec![up, syn_up]);
+    }
+    #[test]
+    #[allow(clippy::result_large_err)] // the PistolError of the mock detector
+    fn test_os_short_timeout() {
+        static TIMEOUT: Mutex<Option<Duration>> = Mutex::new(None);
+        let detector: OsDetector = |_, _, _, _, timeout| {
+            *TIMEOUT.lock().unwrap() = timeout;
+            Ok(PistolOsDetects::new())
+        };
+        let targets = vec![Target::new(Ipv4Addr::new(192, 168, 5, 5).into(), None)];
+        detect_reports(detector, &targets, 3, 1.0, true, 2).unwrap();
+        assert_eq!(
+            *TIMEOUT.lock().unwrap(),
+            Some(Duration::from_secs_f64(OS_SHORT_TIMEOUT))
+        );
+        detect_reports(detector, &targets, 3, 1.0, false, 2).unwrap();
+        assert_eq!(*TIMEOUT.lock().unwrap(), Some(Duration::from_secs(1)));
+        // the shorter timeout is kept
+        detect_reports(detector, &targets, 3, 0.1, true, 2).unwrap();
+        assert_eq!(*TIMEOUT.lock().unwrap(), Some(Duration::from_secs_f64(0.1)));
+    }
 }
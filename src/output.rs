@@ -0,0 +1,837 @@
+/// output formatting
+///
+/// The json schema (the keys are snake_case and stable across the releases,
+/// any incompatible change bumps `SCHEMA_VERSION`):
+/// - json: `{"schema_version": 1, "header": {..}, "records": [..]}`
+/// - jsonl: the first line is `{"schema_version": 1, "header": {..}}`, then one record per line,
+///   and `{"end": ..}` as the last line when the header is written
+/// - header: `command_line`, `targets`, `ports`, `method`, `timeout`, `threads`, `start`, `end` (json only)
+/// - the `start` and `end` timestamps are RFC 3339 with the local timezone offset
+/// - host record: `addr`, `status`, `rtt_seconds`, `mac` (optional), `ouis` (optional)
+/// - port record: `addr`, `port`, `protocol`, `status`, `rtt_seconds`, `rtt_stddev_seconds` (optional), `ttl` (optional), `window` (optional), `reason` (optional), `service` (optional)
+/// - badsum record: `addr`, `port`, `protocol`, `responded`, `rtt_seconds`
+/// - protocol record: `addr`, `protocol`, `name`, `status`, `rtt_seconds`
+/// - os record: `addr`, `candidates` (`rank`, `name`, `accuracy`, `cpe`), `confidence` (optional), `rtt_seconds`
+/// - trace record: `addr`, `hops` (`ttl`, `addr`, `rtt_seconds`)
+/// - every record also has the `method` that produced it (such as `tcp_syn`, `icmp_echo` and `mac`)
+use chrono::DateTime;
+use chrono::Local;
+use clap::ValueEnum;
+use pistol::Target;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::Write;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::sync::LazyLock;
+use std::sync::Mutex;
+
+use crate::InfoShow;
+use crate::syslog::Syslog;
+
+/// The version of the json output schema.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human readable lines with the starting banner and summary tail.
+    Text,
+    /// A single JSON object (with the records array) printed when the scan is done.
+    Json,
+    /// One JSON object per line, flushed as soon as each record is ready.
+    Jsonl,
+    /// Port scanning only: one host per line with the O/C/F cell of each port (a few ports, the same for every host).
+    Matrix,
+    /// Host discovery only: the aligned columns (address, status, rtt, mac and vendor) with the colored status.
+    Table,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HostRecord {
+    pub addr: String,
+    pub status: String,
+    #[serde(rename = "rtt_seconds")]
+    pub cost: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mac: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ouis: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PortRecord {
+    pub addr: String,
+    pub port: u16,
+    pub protocol: String,
+    pub status: String,
+    /// The median rtt when the port was probed several times (--samples).
+    #[serde(rename = "rtt_seconds")]
+    pub cost: f64,
+    #[serde(rename = "rtt_stddev_seconds", skip_serializing_if = "Option::is_none")]
+    pub rtt_stddev: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service: Option<String>,
+}
+
+#[cfg(test)]
+impl PortRecord {
+    pub fn new(addr: &str, port: u16, status: &str) -> PortRecord {
+        PortRecord {
+            addr: addr.to_string(),
+            port,
+            protocol: String::from("tcp"),
+            status: status.to_string(),
+            cost: 0.01,
+            rtt_stddev: None,
+            ttl: None,
+            window: None,
+            reason: None,
+            service: None,
+        }
+    }
+}
+
+/// The result of one bad checksum probe (--badsum).
+#[derive(Debug, Clone, Serialize)]
+pub struct BadsumRecord {
+    pub addr: String,
+    pub port: u16,
+    pub protocol: String,
+    pub responded: bool,
+    #[serde(rename = "rtt_seconds")]
+    pub cost: f64,
+}
+
+/// The result of one ip protocol probe (ip protocol scan).
+#[derive(Debug, Clone, Serialize)]
+pub struct ProtoRecord {
+    pub addr: String,
+    pub protocol: u8,
+    pub name: String,
+    pub status: String,
+    #[serde(rename = "rtt_seconds")]
+    pub cost: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OsCandidate {
+    pub rank: usize,
+    pub name: String,
+    pub accuracy: f64,
+    pub cpe: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OsRecord {
+    pub addr: String,
+    pub candidates: Vec<OsCandidate>,
+    /// Such as `low` with --os-short-timeout.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<String>,
+    #[serde(rename = "rtt_seconds")]
+    pub cost: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HopRecord {
+    pub ttl: u8,
+    pub addr: Option<String>,
+    #[serde(rename = "rtt_seconds")]
+    pub cost: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TraceRecord {
+    pub addr: String,
+    pub hops: Vec<HopRecord>,
+}
+
+/// The scan parameters, written once before the results so an archived log
+/// still tells which flags produced it.
+#[derive(Debug, Clone, Serialize)]
+pub struct ScanHeader {
+    pub command_line: String,
+    pub targets: usize,
+    pub ports: usize,
+    pub method: String,
+    pub timeout: f64,
+    pub threads: usize,
+    pub start: String,
+    /// Set when the results are written.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end: Option<String>,
+}
+
+/// Such as `2026-10-17T09:30:00.123456789+08:00`.
+pub fn rfc3339_now() -> String {
+    let now: DateTime<Local> = Local::now();
+    now.to_rfc3339()
+}
+
+impl ScanHeader {
+    pub fn new(targets: &[Target], method: &str, timeout: f64, threads: usize) -> ScanHeader {
+        let command_line: Vec<String> = std::env::args().collect();
+        let ports = targets.iter().map(|t| t.ports.len()).sum();
+        ScanHeader {
+            command_line: command_line.join(" "),
+            targets: targets.len(),
+            ports,
+            method: method.to_string(),
+            timeout,
+            threads,
+            start: rfc3339_now(),
+            end: None,
+        }
+    }
+    /// The comment-prefixed line used by the text format.
+    pub fn text_line(&self) -> String {
+        format!(
+            "# command={:?} targets={} ports={} method={} timeout={:.2} threads={} start={}",
+            self.command_line,
+            self.targets,
+            self.ports,
+            self.method,
+            self.timeout,
+            self.threads,
+            self.start
+        )
+    }
+}
+
+static SCAN_HEADER: LazyLock<Mutex<Option<ScanHeader>>> = LazyLock::new(|| Mutex::new(None));
+
+/// Set the header written by the next stdout emitter,
+/// later emitters (such as the traceroute after host discovery) do not repeat it.
+pub fn set_header(header: ScanHeader) {
+    let mut scan_header = SCAN_HEADER.lock().expect("try lock SCAN_HEADER failed");
+    *scan_header = Some(header);
+}
+
+static RAW_DEBUG: LazyLock<Mutex<bool>> = LazyLock::new(|| Mutex::new(false));
+
+/// Dump the pistol result objects (--raw-debug) for troubleshooting.
+pub fn set_raw_debug(raw_debug: bool) {
+    let mut raw = RAW_DEBUG.lock().expect("try lock RAW_DEBUG failed");
+    *raw = raw_debug;
+}
+
+static QUIET: LazyLock<Mutex<bool>> = LazyLock::new(|| Mutex::new(false));
+
+/// The stdout emitters only write the results (--quiet).
+pub fn set_quiet(quiet: bool) {
+    let mut q = QUIET.lock().expect("try lock QUIET failed");
+    *q = quiet;
+}
+
+static SYSLOG: LazyLock<Mutex<Option<Syslog>>> = LazyLock::new(|| Mutex::new(None));
+
+/// Every stdout emitter also sends its records to the syslog (--syslog).
+pub fn set_syslog(syslog: Option<Syslog>) {
+    let mut s = SYSLOG.lock().expect("try lock SYSLOG failed");
+    *s = syslog;
+}
+
+/// One result handed to the output sinks.
+pub struct Record<'a> {
+    /// The text line with the method, None when the result is kept out of the text output.
+    pub line: Option<&'a str>,
+    /// The json record with the method.
+    pub value: &'a serde_json::Value,
+}
+
+/// The outputs written next to the formatted stdout (such as --open-file and --syslog),
+/// every record emitted by hd, ps and od goes to all of them.
+pub trait OutputSink {
+    fn write_record(&mut self, record: &Record);
+    /// Called once when the scan is done.
+    fn finish(&mut self) {}
+}
+
+/// The file only receiving the open port lines (--open-file) next to the full output,
+/// the clones share the same file.
+#[derive(Clone)]
+pub struct OpenSink {
+    writer: Arc<Mutex<Box<dyn Write + Send>>>,
+}
+
+impl OpenSink {
+    pub fn new(writer: Box<dyn Write + Send>) -> OpenSink {
+        OpenSink {
+            writer: Arc::new(Mutex::new(writer)),
+        }
+    }
+    pub fn create(filename: &str) -> std::io::Result<OpenSink> {
+        let fp = File::create(filename)?;
+        Ok(OpenSink::new(Box::new(fp)))
+    }
+    fn write_line(&self, line: &str) {
+        let mut writer = self.writer.lock().expect("lock open file failed");
+        writeln!(writer, "{}", line).expect("write open file failed");
+        writer.flush().expect("flush open file failed");
+    }
+}
+
+impl OutputSink for OpenSink {
+    /// Only the port records have the port, the open protocols are not written.
+    fn write_record(&mut self, record: &Record) {
+        if let Some(line) = record.line
+            && record.value.get("port").is_some()
+            && record.value["status"] == "open"
+        {
+            self.write_line(line);
+        }
+    }
+}
+
+static OPEN_SINK: LazyLock<Mutex<Option<OpenSink>>> = LazyLock::new(|| Mutex::new(None));
+
+/// Every stdout emitter also writes its open ports to the file (--open-file).
+pub fn set_open_sink(open_sink: Option<OpenSink>) {
+    let mut s = OPEN_SINK.lock().expect("try lock OPEN_SINK failed");
+    *s = open_sink;
+}
+
+fn write_raw_debug<W: Write, R: Debug>(writer: &mut W, enabled: bool, report: &R) -> bool {
+    if enabled {
+        writeln!(writer, "{:#?}", report).expect("write raw debug failed");
+    }
+    enabled
+}
+
+/// The dump goes to stderr, so the json and jsonl stdout stay machine readable.
+pub fn raw_debug<R: Debug>(report: &R) {
+    let enabled = *RAW_DEBUG.lock().expect("lock RAW_DEBUG failed");
+    write_raw_debug(&mut std::io::stderr(), enabled, report);
+}
+
+/// Pistol reports the probes it failed to send as the error status,
+/// on the high concurrency scans it usually means the system ran out of the ephemeral ports or the socket buffers.
+pub fn send_failure_warning(failed: usize) -> Option<String> {
+    if failed == 0 {
+        return None;
+    }
+    Some(format!(
+        "{} probes failed to send (resource limits); results may be incomplete, lower --nt or --max-probe-parallelism",
+        failed
+    ))
+}
+
+/// The requested targets that got no report at all (such as the ones pistol dropped silently),
+/// listed as `192.168.1.1 -> no data` in the verbose mode.
+pub fn no_data_lines<'a, I: IntoIterator<Item = &'a IpAddr>>(
+    targets: &[Target],
+    reported: I,
+) -> Vec<String> {
+    let reported: Vec<&IpAddr> = reported.into_iter().collect();
+    let mut lines = Vec::new();
+    let mut seen = Vec::new();
+    for t in targets {
+        if !reported.contains(&&t.addr) && !seen.contains(&t.addr) {
+            seen.push(t.addr);
+            lines.push(format!("{} -> no data", t.addr));
+        }
+    }
+    lines
+}
+
+/// The summary of one finished scan.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ScanOutcome {
+    /// The hosts that are up (host discovery only).
+    pub up_addrs: Vec<IpAddr>,
+    /// The number of the results found (the hosts up, the open ports or the detected hosts).
+    pub found: usize,
+    /// The status of each scanned port (port scanning only), compared between the --repeat rounds.
+    pub port_status: BTreeMap<(IpAddr, u16), String>,
+}
+
+/// Collects the scan results and writes them out in the selected format.
+/// In jsonl mode every record is written and flushed immediately,
+/// so a downstream process reading the pipe can react to each result.
+pub struct Emitter<W: Write> {
+    format: OutputFormat,
+    writer: W,
+    header: Option<ScanHeader>,
+    /// The scan method appended to each result, such as `tcp_syn`.
+    method: Option<String>,
+    sinks: Vec<Box<dyn OutputSink>>,
+    info: InfoShow,
+    preamble_written: bool,
+    lines: Vec<String>,
+    records: Vec<serde_json::Value>,
+}
+
+impl Emitter<Box<dyn Write>> {
+    /// With `--syslog-only` nothing is written to stdout.
+    pub fn stdout(format: OutputFormat) -> Emitter<Box<dyn Write>> {
+        let syslog = SYSLOG.lock().expect("try lock SYSLOG failed").clone();
+        let writer: Box<dyn Write> = match &syslog {
+            Some(syslog) if syslog.only => Box::new(std::io::sink()),
+            _ => Box::new(std::io::stdout()),
+        };
+        let quiet = *QUIET.lock().expect("lock QUIET failed");
+        let emitter = Emitter::new(format, writer).with_quiet(quiet);
+        let emitter = match syslog {
+            Some(syslog) => emitter.with_sink(Box::new(syslog)),
+            None => emitter,
+        };
+        let open_sink = OPEN_SINK.lock().expect("try lock OPEN_SINK failed").clone();
+        let emitter = match open_sink {
+            Some(open_sink) => emitter.with_sink(Box::new(open_sink)),
+            None => emitter,
+        };
+        let header = SCAN_HEADER
+            .lock()
+            .expect("try lock SCAN_HEADER failed")
+            .take();
+        match header {
+            Some(header) => emitter.with_header(header),
+            None => emitter,
+        }
+    }
+}
+
+impl<W: Write> Emitter<W> {
+    pub fn new(format: OutputFormat, writer: W) -> Emitter<W> {
+        Emitter {
+            format,
+            writer,
+            header: None,
+            method: None,
+            sinks: Vec::new(),
+            info: InfoShow::default(),
+            preamble_written: false,
+            lines: Vec::new(),
+            records: Vec::new(),
+        }
+    }
+    /// Text: a `#` line after the starting banner.
+    /// Json: the `header` object next to the `records` array.
+    /// Jsonl: in the first line with the schema version.
+    pub fn with_header(mut self, header: ScanHeader) -> Emitter<W> {
+        self.header = Some(header);
+        self
+    }
+    /// Text: ` [tcp_syn]` at the end of the first line of each result.
+    /// Json and jsonl: the `method` field of each record.
+    pub fn with_method(mut self, method: &str) -> Emitter<W> {
+        self.method = Some(method.to_string());
+        self
+    }
+    /// Text: no starting banner, scan header or summary tail, only the result lines.
+    pub fn with_quiet(mut self, quiet: bool) -> Emitter<W> {
+        self.info = InfoShow::new(quiet);
+        self
+    }
+    /// Write each record to the sink as well, whatever the output format.
+    pub fn with_sink(mut self, sink: Box<dyn OutputSink>) -> Emitter<W> {
+        self.sinks.push(sink);
+        self
+    }
+    fn method_line(&self, line: String) -> String {
+        match &self.method {
+            Some(method) => match line.split_once("\n") {
+                Some((first, rest)) => format!("{} [{}]\n{}", first, method, rest),
+                None => format!("{} [{}]", line, method),
+            },
+            None => line,
+        }
+    }
+    fn method_record<R: Serialize>(&self, record: &R) -> serde_json::Value {
+        let mut value = serde_json::to_value(record).expect("serialize record failed");
+        if let Some(method) = &self.method
+            && let Some(map) = value.as_object_mut()
+        {
+            map.insert(String::from("method"), method.clone().into());
+        }
+        value
+    }
+    fn preamble(&self) -> serde_json::Map<String, serde_json::Value> {
+        let mut map = serde_json::Map::new();
+        map.insert(String::from("schema_version"), SCHEMA_VERSION.into());
+        if let Some(header) = &self.header {
+            let value = serde_json::to_value(header).expect("serialize header failed");
+            map.insert(String::from("header"), value);
+        }
+        map
+    }
+    /// The jsonl first line, written before the first record.
+    fn write_preamble(&mut self) {
+        if !self.preamble_written {
+            let value = serde_json::Value::Object(self.preamble());
+            writeln!(self.writer, "{}", value).expect("write header failed");
+            self.preamble_written = true;
+        }
+    }
+    /// Emit one result, `line` is used by the text format and `record` by the json formats.
+    /// Pass `None` as line to keep the record out of the text output.
+    pub fn emit<R: Serialize>(&mut self, line: Option<String>, record: &R) {
+        if !self.sinks.is_empty() {
+            let value = self.method_record(record);
+            let sink_line = line.clone().map(|line| self.method_line(line));
+            let sink_record = Record {
+                line: sink_line.as_deref(),
+                value: &value,
+            };
+            for sink in &mut self.sinks {
+                sink.write_record(&sink_record);
+            }
+        }
+        match self.format {
+            OutputFormat::Text => {
+                if let Some(line) = line {
+                    let line = self.method_line(line);
+                    self.lines.push(line);
+                }
+            }
+            OutputFormat::Json => {
+                let value = self.method_record(record);
+                self.records.push(value);
+            }
+            OutputFormat::Jsonl => {
+                self.write_preamble();
+                let value = self.method_record(record);
+                writeln!(self.writer, "{}", value).expect("write record failed");
+                self.writer.flush().expect("flush output failed");
+            }
+            // the records are pivoted into the rows by the caller
+            OutputFormat::Matrix | OutputFormat::Table => (),
+        }
+    }
+    /// Lines that only make sense for humans, such as `other 3 hosts -> down`.
+    pub fn text(&mut self, line: String) {
+        if self.format == OutputFormat::Text {
+            self.lines.push(line);
+        }
+    }
+    /// The rows of the matrix and table formats, the other formats only show the records.
+    pub fn rows(&mut self, lines: Vec<String>) {
+        if matches!(self.format, OutputFormat::Matrix | OutputFormat::Table) {
+            self.lines.extend(lines);
+        }
+    }
+    pub fn finish(mut self, tail: &str) -> W {
+        for sink in &mut self.sinks {
+            sink.finish();
+        }
+        match self.format {
+            OutputFormat::Text | OutputFormat::Matrix | OutputFormat::Table => {
+                if let Some(header) = &self.header
+                    && !self.info.quiet
+                {
+                    self.lines.insert(0, header.text_line());
+                    self.lines.push(format!("# end={}", rfc3339_now()));
+                }
+                let info = self.lines.join("\n");
+                self.info.write(&mut self.writer, &info, tail);
+            }
+            OutputFormat::Json => {
+                if let Some(header) = &mut self.header {
+                    header.end = Some(rfc3339_now());
+                }
+                let mut map = self.preamble();
+                let records = std::mem::take(&mut self.records);
+                map.insert(String::from("records"), records.into());
+                let s = serde_json::to_string_pretty(&map).expect("serialize records failed");
+                writeln!(self.writer, "{}", s).expect("write records failed");
+            }
+            OutputFormat::Jsonl => {
+                self.write_preamble();
+                if self.header.is_some() {
+                    let value = serde_json::json!({ "end": rfc3339_now() });
+                    writeln!(self.writer, "{}", value).expect("write end failed");
+                }
+            }
+        }
+        self.writer.flush().expect("flush output failed");
+        self.writer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hd::HostDiscoveryMethod;
+    use crate::od::OS_DETECT_METHOD;
+    use crate::ps::PortScanningMethod;
+    use crate::tr::TRACEROUTE_METHOD;
+    #[test]
+    fn test_raw_debug() {
+        let report = vec![(IpAddr::from([192, 168, 5, 5]), 22u16)];
+        let mut buff = Vec::new();
+        assert!(!write_raw_debug(&mut buff, false, &report));
+        assert!(buff.is_empty());
+        assert!(write_raw_debug(&mut buff, true, &report));
+        let dump = String::from_utf8(buff).unwrap();
+        assert_eq!(dump, format!("{:#?}\n", report));
+        assert!(dump.contains("192.168.5.5"));
+    }
+    #[test]
+    fn test_jsonl_lines() {
+        let mut emitter = Emitter::new(OutputFormat::Jsonl, Vec::new());
+        for port in [22, 80, 443] {
+            let record = PortRecord::new("192.168.5.5", port, "open");
+            emitter.emit(Some(format!("192.168.5.5:{}/tcp -> open", port)), &record);
+        }
+        emitter.text(String::from("other 10 ports -> closed"));
+        let buf = emitter.finish("pslmap done");
+        let output = String::from_utf8(buf).unwrap();
+
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 4);
+        let value: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(value["schema_version"], SCHEMA_VERSION);
+        for line in &lines[1..] {
+            let value: serde_json::Value = serde_json::from_str(line).unwrap();
+            assert_eq!(value["addr"], "192.168.5.5");
+        }
+    }
+    #[test]
+    fn test_header() {
+        let targets = vec![Target::new(
+            std::net::Ipv4Addr::new(192, 168, 5, 5).into(),
+            Some(vec![22, 80, 443]),
+        )];
+        let header = ScanHeader::new(&targets, "TcpSyn", 1.5, 8);
+
+        let emitter = Emitter::new(OutputFormat::Text, Vec::new()).with_header(header.clone());
+        let buf = emitter.finish("pslmap done");
+        let output = String::from_utf8(buf).unwrap();
+        let line = output.lines().nth(1).unwrap();
+        assert!(line.starts_with("# "));
+        assert!(line.contains("method=TcpSyn"));
+        assert!(line.contains("targets=1 ports=3"));
+
+        let emitter = Emitter::new(OutputFormat::Json, Vec::new()).with_header(header.clone());
+        let buf = emitter.finish("pslmap done");
+        let value: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(value["header"]["method"], "TcpSyn");
+        assert_eq!(value["header"]["targets"], 1);
+        assert_eq!(value["header"]["threads"], 8);
+
+        let emitter = Emitter::new(OutputFormat::Jsonl, Vec::new()).with_header(header);
+        let buf = emitter.finish("pslmap done");
+        let output = String::from_utf8(buf).unwrap();
+        let value: serde_json::Value =
+            serde_json::from_str(output.lines().next().unwrap()).unwrap();
+        assert_eq!(value["header"]["method"], "TcpSyn");
+    }
+    #[test]
+    fn test_timestamps() {
+        let targets = vec![Target::new(IpAddr::from([192, 168, 5, 5]), Some(vec![22]))];
+        let header = ScanHeader::new(&targets, "TcpSyn", 1.5, 8);
+        let rfc3339 = |s: &str| DateTime::parse_from_rfc3339(s).unwrap();
+
+        let emitter = Emitter::new(OutputFormat::Json, Vec::new()).with_header(header.clone());
+        let value: serde_json::Value = serde_json::from_slice(&emitter.finish("")).unwrap();
+        let start = rfc3339(value["header"]["start"].as_str().unwrap());
+        let end = rfc3339(value["header"]["end"].as_str().unwrap());
+        assert!(start <= end);
+
+        let emitter = Emitter::new(OutputFormat::Jsonl, Vec::new()).with_header(header.clone());
+        let output = String::from_utf8(emitter.finish("")).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 2);
+        let first: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert!(first["header"].get("end").is_none());
+        let last: serde_json::Value = serde_json::from_str(lines[1]).unwrap();
+        rfc3339(last["end"].as_str().unwrap());
+
+        let emitter = Emitter::new(OutputFormat::Text, Vec::new()).with_header(header);
+        let output = String::from_utf8(emitter.finish("pslmap done")).unwrap();
+        let end = output
+            .lines()
+            .find_map(|l| l.strip_prefix("# end="))
+            .unwrap();
+        rfc3339(end);
+    }
+    #[test]
+    fn test_quiet() {
+        let targets = vec![Target::new(IpAddr::from([192, 168, 5, 5]), Some(vec![22]))];
+        let header = ScanHeader::new(&targets, "TcpSyn", 1.5, 8);
+        let record = HostRecord {
+            addr: String::from("192.168.5.5"),
+            status: String::from("up"),
+            cost: 0.01,
+            mac: None,
+            ouis: None,
+        };
+        let line = String::from("192.168.5.5 -> up (0.01s)");
+
+        let mut emitter = Emitter::new(OutputFormat::Text, Vec::new())
+            .with_header(header.clone())
+            .with_quiet(true);
+        emitter.emit(Some(line.clone()), &record);
+        let buf = emitter.finish("pslmap done");
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "192.168.5.5 -> up (0.01s)\n"
+        );
+
+        // nothing at all without the results
+        let emitter = Emitter::new(OutputFormat::Text, Vec::new()).with_quiet(true);
+        assert!(emitter.finish("pslmap done").is_empty());
+
+        let mut emitter = Emitter::new(OutputFormat::Text, Vec::new()).with_header(header);
+        emitter.emit(Some(line), &record);
+        let output = String::from_utf8(emitter.finish("pslmap done")).unwrap();
+        assert!(output.starts_with("starting "));
+        assert!(output.ends_with("pslmap done\n"));
+    }
+    #[test]
+    fn test_record_method() {
+        let record = HostRecord {
+            addr: String::from("192.168.5.5"),
+            status: String::from("up"),
+            cost: 0.01,
+            mac: None,
+            ouis: None,
+        };
+        let methods = [
+            HostDiscoveryMethod::IcmpEcho.name(),
+            HostDiscoveryMethod::Mac.name(),
+            PortScanningMethod::TcpSyn.name(),
+            PortScanningMethod::IpProto.name(),
+            OS_DETECT_METHOD,
+            TRACEROUTE_METHOD,
+        ];
+        assert_eq!(
+            methods,
+            [
+                "icmp_echo",
+                "mac",
+                "tcp_syn",
+                "ip_proto",
+                "os_detect",
+                "traceroute"
+            ]
+        );
+        for method in methods {
+            let mut emitter = Emitter::new(OutputFormat::Text, Vec::new()).with_method(method);
+            emitter.emit(Some(String::from("192.168.5.5 -> up\n  detail")), &record);
+            let output = String::from_utf8(emitter.finish("pslmap done")).unwrap();
+            assert!(output.contains(&format!("192.168.5.5 -> up [{}]\n  detail", method)));
+
+            let mut emitter = Emitter::new(OutputFormat::Jsonl, Vec::new()).with_method(method);
+            emitter.emit(None, &record);
+            let output = String::from_utf8(emitter.finish("pslmap done")).unwrap();
+            let value: serde_json::Value =
+                serde_json::from_str(output.lines().nth(1).unwrap()).unwrap();
+            assert_eq!(value["method"], method);
+        }
+    }
+    #[test]
+    fn test_no_data_lines() {
+        let addr1: IpAddr = "192.168.5.5".parse().unwrap();
+        let addr2: IpAddr = "192.168.5.6".parse().unwrap();
+        let targets = vec![
+            Target::new(addr1, Some(vec![22])),
+            Target::new(addr2, Some(vec![22])),
+            Target::new(addr2, Some(vec![80])),
+        ];
+        let mut reported = BTreeMap::new();
+        reported.insert(addr1, ());
+        assert_eq!(
+            no_data_lines(&targets, reported.keys()),
+            vec!["192.168.5.6 -> no data"]
+        );
+        reported.insert(addr2, ());
+        assert!(no_data_lines(&targets, reported.keys()).is_empty());
+    }
+    #[test]
+    fn test_open_sink() {
+        struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+        impl Write for SharedBuf {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        let open_buf = Arc::new(Mutex::new(Vec::new()));
+        let open_sink = OpenSink::new(Box::new(SharedBuf(open_buf.clone())));
+        let mut emitter = Emitter::new(OutputFormat::Text, Vec::new())
+            .with_method("tcp_syn")
+            .with_sink(Box::new(open_sink));
+        for (port, status) in [(22, "open"), (80, "closed"), (443, "open")] {
+            let record = PortRecord::new("192.168.5.5", port, status);
+            let line = format!("192.168.5.5:{}/tcp -> {}", port, status);
+            emitter.emit(Some(line), &record);
+        }
+        let stdout = String::from_utf8(emitter.finish("pslmap done")).unwrap();
+        assert!(stdout.contains("192.168.5.5:80/tcp -> closed [tcp_syn]"));
+        assert!(stdout.contains("192.168.5.5:22/tcp -> open [tcp_syn]"));
+        let open_file = String::from_utf8(open_buf.lock().unwrap().clone()).unwrap();
+        assert_eq!(
+            open_file,
+            "192.168.5.5:22/tcp -> open [tcp_syn]\n192.168.5.5:443/tcp -> open [tcp_syn]\n"
+        );
+    }
+    #[test]
+    fn test_output_sinks() {
+        struct MemorySink {
+            records: Arc<Mutex<Vec<String>>>,
+        }
+        impl OutputSink for MemorySink {
+            fn write_record(&mut self, record: &Record) {
+                let line = record.line.unwrap_or("-").to_string();
+                let entry = format!("{} {}", line, record.value["method"]);
+                self.records.lock().unwrap().push(entry);
+            }
+            fn finish(&mut self) {
+                self.records.lock().unwrap().push(String::from("finish"));
+            }
+        }
+        let sinks: Vec<Arc<Mutex<Vec<String>>>> =
+            (0..2).map(|_| Arc::new(Mutex::new(Vec::new()))).collect();
+        let mut emitter = Emitter::new(OutputFormat::Json, Vec::new()).with_method("tcp_syn");
+        for records in &sinks {
+            emitter = emitter.with_sink(Box::new(MemorySink {
+                records: records.clone(),
+            }));
+        }
+        for (port, status) in [(22, "open"), (80, "closed")] {
+            let record = PortRecord::new("192.168.5.5", port, status);
+            // the record kept out of the text output still goes to the sinks
+            let line = (port == 22).then(|| format!("192.168.5.5:{}/tcp -> {}", port, status));
+            emitter.emit(line, &record);
+        }
+        emitter.finish("pslmap done");
+        for records in &sinks {
+            assert_eq!(
+                *records.lock().unwrap(),
+                vec![
+                    "192.168.5.5:22/tcp -> open [tcp_syn] \"tcp_syn\"",
+                    "- \"tcp_syn\"",
+                    "finish",
+                ]
+            );
+        }
+    }
+    #[test]
+    fn test_schema() {
+        let mut emitter = Emitter::new(OutputFormat::Json, Vec::new());
+        let record = PortRecord::new("192.168.5.5", 22, "open");
+        emitter.emit(None, &record);
+        let buf = emitter.finish("pslmap done");
+        let value: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(value["schema_version"], 1);
+
+        let record = value["records"][0].as_object().unwrap();
+        let mut keys: Vec<&str> = record.keys().map(|k| k.as_str()).collect();
+        keys.sort();
+        assert_eq!(
+            keys,
+            vec!["addr", "port", "protocol", "rtt_seconds", "status"]
+        );
+    }
+}
@@ -0,0 +1,98 @@
+/// the human readable scan plan (--explain)
+use pistol::Target;
+use std::collections::BTreeSet;
+
+use crate::hd::HostDiscoveryMethod;
+use crate::ps::MAX_ATTEMPTS;
+use crate::ps::PortScanningMethod;
+
+fn ports_count(targets: &[Target]) -> usize {
+    targets
+        .iter()
+        .flat_map(|t| t.ports.iter())
+        .collect::<BTreeSet<_>>()
+        .len()
+}
+
+fn probes_count(targets: &[Target]) -> usize {
+    targets.iter().map(|t| t.ports.len()).sum()
+}
+
+/// Such as `Will scan 254 hosts on 3 TCP ports using SYN scan, timeout 1.0s, 2 attempts, ~1524 probes estimated.`,
+/// the estimate is the upper bound (every probe gets no response and is sent again).
+pub fn scan_plan(targets: &[Target], ps_method: PortScanningMethod, timeout: f64) -> String {
+    let ports = match ps_method {
+        PortScanningMethod::Udp => "UDP ports",
+        PortScanningMethod::SctpInit => "SCTP ports",
+        PortScanningMethod::IpProto => "IP protocols",
+        _ => "TCP ports",
+    };
+    // the ip protocol scan is not retried
+    let attempts = match ps_method {
+        PortScanningMethod::IpProto => 1,
+        _ => MAX_ATTEMPTS,
+    };
+    let name = ps_method.name();
+    let name = name.strip_prefix("tcp_").unwrap_or(name).to_uppercase();
+    format!(
+        "Will scan {} hosts on {} {} using {} scan, timeout {:.1}s, {} attempts, ~{} probes estimated.",
+        targets.len(),
+        ports_count(targets),
+        ports,
+        name,
+        timeout,
+        attempts,
+        probes_count(targets) * attempts
+    )
+}
+
+/// Such as `Will probe 254 hosts using IcmpEcho+TcpSyn host discovery, timeout 1.0s, ~508 probes estimated.`
+pub fn discovery_plan(
+    targets: &[Target],
+    hd_methods: &[HostDiscoveryMethod],
+    timeout: f64,
+) -> String {
+    let names: Vec<String> = hd_methods.iter().map(|m| format!("{:?}", m)).collect();
+    format!(
+        "Will probe {} hosts using {} host discovery, timeout {:.1}s, ~{} probes estimated.",
+        targets.len(),
+        names.join("+"),
+        timeout,
+        targets.len() * hd_methods.len()
+    )
+}
+
+/// Such as `Will detect the os of 2 hosts, timeout 1.0s, top 3 guesses of each host.`
+pub fn os_plan(targets: &[Target], top_k: usize, timeout: f64) -> String {
+    format!(
+        "Will detect the os of {} hosts, timeout {:.1}s, top {} guesses of each host.",
+        targets.len(),
+        timeout,
+        top_k
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tp::TargetParser;
+    #[test]
+    fn test_scan_plan() {
+        let mut parser = TargetParser::new();
+        let targets = parser.target_from_input("192.168.1.0/24", Some(String::from("22,80,443")));
+        assert_eq!(
+            scan_plan(&targets, PortScanningMethod::TcpSyn, 1.0),
+            "Will scan 254 hosts on 3 TCP ports using SYN scan, timeout 1.0s, 2 attempts, ~1524 probes estimated."
+        );
+        let targets = parser.target_from_input("192.168.1.1-2", Some(String::from("53")));
+        assert_eq!(
+            scan_plan(&targets, PortScanningMethod::Udp, 0.5),
+            "Will scan 2 hosts on 1 UDP ports using UDP scan, timeout 0.5s, 2 attempts, ~4 probes estimated."
+        );
+        let methods = [HostDiscoveryMethod::IcmpEcho, HostDiscoveryMethod::TcpSyn];
+        assert_eq!(
+            discovery_plan(&targets, &methods, 1.0),
+            "Will probe 2 hosts using IcmpEcho+TcpSyn host discovery, timeout 1.0s, ~4 probes estimated."
+        );
+    }
+}
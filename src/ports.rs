@@ -0,0 +1,216 @@
+/// port presets
+use clap::ValueEnum;
+use pistol::TOP_1000_TCP_PORTS;
+use pistol::Target;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+
+/// The top 100 tcp ports ordered by frequency (most common first),
+/// same as the order of nmap `--top-ports 100`.
+pub const TOP_100_TCP_PORTS_BY_FREQUENCY: [u16; 100] = [
+    80, 23, 443, 21, 22, 25, 3389, 110, 445, 139, 143, 53, 135, 3306, 8080, 1723, 111, 995, 993,
+    5900, 1025, 587, 8888, 199, 1720, 465, 548, 113, 81, 6001, 10000, 514, 5060, 179, 1026, 2000,
+    8443, 8000, 32768, 554, 26, 1433, 49152, 2001, 515, 8008, 49154, 1027, 5666, 646, 5000, 5631,
+    631, 49153, 8081, 2049, 88, 79, 5800, 106, 2121, 1110, 49155, 6000, 513, 990, 5357, 427, 49156,
+    543, 544, 5101, 144, 7, 389, 8009, 3128, 444, 9999, 5009, 7070, 5190, 3000, 5432, 1900, 3986,
+    13, 1029, 9, 5051, 6646, 49157, 1028, 873, 1755, 2717, 4899, 9100, 119, 37,
+];
+
+/// Number of ports used by the `--fast` option (same as nmap -F).
+pub const FAST_PORTS_NUM: usize = 100;
+
+/// The ports of the targets without --ports, --top-ports, --fast or the ports of the target input,
+/// same as the nmap default (the top 1000 tcp ports), filled in by `empty_ports_fill`.
+pub const DEFAULT_PORTS: [u16; 1000] = TOP_1000_TCP_PORTS;
+
+/// Returns the top `n` most common tcp ports.
+/// The first 100 ports follow the frequency order,
+/// the rest are filled from the top 1000 table (which is sorted by port number),
+/// `n` above the table size is clamped to the whole table.
+pub fn top_ports(n: usize) -> Vec<u16> {
+    let n = n.min(TOP_1000_TCP_PORTS.len());
+    let mut ret: Vec<u16> = TOP_100_TCP_PORTS_BY_FREQUENCY
+        .iter()
+        .take(n)
+        .copied()
+        .collect();
+    for p in TOP_1000_TCP_PORTS {
+        if ret.len() >= n {
+            break;
+        }
+        if !ret.contains(&p) {
+            ret.push(p);
+        }
+    }
+    ret
+}
+
+/// The number of the top ports, such as `100` or `5%` of the top 1000 table (rounded up).
+pub fn top_ports_parser(top_str: &str) -> Result<usize, String> {
+    let top_str = top_str.trim();
+    let n = match top_str.strip_suffix("%") {
+        Some(percent_str) => {
+            let percent: f64 = percent_str
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid top ports percentage {}", top_str))?;
+            // a percentage out of (0, 100] gives a count out of the bound below
+            (TOP_1000_TCP_PORTS.len() as f64 * percent / 100.0).ceil() as usize
+        }
+        None => top_str
+            .parse()
+            .map_err(|_| format!("invalid top ports {}", top_str))?,
+    };
+    if n == 0 || n > TOP_1000_TCP_PORTS.len() {
+        return Err(format!(
+            "invalid top ports {}: it must be in (0, {}] or (0%, 100%]",
+            top_str,
+            TOP_1000_TCP_PORTS.len()
+        ));
+    }
+    Ok(n)
+}
+
+/// The service categories used as `@name` in the ports option (such as `-p @web,9000`).
+pub const PORT_CATEGORIES: [(&str, &[u16]); 3] = [
+    ("web", &[80, 443, 8080, 8443]),
+    ("db", &[3306, 5432, 1433, 27017, 6379]),
+    ("mail", &[25, 110, 143, 465, 587, 993, 995]),
+];
+
+/// The ports of the category `name` (without the `@`).
+pub fn port_category(name: &str) -> Result<Vec<u16>, String> {
+    match PORT_CATEGORIES.iter().find(|(n, _)| *n == name) {
+        Some((_, ports)) => Ok(ports.to_vec()),
+        None => {
+            let valid: Vec<String> = PORT_CATEGORIES
+                .iter()
+                .map(|(n, _)| format!("@{}", n))
+                .collect();
+            Err(format!(
+                "unknown port category @{} (valid: {})",
+                name,
+                valid.join(", ")
+            ))
+        }
+    }
+}
+
+/// Shuffle the ports of each target so they are not probed in sequential order,
+/// the same seed always produces the same permutation.
+pub fn shuffle_ports(targets: &mut [Target], seed: Option<u64>) {
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_os_rng(),
+    };
+    for t in targets {
+        t.ports.shuffle(&mut rng);
+    }
+}
+
+/// What the port scan and the os detection do with the targets without ports (--empty-ports).
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum EmptyPorts {
+    /// Scan the DEFAULT_PORTS (the top 1000 ports, same as nmap).
+    Default,
+    /// Stop with the error before any probe is sent.
+    Error,
+    /// Scan the top 100 ports (same as --fast).
+    Top100,
+}
+
+/// The targets without ports (no --ports, --top-ports or the ports of the target input)
+/// get the DEFAULT_PORTS or the top 100 ports, or stop the scan.
+pub fn empty_ports_fill(targets: &mut [Target], empty_ports: EmptyPorts) -> Result<(), String> {
+    if targets.iter().all(|t| !t.ports.is_empty()) {
+        return Ok(());
+    }
+    match empty_ports {
+        EmptyPorts::Default => {
+            for t in targets.iter_mut().filter(|t| t.ports.is_empty()) {
+                t.ports = DEFAULT_PORTS.to_vec();
+            }
+            Ok(())
+        }
+        EmptyPorts::Error => Err(String::from(
+            "no ports specified; use --ports or --top-ports",
+        )),
+        EmptyPorts::Top100 => {
+            for t in targets.iter_mut().filter(|t| t.ports.is_empty()) {
+                t.ports = top_ports(FAST_PORTS_NUM);
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+    #[test]
+    fn test_empty_ports() {
+        let mut targets = vec![
+            Target::new(Ipv4Addr::new(192, 168, 5, 5).into(), Some(vec![22])),
+            Target::new(Ipv4Addr::new(192, 168, 5, 6).into(), None),
+        ];
+        assert_eq!(
+            empty_ports_fill(&mut targets, EmptyPorts::Error),
+            Err(String::from(
+                "no ports specified; use --ports or --top-ports"
+            ))
+        );
+        assert!(empty_ports_fill(&mut targets, EmptyPorts::Top100).is_ok());
+        // the ports of the target input are kept
+        assert_eq!(targets[0].ports, vec![22]);
+        assert_eq!(targets[1].ports, top_ports(FAST_PORTS_NUM));
+        assert!(empty_ports_fill(&mut targets, EmptyPorts::Error).is_ok());
+    }
+    #[test]
+    fn test_shuffle_ports() {
+        let ports: Vec<u16> = (1..=100).collect();
+        let new_targets = || {
+            vec![Target::new(
+                Ipv4Addr::new(192, 168, 5, 5).into(),
+                Some(ports.clone()),
+            )]
+        };
+
+        let mut targets_1 = new_targets();
+        let mut targets_2 = new_targets();
+        shuffle_ports(&mut targets_1, Some(42));
+        shuffle_ports(&mut targets_2, Some(42));
+        assert_eq!(targets_1[0].ports, targets_2[0].ports);
+        assert_ne!(targets_1[0].ports, ports);
+
+        let mut sorted = targets_1[0].ports.clone();
+        sorted.sort();
+        assert_eq!(sorted, ports);
+    }
+    #[test]
+    fn test_top_ports_parser() {
+        assert_eq!(TOP_1000_TCP_PORTS.len(), 1000);
+        assert_eq!(top_ports_parser("10%"), Ok(100));
+        assert_eq!(top_ports(top_ports_parser("10%").unwrap()), top_ports(100));
+        assert_eq!(top_ports_parser("0.15%"), Ok(2));
+        assert_eq!(top_ports_parser("100%"), Ok(1000));
+        assert_eq!(top_ports_parser("20"), Ok(20));
+        assert!(top_ports_parser("5000").is_err());
+        assert!(top_ports_parser("0").is_err());
+        assert_eq!(top_ports(5000).len(), 1000);
+        assert!(top_ports_parser("0%").is_err());
+        assert!(top_ports_parser("101%").is_err());
+        assert!(top_ports_parser("x%").is_err());
+        assert!(top_ports_parser("-5%").is_err());
+        // the count and the percentage share the same bound
+        assert_eq!(top_ports_parser("5%"), top_ports_parser("50"));
+        assert_eq!(top_ports_parser("1000"), top_ports_parser("100%"));
+        assert_eq!(
+            top_ports_parser("500%"),
+            Err(String::from(
+                "invalid top ports 500%: it must be in (0, 1000] or (0%, 100%]"
+            ))
+        );
+    }
+}
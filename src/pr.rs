@@ -0,0 +1,420 @@
+/// ip protocol scan (same as nmap -sO)
+use pistol::Target;
+use pistol::layer::ICMP_HEADER_SIZE;
+use pistol::layer::IPV4_HEADER_SIZE;
+use pistol::layer::Layer3Match;
+use pistol::layer::LayerMatch;
+use pistol::layer::TCP_HEADER_SIZE;
+use pistol::layer::UDP_HEADER_SIZE;
+use pistol::layer::infer_addr;
+use pistol::layer::layer3_ipv4_send;
+use pnet::packet::Packet;
+use pnet::packet::icmp;
+use pnet::packet::icmp::IcmpCode;
+use pnet::packet::icmp::IcmpPacket;
+use pnet::packet::icmp::IcmpTypes;
+use pnet::packet::icmp::MutableIcmpPacket;
+use pnet::packet::ip::IpNextHeaderProtocol;
+use pnet::packet::ip::IpNextHeaderProtocols;
+use pnet::packet::ipv4;
+use pnet::packet::ipv4::Ipv4Flags;
+use pnet::packet::ipv4::Ipv4Packet;
+use pnet::packet::ipv4::MutableIpv4Packet;
+use pnet::packet::tcp;
+use pnet::packet::tcp::MutableTcpPacket;
+use pnet::packet::tcp::TcpFlags;
+use pnet::packet::udp;
+use pnet::packet::udp::MutableUdpPacket;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::SRC_ADDR;
+use crate::output::Emitter;
+use crate::output::OutputFormat;
+use crate::output::ProtoRecord;
+use crate::output::ScanOutcome;
+use crate::ps::PortScanningMethod;
+
+const TTL: u8 = 64;
+/// Same as nmap, the udp probe is sent to an unlikely port.
+const UDP_PROBE_PORT: u16 = 40125;
+const MAX_ATTEMPTS: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProtoStatus {
+    Open,
+    Closed,
+    Filtered,
+    OpenOrFiltered,
+}
+
+impl fmt::Display for ProtoStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            ProtoStatus::Open => "open",
+            ProtoStatus::Closed => "closed",
+            ProtoStatus::Filtered => "filtered",
+            ProtoStatus::OpenOrFiltered => "open|filtered",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProtoReport {
+    pub addr: IpAddr,
+    pub protocol: u8,
+    pub status: ProtoStatus,
+    pub cost: Duration,
+}
+
+impl ProtoReport {
+    /// Such as `192.168.1.1/tcp(6) -> open`.
+    pub fn line(&self) -> String {
+        format!(
+            "{}/{}({}) -> {}",
+            self.addr,
+            proto_name(self.protocol),
+            self.protocol,
+            self.status
+        )
+    }
+}
+
+/// The keywords of the iana protocol numbers.
+pub fn proto_name(protocol: u8) -> &'static str {
+    match protocol {
+        0 => "hopopt",
+        1 => "icmp",
+        2 => "igmp",
+        3 => "ggp",
+        4 => "ipv4",
+        5 => "st",
+        6 => "tcp",
+        8 => "egp",
+        9 => "igp",
+        17 => "udp",
+        27 => "rdp",
+        33 => "dccp",
+        41 => "ipv6",
+        43 => "ipv6-route",
+        44 => "ipv6-frag",
+        46 => "rsvp",
+        47 => "gre",
+        50 => "esp",
+        51 => "ah",
+        58 => "ipv6-icmp",
+        59 => "ipv6-nonxt",
+        60 => "ipv6-opts",
+        88 => "eigrp",
+        89 => "ospf",
+        94 => "ipip",
+        103 => "pim",
+        108 => "ipcomp",
+        112 => "vrrp",
+        115 => "l2tp",
+        132 => "sctp",
+        136 => "udplite",
+        137 => "mpls-in-ip",
+        _ => "unknown",
+    }
+}
+
+/// The tcp, udp and icmp probes carry a valid header so the target can answer in the same protocol,
+/// the other protocols are sent with the empty payload.
+fn build_proto_packet(dst_ipv4: Ipv4Addr, src_ipv4: Ipv4Addr, protocol: u8) -> Vec<u8> {
+    let payload_size = match protocol {
+        1 => ICMP_HEADER_SIZE,
+        6 => TCP_HEADER_SIZE,
+        17 => UDP_HEADER_SIZE,
+        _ => 0,
+    };
+    let mut buff = vec![0u8; IPV4_HEADER_SIZE + payload_size];
+    let total_length = buff.len() as u16;
+    let mut ip_header = MutableIpv4Packet::new(&mut buff).expect("build ipv4 packet failed");
+    ip_header.set_version(4);
+    ip_header.set_header_length(5);
+    ip_header.set_source(src_ipv4);
+    ip_header.set_destination(dst_ipv4);
+    ip_header.set_total_length(total_length);
+    ip_header.set_identification(rand::random());
+    ip_header.set_flags(Ipv4Flags::DontFragment);
+    ip_header.set_ttl(TTL);
+    ip_header.set_next_level_protocol(IpNextHeaderProtocol(protocol));
+    let c = ipv4::checksum(&ip_header.to_immutable());
+    ip_header.set_checksum(c);
+
+    let payload = &mut buff[IPV4_HEADER_SIZE..];
+    match protocol {
+        1 => {
+            let mut icmp_header =
+                MutableIcmpPacket::new(payload).expect("build icmp packet failed");
+            icmp_header.set_icmp_type(IcmpTypes::EchoRequest);
+            icmp_header.set_icmp_code(IcmpCode(0));
+            let checksum = icmp::checksum(&icmp_header.to_immutable());
+            icmp_header.set_checksum(checksum);
+        }
+        6 => {
+            let mut tcp_header = MutableTcpPacket::new(payload).expect("build tcp packet failed");
+            tcp_header.set_source(rand::random_range(10000..=65535));
+            tcp_header.set_destination(80);
+            tcp_header.set_acknowledgement(rand::random());
+            tcp_header.set_flags(TcpFlags::ACK);
+            tcp_header.set_window(1024);
+            tcp_header.set_data_offset(5);
+            let checksum = tcp::ipv4_checksum(&tcp_header.to_immutable(), &src_ipv4, &dst_ipv4);
+            tcp_header.set_checksum(checksum);
+        }
+        17 => {
+            let mut udp_header = MutableUdpPacket::new(payload).expect("build udp packet failed");
+            udp_header.set_source(rand::random_range(10000..=65535));
+            udp_header.set_destination(UDP_PROBE_PORT);
+            udp_header.set_length(UDP_HEADER_SIZE as u16);
+            let checksum = udp::ipv4_checksum(&udp_header.to_immutable(), &src_ipv4, &dst_ipv4);
+            udp_header.set_checksum(checksum);
+        }
+        _ => (),
+    }
+    buff
+}
+
+/// Same as nmap, the icmp protocol unreachable means closed,
+/// the other icmp unreachable errors mean filtered and any other response means open.
+fn proto_status(protocol: u8, response: &[u8]) -> ProtoStatus {
+    let ipv4_packet = match Ipv4Packet::new(response) {
+        Some(ipv4_packet) if !response.is_empty() => ipv4_packet,
+        _ => return ProtoStatus::OpenOrFiltered,
+    };
+    if ipv4_packet.get_next_level_protocol() == IpNextHeaderProtocols::Icmp
+        && let Some(icmp_packet) = IcmpPacket::new(ipv4_packet.payload())
+        && icmp_packet.get_icmp_type() == IcmpTypes::DestinationUnreachable
+    {
+        match icmp_packet.get_icmp_code().0 {
+            2 => return ProtoStatus::Closed,
+            // the port unreachable means the udp probe reached the udp stack
+            3 if protocol == 17 => return ProtoStatus::Open,
+            0 | 1 | 3 | 9 | 10 | 13 => return ProtoStatus::Filtered,
+            _ => (),
+        }
+    }
+    ProtoStatus::Open
+}
+
+fn send_proto_probe(
+    dst_ipv4: Ipv4Addr,
+    src_ipv4: Ipv4Addr,
+    protocol: u8,
+    timeout: Option<Duration>,
+) -> (ProtoStatus, Duration) {
+    let packet = build_proto_packet(dst_ipv4, src_ipv4, protocol);
+    let layer3 = Layer3Match {
+        name: "ip protocol layer3",
+        layer2: None,
+        src_addr: Some(dst_ipv4.into()),
+        dst_addr: Some(src_ipv4.into()),
+    };
+    let layer_match = LayerMatch::Layer3Match(layer3);
+    match layer3_ipv4_send(
+        dst_ipv4,
+        src_ipv4,
+        &packet,
+        vec![layer_match],
+        timeout,
+        true,
+    ) {
+        Ok((ret, rtt)) => (proto_status(protocol, &ret), rtt),
+        Err(_) => (ProtoStatus::OpenOrFiltered, Duration::ZERO),
+    }
+}
+
+/// The target ports are the protocol numbers, all 256 protocols are scanned when no port is set.
+fn protocols_of(target: &Target) -> Vec<u8> {
+    if target.ports.is_empty() {
+        return (0..=255).collect();
+    }
+    let mut protocols = Vec::new();
+    for &port in &target.ports {
+        match u8::try_from(port) {
+            Ok(protocol) => protocols.push(protocol),
+            Err(_) => eprintln!("warning: skip the invalid ip protocol number {}", port),
+        }
+    }
+    protocols
+}
+
+/// Send one probe for each ip protocol, only IPv4 targets are supported.
+pub fn protocol_scan(
+    targets: &[Target],
+    timeout: Option<Duration>,
+    num_threads: usize,
+) -> Vec<ProtoReport> {
+    let src_addr = *SRC_ADDR.lock().expect("lock SRC_ADDR failed");
+    let mut probes = Vec::new();
+    for t in targets {
+        if let IpAddr::V4(dst_ipv4) = t.addr
+            && let Ok(Some(ia)) = infer_addr(t.addr, src_addr)
+            && let IpAddr::V4(src_ipv4) = ia.src_addr
+        {
+            for protocol in protocols_of(t) {
+                probes.push((dst_ipv4, src_ipv4, protocol));
+            }
+        } else {
+            eprintln!(
+                "warning: ip protocol scan only supports IPv4, skip {}",
+                t.addr
+            );
+        }
+    }
+
+    let chunk_size = probes.len().div_ceil(num_threads.max(1)).max(1);
+    std::thread::scope(|s| {
+        let handles: Vec<_> = probes
+            .chunks(chunk_size)
+            .map(|chunk| {
+                s.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|&(dst_ipv4, src_ipv4, protocol)| {
+                            let mut ret = (ProtoStatus::OpenOrFiltered, Duration::ZERO);
+                            for _ in 0..MAX_ATTEMPTS {
+                                ret = send_proto_probe(dst_ipv4, src_ipv4, protocol, timeout);
+                                if ret.0 != ProtoStatus::OpenOrFiltered {
+                                    break;
+                                }
+                            }
+                            ProtoReport {
+                                addr: dst_ipv4.into(),
+                                protocol,
+                                status: ret.0,
+                                cost: ret.1,
+                            }
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().expect("ip protocol thread panicked"))
+            .collect()
+    })
+}
+
+pub fn protocol_scanning(
+    targets: &[Target],
+    timeout: Option<Duration>,
+    num_threads: usize,
+    output_format: OutputFormat,
+) -> ScanOutcome {
+    let start = Instant::now();
+    let reports = protocol_scan(targets, timeout, num_threads);
+
+    // sorted
+    let mut btm: BTreeMap<(IpAddr, u8), ProtoReport> = BTreeMap::new();
+    for report in reports {
+        btm.insert((report.addr, report.protocol), report);
+    }
+
+    let mut protocols_open = 0;
+    let mut protocols_not_open = 0;
+    let mut emitter =
+        Emitter::stdout(output_format).with_method(PortScanningMethod::IpProto.name());
+    for report in btm.values() {
+        let line = match report.status {
+            ProtoStatus::Open => {
+                protocols_open += 1;
+                Some(format!(
+                    "{} ({:.2}s)",
+                    report.line(),
+                    report.cost.as_secs_f64()
+                ))
+            }
+            _ => {
+                protocols_not_open += 1;
+                None
+            }
+        };
+        let record = ProtoRecord {
+            addr: report.addr.to_string(),
+            protocol: report.protocol,
+            name: proto_name(report.protocol).to_string(),
+            status: report.status.to_string(),
+            cost: report.cost.as_secs_f64(),
+        };
+        emitter.emit(line, &record);
+    }
+    if protocols_not_open > 0 {
+        let line = format!(
+            "other {} protocols -> closed or filtered",
+            protocols_not_open
+        );
+        emitter.text(line);
+    }
+
+    let tail = format!(
+        "pslmap done: {} ip addresses ({} protocols open) scanned in {:.2} seconds",
+        targets.len(),
+        protocols_open,
+        start.elapsed().as_secs_f64()
+    );
+    emitter.finish(&tail);
+    ScanOutcome {
+        up_addrs: Vec::new(),
+        found: protocols_open,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_proto_reports() {
+        let target_ipv4 = Ipv4Addr::new(192, 168, 5, 5);
+        let addr = IpAddr::V4(target_ipv4);
+        let report = |protocol: u8, status: ProtoStatus| ProtoReport {
+            addr,
+            protocol,
+            status,
+            cost: Duration::from_millis(10),
+        };
+        let reports = [
+            report(1, ProtoStatus::Open),
+            report(6, ProtoStatus::Open),
+            report(17, ProtoStatus::OpenOrFiltered),
+            report(47, ProtoStatus::Closed),
+            report(132, ProtoStatus::Filtered),
+            report(253, ProtoStatus::Closed),
+        ];
+        let lines: Vec<String> = reports.iter().map(|r| r.line()).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "192.168.5.5/icmp(1) -> open",
+                "192.168.5.5/tcp(6) -> open",
+                "192.168.5.5/udp(17) -> open|filtered",
+                "192.168.5.5/gre(47) -> closed",
+                "192.168.5.5/sctp(132) -> filtered",
+                "192.168.5.5/unknown(253) -> closed",
+            ]
+        );
+
+        // the icmp protocol unreachable from the target
+        let local_ipv4 = Ipv4Addr::new(192, 168, 5, 3);
+        let mut response = build_proto_packet(local_ipv4, target_ipv4, 1);
+        {
+            let mut icmp_packet =
+                MutableIcmpPacket::new(&mut response[IPV4_HEADER_SIZE..]).unwrap();
+            icmp_packet.set_icmp_type(IcmpTypes::DestinationUnreachable);
+            icmp_packet.set_icmp_code(IcmpCode(2));
+        }
+        assert_eq!(proto_status(47, &response), ProtoStatus::Closed);
+        let tcp_response = build_proto_packet(local_ipv4, target_ipv4, 6);
+        assert_eq!(proto_status(6, &tcp_response), ProtoStatus::Open);
+        assert_eq!(proto_status(47, &[]), ProtoStatus::OpenOrFiltered);
+    }
+}
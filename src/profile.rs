@@ -0,0 +1,70 @@
+/// scan profiles
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+
+/// The default profiles file in the working directory.
+pub const DEFAULT_PROFILES_FILE: &str = "profiles.toml";
+
+/// One named profile, such as
+/// ```toml
+/// [quick]
+/// method = "syn"
+/// ports = "22,80,443"
+/// timeout = 0.5
+/// threads = 16
+/// ```
+/// The `method` is the port scanning method (syn, connect, fin, null, xmas, ack, window, maimon, udp and ipproto).
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub method: Option<String>,
+    pub ports: Option<String>,
+    pub timeout: Option<f64>,
+    pub threads: Option<usize>,
+}
+
+pub fn profiles_parser(content: &str) -> Result<BTreeMap<String, Profile>, String> {
+    toml::from_str(content).map_err(|e| format!("parse profiles failed: {}", e))
+}
+
+pub fn load_profile(filename: &str, name: &str) -> Result<Profile, String> {
+    let content =
+        fs::read_to_string(filename).map_err(|e| format!("read {} failed: {}", filename, e))?;
+    let mut profiles = profiles_parser(&content)?;
+    profiles
+        .remove(name)
+        .ok_or(format!("profile {} not found in {}", name, filename))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_profiles_parser() {
+        let content = r#"
+# my profiles
+[quick]
+method = "connect" # the full handshake
+ports = "22,80,443"
+timeout = 0.5 # seconds
+threads = 16
+
+[full]
+ports = "1-65535"
+timeout = 2
+"#;
+        let profiles = profiles_parser(content).unwrap();
+        assert_eq!(profiles.len(), 2);
+        let quick = &profiles["quick"];
+        assert_eq!(quick.method, Some(String::from("connect")));
+        assert_eq!(quick.ports, Some(String::from("22,80,443")));
+        assert_eq!(quick.timeout, Some(0.5));
+        assert_eq!(quick.threads, Some(16));
+        assert_eq!(profiles["full"].timeout, Some(2.0));
+        assert_eq!(profiles["full"].method, None);
+
+        assert!(profiles_parser("[quick]\nspeed = 1").is_err());
+        assert!(profiles_parser("timeout = 1").is_err());
+    }
+}
@@ -1,6 +1,5 @@
 /// port scanning
-use pistol::PistolLogger;
-use pistol::PistolRunner;
+use clap::ValueEnum;
 use pistol::Target;
 use pistol::scan::PortReport;
 use pistol::scan::PortStatus;
@@ -15,14 +14,42 @@ use pistol::tcp_window_scan;
 use pistol::tcp_xmas_scan;
 use pistol::udp_scan;
 use std::collections::BTreeMap;
+use std::collections::HashMap;
 use std::net::IpAddr;
 use std::net::Ipv4Addr;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
 use std::time::Duration;
 use std::time::Instant;
 
-use crate::InfoShow;
+use crate::SRC_ADDR;
+use crate::bs::badsum_scan;
+use crate::bs::badsum_supported;
+use crate::error::PslmapError;
+use crate::fp::Fingerprint;
+use crate::fp::fingerprint_supported;
+use crate::fp::probe_fingerprint;
+use crate::output::BadsumRecord;
+use crate::output::Emitter;
+use crate::output::OutputFormat;
+use crate::output::PortRecord;
+use crate::output::ScanOutcome;
+use crate::output::no_data_lines;
+use crate::output::raw_debug;
+use crate::output::send_failure_warning;
+use crate::pr::protocol_scanning;
+use crate::rtt::RttTimeout;
+use crate::services::service_name;
+use crate::sf::custom_flags_scan;
+use crate::sf::icmp_reason;
+use crate::sf::icmp_reply;
+use crate::stats::record_reports;
+use crate::stats::stats_tail;
+use crate::udp::udp_batch;
+use crate::udp::udp_batch_scan;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum PortScanningMethod {
     TcpSyn,
     TcpConnect,
@@ -32,29 +59,316 @@ pub enum PortScanningMethod {
     TcpAck,
     TcpWindow,
     TcpMaimon,
+    TcpCustom(u8),
     Udp,
+    /// Pistol has no sctp probes yet, so the scan fails as unsupported.
+    SctpInit,
     TcpIdle,
+    IpProto,
 }
 
-pub fn port_scanning(
+impl PortScanningMethod {
+    /// The method name of the output records.
+    pub fn name(&self) -> &'static str {
+        match self {
+            PortScanningMethod::TcpSyn => "tcp_syn",
+            PortScanningMethod::TcpConnect => "tcp_connect",
+            PortScanningMethod::TcpFin => "tcp_fin",
+            PortScanningMethod::TcpNull => "tcp_null",
+            PortScanningMethod::TcpXmas => "tcp_xmas",
+            PortScanningMethod::TcpAck => "tcp_ack",
+            PortScanningMethod::TcpWindow => "tcp_window",
+            PortScanningMethod::TcpMaimon => "tcp_maimon",
+            PortScanningMethod::TcpCustom(_) => "tcp_custom",
+            PortScanningMethod::Udp => "udp",
+            PortScanningMethod::SctpInit => "sctp_init",
+            PortScanningMethod::TcpIdle => "tcp_idle",
+            PortScanningMethod::IpProto => "ip_proto",
+        }
+    }
+}
+
+pub const MAX_ATTEMPTS: usize = 2;
+/// The most ports of the matrix output, the wider rows are hard to read.
+const MATRIX_MAX_PORTS: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum RetryPolicy {
+    /// Probe every port max attempts times and keep the first definitive response.
+    Always,
+    /// Probe the port again only when the previous attempt got no response (such as filtered).
+    OnNoResponse,
+}
+
+/// Filtered and the other guesses mean the probe got no reply.
+fn is_no_response(status: PortStatus) -> bool {
+    matches!(
+        status,
+        PortStatus::Filtered | PortStatus::OpenOrFiltered | PortStatus::ClosedOrFiltered
+    )
+}
+
+/// The reports of the scan with the probes sent again to each host by the retries of `retry_scan`.
+#[derive(Debug, Default)]
+struct ScanReports {
+    reports: Vec<PortReport>,
+    retries: BTreeMap<IpAddr, usize>,
+}
+
+impl ScanReports {
+    fn extend(&mut self, other: ScanReports) {
+        self.reports.extend(other.reports);
+        for (addr, n) in other.retries {
+            *self.retries.entry(addr).or_default() += n;
+        }
+    }
+}
+
+type PortRtts = BTreeMap<(IpAddr, u16), Vec<Duration>>;
+
+/// The data of one run shown next to the rtt in the port lines.
+#[derive(Debug, Clone, Default)]
+struct PortLineContext {
+    /// The dns lookup time of each hostname (--timing-includes-dns), added to the cost of its targets.
+    dns_times: HashMap<String, Duration>,
+    /// The probes sent again to each host (shown with -v).
+    retries: BTreeMap<IpAddr, usize>,
+    /// The rtts of each open port probed again (--samples), the first one is the rtt of the scan.
+    samples: PortRtts,
+}
+
+impl PortLineContext {
+    fn host_retries(&self, addr: IpAddr) -> usize {
+        self.retries.get(&addr).copied().unwrap_or(0)
+    }
+    fn sample_stats(&self, addr: IpAddr, port: u16) -> Option<(Duration, Duration)> {
+        self.samples.get(&(addr, port)).and_then(|s| rtt_stats(s))
+    }
+}
+
+/// The median and the standard deviation (population) of the rtts.
+fn rtt_stats(samples: &[Duration]) -> Option<(Duration, Duration)> {
+    if samples.is_empty() {
+        return None;
+    }
+    let mut secs: Vec<f64> = samples.iter().map(|s| s.as_secs_f64()).collect();
+    secs.sort_by(|a, b| a.total_cmp(b));
+    let mid = secs.len() / 2;
+    let median = if secs.len().is_multiple_of(2) {
+        (secs[mid - 1] + secs[mid]) / 2.0
+    } else {
+        secs[mid]
+    };
+    let mean = secs.iter().sum::<f64>() / secs.len() as f64;
+    let variance = secs.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / secs.len() as f64;
+    Some((
+        Duration::from_secs_f64(median),
+        Duration::from_secs_f64(variance.sqrt()),
+    ))
+}
+
+/// Probe the open ports `samples - 1` more times with the `scan`,
+/// only the probes that found the port open again add their rtt.
+fn rtt_samples<F: FnMut(&[Target]) -> Result<Vec<PortReport>, PslmapError>>(
+    reports: &[PortReport],
+    samples: usize,
+    mut scan: F,
+) -> Result<PortRtts, PslmapError> {
+    let mut ret = BTreeMap::new();
+    let mut open_ports: BTreeMap<IpAddr, Vec<u16>> = BTreeMap::new();
+    for report in reports
+        .iter()
+        .filter(|r| matches!(r.status, PortStatus::Open))
+    {
+        ret.insert((report.addr, report.port), vec![report.cost]);
+        open_ports.entry(report.addr).or_default().push(report.port);
+    }
+    let targets: Vec<Target> = open_ports
+        .into_iter()
+        .map(|(addr, ports)| Target::new(addr, Some(ports)))
+        .collect();
+    if !targets.is_empty() {
+        for _ in 1..samples {
+            for report in scan(&targets)? {
+                if matches!(report.status, PortStatus::Open)
+                    && let Some(rtts) = ret.get_mut(&(report.addr, report.port))
+                {
+                    rtts.push(report.cost);
+                }
+            }
+        }
+    }
+    Ok(ret)
+}
+
+/// Such as `(0.02s)`, or `(0.32s incl. dns 0.30s)` when the target came from the hostname,
+/// or `(median 0.021s ±0.003)` when the port was probed several times (--samples).
+fn cost_tag(report: &PortReport, context: &PortLineContext) -> String {
+    if let Some((median, stddev)) = context.sample_stats(report.addr, report.port) {
+        return format!(
+            "(median {:.3}s ±{:.3})",
+            median.as_secs_f64(),
+            stddev.as_secs_f64()
+        );
+    }
+    let dns = report
+        .origin
+        .as_ref()
+        .and_then(|o| context.dns_times.get(o));
+    match dns {
+        Some(dns) => format!(
+            "({:.2}s incl. dns {:.2}s)",
+            (report.cost + *dns).as_secs_f64(),
+            dns.as_secs_f64()
+        ),
+        None => format!("({:.2}s)", report.cost.as_secs_f64()),
+    }
+}
+
+/// Such as `(1 retry)` or `(2 retries)`, none when the host answered the first probes.
+fn retries_tag(retries: usize) -> Option<String> {
+    match retries {
+        0 => None,
+        1 => Some(String::from("(1 retry)")),
+        n => Some(format!("({} retries)", n)),
+    }
+}
+
+/// Run the `scan` up to `max_attempts` times, each time with one attempt only,
+/// the ports of the next attempt are chosen by the retry policy.
+/// The port is not probed again once the total cost of its probes reaches the `probe_budget`.
+fn retry_scan<F: FnMut(&[Target]) -> Result<Vec<PortReport>, PslmapError>>(
     targets: &[Target],
+    retry_policy: RetryPolicy,
+    max_attempts: usize,
+    probe_budget: Option<Duration>,
+    mut scan: F,
+) -> Result<ScanReports, PslmapError> {
+    let mut results: BTreeMap<(IpAddr, u16), PortReport> = BTreeMap::new();
+    let mut retries = BTreeMap::new();
+    let mut spent: BTreeMap<(IpAddr, u16), Duration> = BTreeMap::new();
+    let mut pending = targets.to_vec();
+    for attempt in 0..max_attempts {
+        if pending.iter().all(|t| t.ports.is_empty()) {
+            break;
+        }
+        if attempt > 0 {
+            for t in pending.iter().filter(|t| !t.ports.is_empty()) {
+                *retries.entry(t.addr).or_default() += t.ports.len();
+            }
+        }
+        for report in scan(&pending)? {
+            let key = (report.addr, report.port);
+            *spent.entry(key).or_default() += report.cost;
+            let keep_old = match results.get(&key) {
+                Some(old) => !is_no_response(old.status),
+                None => false,
+            };
+            if !keep_old {
+                results.insert(key, report);
+            }
+        }
+        if retry_policy == RetryPolicy::OnNoResponse {
+            for t in &mut pending {
+                t.ports.retain(|&p| match results.get(&(t.addr, p)) {
+                    Some(report) => is_no_response(report.status),
+                    None => true,
+                });
+            }
+        }
+        if let Some(budget) = probe_budget {
+            for t in &mut pending {
+                t.ports.retain(|&p| match spent.get(&(t.addr, p)) {
+                    Some(cost) => *cost < budget,
+                    None => true,
+                });
+            }
+        }
+    }
+    Ok(ScanReports {
+        reports: results.into_values().collect(),
+        retries,
+    })
+}
+
+/// The method and the retries of the probes, the same for every scan of one port scanning run.
+#[derive(Debug, Clone, Copy)]
+struct ProbeSettings {
     ps_method: PortScanningMethod,
     zombie_ipv4: Option<Ipv4Addr>, // tcp idle scan use only
     zombie_port: Option<u16>,      // tcp idle scan use only
-    log_level: PistolLogger,
-    timeout: f64,
-    num_threads: usize,
-) {
-    let start = Instant::now();
-    // let capture = Some(String::from("scan.pcapng"));
-    let capture = None;
-    let _pr = PistolRunner::init(log_level, capture, None).expect("init pistol runner failed");
+    retry_policy: RetryPolicy,
+    probe_budget: Option<Duration>,
+}
 
-    let num_threads = Some(num_threads);
-    let src_addr = None;
+/// Run the scan of the `ps_method` and returns the reports with the protocol name.
+fn scan_reports(
+    targets: &[Target],
+    probe: ProbeSettings,
+    timeout: Option<Duration>,
+    num_threads: Option<usize>,
+) -> Result<(ScanReports, &'static str), PslmapError> {
+    let mut protocol = "tcp";
+    let reports = retry_scan(
+        targets,
+        probe.retry_policy,
+        MAX_ATTEMPTS,
+        probe.probe_budget,
+        |targets| {
+            let (reports, p) = scan_once(
+                targets,
+                probe.ps_method,
+                probe.zombie_ipv4,
+                probe.zombie_port,
+                timeout,
+                num_threads,
+            )?;
+            record_reports(probe.ps_method, &reports);
+            protocol = p;
+            Ok(reports)
+        },
+    )?;
+    Ok((reports, protocol))
+}
+
+/// Only the probes that got a response have the real rtt.
+fn rtt_update(rtt_timeout: &mut RttTimeout, reports: &[PortReport]) {
+    for report in reports {
+        if !is_no_response(report.status) {
+            rtt_timeout.update(report.cost);
+        }
+    }
+}
+
+/// Scan the hosts batch by batch (one `scan` call of each batch with the current timeout),
+/// the timeout only grows or shrinks between the batches, following the rtts observed so far.
+fn adaptive_scan<F: FnMut(&[Target], Duration) -> Result<ScanReports, PslmapError>>(
+    targets: &[Target],
+    host_parallelism: usize,
+    rtt_timeout: &mut RttTimeout,
+    mut scan: F,
+) -> Result<ScanReports, PslmapError> {
+    let mut reports = ScanReports::default();
+    for batch in host_groups(targets, host_parallelism) {
+        let ret = scan(batch, rtt_timeout.timeout())?;
+        rtt_update(rtt_timeout, &ret.reports);
+        reports.extend(ret);
+    }
+    Ok(reports)
+}
+
+/// One attempt only, the retries are done by `retry_scan`.
+fn scan_once(
+    targets: &[Target],
+    ps_method: PortScanningMethod,
+    zombie_ipv4: Option<Ipv4Addr>,
+    zombie_port: Option<u16>,
+    timeout: Option<Duration>,
+    num_threads: Option<usize>,
+) -> Result<(Vec<PortReport>, &'static str), PslmapError> {
+    let src_addr = *SRC_ADDR.lock().expect("lock SRC_ADDR failed");
     let src_port = None;
-    let max_attempts = 2;
-    let timeout = Some(Duration::from_secs_f64(timeout));
+    let max_attempts = 1;
 
     let protocol_tcp = "tcp";
     let protocol_udp = "udp";
@@ -69,7 +383,7 @@ pub fn port_scanning(
                 timeout,
                 max_attempts,
             )
-            .expect("tcp syn scan failed");
+            .map_err(PslmapError::pistol("tcp syn scan"))?;
             (ret, protocol_tcp)
         }
         PortScanningMethod::TcpConnect => {
@@ -81,7 +395,7 @@ pub fn port_scanning(
                 timeout,
                 max_attempts,
             )
-            .expect("tcp connect scan failed");
+            .map_err(PslmapError::pistol("tcp connect scan"))?;
             (ret, protocol_tcp)
         }
         PortScanningMethod::TcpFin => {
@@ -93,7 +407,7 @@ pub fn port_scanning(
                 timeout,
                 max_attempts,
             )
-            .expect("tcp fin scan failed");
+            .map_err(PslmapError::pistol("tcp fin scan"))?;
             (ret, protocol_tcp)
         }
         PortScanningMethod::TcpNull => {
@@ -105,7 +419,7 @@ pub fn port_scanning(
                 timeout,
                 max_attempts,
             )
-            .expect("tcp null scan failed");
+            .map_err(PslmapError::pistol("tcp null scan"))?;
             (ret, protocol_tcp)
         }
         PortScanningMethod::TcpXmas => {
@@ -117,7 +431,7 @@ pub fn port_scanning(
                 timeout,
                 max_attempts,
             )
-            .expect("tcp xmas scan failed");
+            .map_err(PslmapError::pistol("tcp xmas scan"))?;
             (ret, protocol_tcp)
         }
         PortScanningMethod::TcpAck => {
@@ -129,7 +443,7 @@ pub fn port_scanning(
                 timeout,
                 max_attempts,
             )
-            .expect("tcp ack scan failed");
+            .map_err(PslmapError::pistol("tcp ack scan"))?;
             (ret, protocol_tcp)
         }
         PortScanningMethod::TcpWindow => {
@@ -141,7 +455,7 @@ pub fn port_scanning(
                 timeout,
                 max_attempts,
             )
-            .expect("tcp window scan failed");
+            .map_err(PslmapError::pistol("tcp window scan"))?;
             (ret, protocol_tcp)
         }
         PortScanningMethod::TcpMaimon => {
@@ -153,20 +467,37 @@ pub fn port_scanning(
                 timeout,
                 max_attempts,
             )
-            .expect("tcp maimon scan failed");
+            .map_err(PslmapError::pistol("tcp maimon scan"))?;
             (ret, protocol_tcp)
         }
+        PortScanningMethod::TcpCustom(flags) => {
+            let num_threads = num_threads.unwrap_or(1);
+            let port_reports = custom_flags_scan(targets, flags, timeout, num_threads);
+            return Ok((port_reports, protocol_tcp));
+        }
         PortScanningMethod::Udp => {
-            let ret = udp_scan(
-                targets,
-                num_threads,
-                src_addr,
-                src_port,
-                timeout,
-                max_attempts,
-            )
-            .expect("udp scan failed");
-            (ret, protocol_udp)
+            let scan = |targets: &[Target]| {
+                let ret = udp_scan(
+                    targets,
+                    num_threads,
+                    src_addr,
+                    src_port,
+                    timeout,
+                    max_attempts,
+                )
+                .map_err(PslmapError::pistol("udp scan"))?;
+                Ok(ret.port_reports)
+            };
+            let port_reports = match udp_batch() {
+                Some(batch) => udp_batch_scan(targets, batch, scan, std::thread::sleep)?,
+                None => scan(targets)?,
+            };
+            return Ok((port_reports, protocol_udp));
+        }
+        PortScanningMethod::SctpInit => {
+            return Err(PslmapError::Unsupported(String::from(
+                "sctp init scan (the sctp protocol) is not supported by pistol",
+            )));
         }
         PortScanningMethod::TcpIdle => {
             let ret = tcp_idle_scan(
@@ -179,14 +510,645 @@ pub fn port_scanning(
                 timeout,
                 max_attempts,
             )
-            .expect("tcp idle scan failed");
+            .map_err(PslmapError::pistol("tcp idle scan"))?;
             (ret, protocol_tcp)
         }
+        PortScanningMethod::IpProto => {
+            return Err(PslmapError::Unsupported(String::from(
+                "ip protocol scan does not scan the ports",
+            )));
+        }
+    };
+
+    Ok((ret.port_reports, protocol))
+}
+
+/// Split the targets into the chunks of single port targets,
+/// so the reports can be observed chunk by chunk.
+fn probe_chunks(targets: &[Target], chunk_size: usize) -> Vec<Vec<Target>> {
+    let mut probes = Vec::new();
+    for t in targets {
+        for &port in &t.ports {
+            let mut probe = t.clone();
+            probe.ports = vec![port];
+            probes.push(probe);
+        }
+    }
+    probes
+        .chunks(chunk_size.max(1))
+        .map(|c| c.to_vec())
+        .collect()
+}
+
+/// At most `max_hosts` hosts are probed at the same time (--max-host-parallelism),
+/// the probes of each group still run on the probe threads.
+fn host_groups(targets: &[Target], max_hosts: usize) -> Vec<&[Target]> {
+    targets.chunks(max_hosts.max(1)).collect()
+}
+
+/// The `workers` threads pull the hosts one by one, so a slow host (waiting for the timeout)
+/// only holds its own thread while the others go on, the results are in the completion order.
+/// The `num_threads` budget is divided among the workers, each `scan` gets its share.
+fn host_pool<T, F>(targets: &[Target], workers: usize, num_threads: usize, scan: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(&Target, usize) -> T + Sync,
+{
+    let workers = workers.max(1).min(targets.len());
+    if workers == 0 {
+        return Vec::new();
+    }
+    let share = (num_threads / workers).max(1);
+    let next = AtomicUsize::new(0);
+    let done = Mutex::new(Vec::new());
+    std::thread::scope(|s| {
+        for _ in 0..workers {
+            s.spawn(|| {
+                while let Some(t) = targets.get(next.fetch_add(1, Ordering::SeqCst)) {
+                    let ret = scan(t, share);
+                    done.lock().expect("lock host pool failed").push(ret);
+                }
+            });
+        }
+    });
+    done.into_inner().expect("lock host pool failed")
+}
+
+/// The probes that pistol failed to send.
+fn send_failures(reports: &[PortReport]) -> usize {
+    reports
+        .iter()
+        .filter(|r| r.status == PortStatus::Error)
+        .count()
+}
+
+/// Consume the reports in order and stop at the first open port,
+/// the reports after it are never pulled from the iterator.
+fn find_first_open<I: IntoIterator<Item = PortReport>>(reports: I) -> Option<PortReport> {
+    reports
+        .into_iter()
+        .find(|report| report.status == PortStatus::Open)
+}
+
+fn push_range(
+    ret: &mut Vec<(PortStatus, Vec<(u16, u16)>)>,
+    status: PortStatus,
+    start: u16,
+    end: u16,
+) {
+    match ret.iter_mut().find(|(s, _)| *s == status) {
+        Some((_, ranges)) => ranges.push((start, end)),
+        None => ret.push((status, vec![(start, end)])),
+    }
+}
+
+/// Coalesce the contiguous not open ports with the same status into ranges,
+/// a port of another status (or a port not scanned) breaks the range.
+/// The input must be sorted by port.
+fn coalesce_ranges(ports: &[(u16, PortStatus)]) -> Vec<(PortStatus, Vec<(u16, u16)>)> {
+    let mut ret: Vec<(PortStatus, Vec<(u16, u16)>)> = Vec::new();
+    let mut current: Option<(PortStatus, u16, u16)> = None;
+    for &(port, status) in ports {
+        current = match current {
+            Some((s, start, end)) if s == status && end.checked_add(1) == Some(port) => {
+                Some((s, start, port))
+            }
+            Some((s, start, end)) => {
+                push_range(&mut ret, s, start, end);
+                Some((status, port, port))
+            }
+            None => Some((status, port, port)),
+        };
+    }
+    if let Some((s, start, end)) = current {
+        push_range(&mut ret, s, start, end);
+    }
+    ret.retain(|(s, _)| *s != PortStatus::Open);
+    ret
+}
+
+/// Such as `1-21,23,25-79`.
+fn ranges_str(ranges: &[(u16, u16)]) -> String {
+    let ranges: Vec<String> = ranges
+        .iter()
+        .map(|(start, end)| {
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{}-{}", start, end)
+            }
+        })
+        .collect();
+    ranges.join(",")
+}
+
+/// Why the port got the status (--reason), same as the nmap reason column.
+/// The icmp unreachable code tells a firewall (such as `icmp-admin-prohibited`) from a closed port,
+/// only the --scanflags probes see it (pistol only returns the status).
+fn status_reason(ps_method: PortScanningMethod, report: &PortReport) -> Option<String> {
+    if let PortScanningMethod::TcpCustom(_) = ps_method
+        && let Some((icmp_type, icmp_code)) = icmp_reply(report.addr, report.port)
+        && let Some(name) = icmp_reason(icmp_type, icmp_code)
+    {
+        return Some(format!("icmp-{}", name));
+    }
+    let reason = match (ps_method, report.status) {
+        (PortScanningMethod::TcpSyn, PortStatus::Open) => "syn-ack",
+        (PortScanningMethod::TcpConnect, PortStatus::Open) => "connection established",
+        (PortScanningMethod::Udp, PortStatus::Open) => "udp response",
+        (PortScanningMethod::Udp, PortStatus::OpenOrFiltered) => "no response",
+        // pistol only takes the port unreachable as closed
+        (PortScanningMethod::Udp, PortStatus::Closed) => "icmp-port-unreachable",
+        _ => return None,
+    };
+    Some(reason.to_string())
+}
+
+/// The text line of the open port, the udp open|filtered port is printed too (rendered apart from open,
+/// no response can not tell an open udp port from a filtered one), None for the other statuses.
+fn port_line(
+    report: &PortReport,
+    protocol: &str,
+    ps_method: PortScanningMethod,
+    reason: bool,
+    context: &PortLineContext,
+) -> Option<String> {
+    let mut line = match report.status {
+        PortStatus::Open => format!(
+            "{}:{}/{} -> {} {}",
+            report.addr,
+            report.port,
+            protocol,
+            report.status,
+            cost_tag(report, context)
+        ),
+        PortStatus::OpenOrFiltered if matches!(ps_method, PortScanningMethod::Udp) => format!(
+            "{}:{}/{} -> open|filtered",
+            report.addr, report.port, protocol
+        ),
+        _ => return None,
+    };
+    if reason && let Some(r) = status_reason(ps_method, report) {
+        line = format!("{} ({})", line, r);
+    }
+    Some(line)
+}
+
+/// The tags of the port lines (--reason, --show-fingerprint, --service-names and the retries with -v).
+#[derive(Debug, Clone, Copy)]
+struct LineOptions {
+    ps_method: PortScanningMethod,
+    reason: bool,
+    show_fingerprint: bool,
+    service_names: bool,
+    verbose: u8,
+    timeout: Option<Duration>,
+}
+
+/// The tagged line and the record of one port, shared by the full scan and --first-open.
+/// The port not `listed` (over --max-open-per-host) only has the record.
+fn port_output(
+    report: &PortReport,
+    protocol: &str,
+    listed: bool,
+    options: LineOptions,
+    context: &PortLineContext,
+) -> (Option<String>, PortRecord) {
+    let (addr, port) = (report.addr, report.port);
+    let mut line =
+        port_line(report, protocol, options.ps_method, options.reason, context).filter(|_| listed);
+    let mut fp = Fingerprint::default();
+    if options.show_fingerprint && line.is_some() && matches!(report.status, PortStatus::Open) {
+        fp = probe_fingerprint(addr, port, options.ps_method, options.timeout);
+        if let Some(tag) = fp.tag() {
+            line = line.map(|l| format!("{} {}", l, tag));
+        }
+    }
+    // only the port lines are annotated, the closed ports have none
+    let service = service_name(port, protocol).filter(|_| options.service_names && line.is_some());
+    if let Some(name) = service {
+        line = line.map(|l| format!("{} {}", l, name));
+    }
+    if options.verbose >= 1
+        && let Some(tag) = retries_tag(context.host_retries(addr))
+    {
+        line = line.map(|l| format!("{} {}", l, tag));
+    }
+    let (median, stddev) = context.sample_stats(addr, port).unzip();
+    let record = PortRecord {
+        addr: addr.to_string(),
+        port,
+        protocol: protocol.to_string(),
+        status: report.status.to_string(),
+        cost: median.unwrap_or(report.cost).as_secs_f64(),
+        rtt_stddev: stddev.map(|s| s.as_secs_f64()),
+        ttl: fp.ttl,
+        window: fp.window,
+        reason: status_reason(options.ps_method, report).filter(|_| options.reason),
+        service: service.map(String::from),
+    };
+    (line, record)
+}
+
+/// The open ports of the host over the cap (--max-open-per-host), they are counted but not listed.
+fn capped_open_ports(reports: &BTreeMap<u16, PortReport>, max_open: usize) -> Vec<u16> {
+    reports
+        .values()
+        .filter(|r| r.status == PortStatus::Open)
+        .skip(max_open)
+        .map(|r| r.port)
+        .collect()
+}
+
+/// Such as `192.168.1.1 ... and 900 more open`, the host answering every probe as open
+/// (more ports than the cap) is flagged as a likely tarpit.
+fn capped_lines(addr: IpAddr, reports: &BTreeMap<u16, PortReport>, capped: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    if capped > 0 {
+        lines.push(format!("{} ... and {} more open", addr, capped));
+        if reports.values().all(|r| r.status == PortStatus::Open) {
+            lines.push(format!(
+                "{} every probed port ({}) is open, likely a tarpit",
+                addr,
+                reports.len()
+            ));
+        }
+    }
+    lines
+}
+
+/// The per host status counts (--per-host-summary), open|filtered is counted apart from open,
+/// the other statuses (such as closed|filtered and unfiltered) are counted as filtered.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct PortCounts {
+    open: usize,
+    open_filtered: usize,
+    closed: usize,
+    filtered: usize,
+}
+
+impl PortCounts {
+    fn from_reports(reports: &BTreeMap<u16, PortReport>) -> PortCounts {
+        let mut counts = PortCounts::default();
+        for report in reports.values() {
+            match report.status {
+                PortStatus::Open => counts.open += 1,
+                PortStatus::OpenOrFiltered => counts.open_filtered += 1,
+                PortStatus::Closed => counts.closed += 1,
+                _ => counts.filtered += 1,
+            }
+        }
+        counts
+    }
+    /// Such as `192.168.1.1: 3 open, 12 closed, 2 filtered`,
+    /// the open|filtered count is only shown when there is any.
+    fn line(&self, addr: IpAddr) -> String {
+        let open_filtered = if self.open_filtered > 0 {
+            format!(", {} open|filtered", self.open_filtered)
+        } else {
+            String::new()
+        };
+        format!(
+            "{}: {} open{}, {} closed, {} filtered",
+            addr, self.open, open_filtered, self.closed, self.filtered
+        )
+    }
+}
+
+/// Same as the per host summary, the statuses other than open and closed are shown as filtered.
+fn matrix_cell(status: PortStatus) -> &'static str {
+    match status {
+        PortStatus::Open => "O",
+        PortStatus::Closed => "C",
+        _ => "F",
+    }
+}
+
+/// Rows are the hosts and columns are the ports, such as
+/// ```text
+/// host        22 80 443
+/// 192.168.1.1  O  C   F
+/// ```
+/// Every host must have the same ports and no more than MATRIX_MAX_PORTS.
+fn port_matrix(btm: &BTreeMap<IpAddr, BTreeMap<u16, PortReport>>) -> Result<Vec<String>, String> {
+    let ports: Vec<u16> = match btm.values().next() {
+        Some(reports) => reports.keys().copied().collect(),
+        None => return Ok(Vec::new()),
+    };
+    if ports.len() > MATRIX_MAX_PORTS {
+        return Err(format!(
+            "matrix output supports at most {} ports, got {}",
+            MATRIX_MAX_PORTS,
+            ports.len()
+        ));
+    }
+    for (addr, reports) in btm {
+        if !reports.keys().eq(ports.iter()) {
+            return Err(format!(
+                "matrix output needs the same ports for every host, the ports of {} differ",
+                addr
+            ));
+        }
+    }
+
+    let addr_width = btm
+        .keys()
+        .map(|addr| addr.to_string().len())
+        .max()
+        .unwrap_or(0)
+        .max("host".len());
+    let mut header = format!("{:<width$}", "host", width = addr_width);
+    for port in &ports {
+        header += &format!(" {}", port);
+    }
+    let mut lines = vec![header];
+    for (addr, reports) in btm {
+        let mut line = format!("{:<width$}", addr.to_string(), width = addr_width);
+        for (port, report) in reports {
+            let width = port.to_string().len();
+            line += &format!(" {:>width$}", matrix_cell(report.status), width = width);
+        }
+        lines.push(line);
+    }
+    Ok(lines)
+}
+
+/// The options of one port scanning run (the ps subcommand).
+#[derive(Debug, Clone)]
+pub struct PortScanOptions {
+    pub ps_method: PortScanningMethod,
+    pub zombie_ipv4: Option<Ipv4Addr>, // tcp idle scan use only
+    pub zombie_port: Option<u16>,      // tcp idle scan use only
+    pub timeout: f64,
+    pub num_threads: usize,
+    pub host_parallelism: usize,
+    pub first_open: bool,
+    pub closed_summary: bool,
+    pub per_host_summary: bool,
+    pub reason: bool,
+    pub retry_policy: RetryPolicy,
+    pub probe_budget: Option<Duration>,
+    pub rtt_timeout: Option<RttTimeout>,
+    pub badsum: bool,
+    pub show_fingerprint: bool,
+    pub service_names: bool,
+    pub max_open_per_host: Option<usize>,
+    pub samples: Option<usize>,
+    /// The dns lookup time of each hostname (--timing-includes-dns).
+    pub dns_times: HashMap<String, Duration>,
+    pub verbose: u8,
+    pub output_format: OutputFormat,
+}
+
+pub fn port_scanning(
+    targets: &[Target],
+    options: PortScanOptions,
+) -> Result<ScanOutcome, PslmapError> {
+    let start = Instant::now();
+    let PortScanOptions {
+        ps_method,
+        zombie_ipv4,
+        zombie_port,
+        timeout,
+        num_threads,
+        host_parallelism,
+        first_open,
+        closed_summary,
+        per_host_summary,
+        reason,
+        retry_policy,
+        probe_budget,
+        rtt_timeout,
+        badsum,
+        show_fingerprint,
+        service_names,
+        max_open_per_host,
+        samples,
+        dns_times,
+        verbose,
+        output_format,
+    } = options;
+    let probe = ProbeSettings {
+        ps_method,
+        zombie_ipv4,
+        zombie_port,
+        retry_policy,
+        probe_budget,
+    };
+
+    let timeout = Some(Duration::from_secs_f64(timeout));
+    if output_format == OutputFormat::Matrix
+        && (first_open || badsum || matches!(ps_method, PortScanningMethod::IpProto))
+    {
+        return Err(PslmapError::Unsupported(String::from(
+            "matrix output does not work with --first-open, --badsum or the ip protocol scan",
+        )));
+    }
+    if matches!(ps_method, PortScanningMethod::IpProto) {
+        // the ports are the ip protocol numbers
+        return Ok(protocol_scanning(
+            targets,
+            timeout,
+            num_threads,
+            output_format,
+        ));
+    }
+    if show_fingerprint && !fingerprint_supported(ps_method) {
+        eprintln!(
+            "warning: --show-fingerprint only works with the tcp syn and ack scans, not {:?}",
+            ps_method
+        );
+    }
+    let show_fingerprint = show_fingerprint && fingerprint_supported(ps_method);
+    let line_options = LineOptions {
+        ps_method,
+        reason,
+        show_fingerprint,
+        service_names,
+        verbose,
+        timeout,
+    };
+
+    if badsum {
+        if !badsum_supported(ps_method) {
+            return Err(PslmapError::Unsupported(format!(
+                "{:?} scan does not support the badsum probes",
+                ps_method
+            )));
+        }
+        let protocol = match ps_method {
+            PortScanningMethod::Udp => "udp",
+            _ => "tcp",
+        };
+        let mut reports = badsum_scan(targets, ps_method, timeout, num_threads);
+        reports.sort_by_key(|r| (r.addr, r.port));
+
+        let mut responded = 0;
+        let mut emitter = Emitter::stdout(output_format).with_method(ps_method.name());
+        for report in &reports {
+            let line = if report.responded {
+                responded += 1;
+                Some(format!(
+                    "{}:{}/{} -> {} ({:.2}s)",
+                    report.addr,
+                    report.port,
+                    protocol,
+                    report.label(),
+                    report.rtt.as_secs_f64()
+                ))
+            } else {
+                None
+            };
+            let record = BadsumRecord {
+                addr: report.addr.to_string(),
+                port: report.port,
+                protocol: protocol.to_string(),
+                responded: report.responded,
+                cost: report.rtt.as_secs_f64(),
+            };
+            emitter.emit(line, &record);
+        }
+        if reports.len() > responded {
+            let line = format!(
+                "other {} ports -> no response to bad checksum",
+                reports.len() - responded
+            );
+            emitter.text(line);
+        }
+        let tail = format!(
+            "pslmap done: {} ip addresses ({} ports responded to bad checksum) scanned in {:.2} seconds",
+            targets.len(),
+            responded,
+            start.elapsed().as_secs_f64()
+        );
+        emitter.finish(&tail);
+        return Ok(ScanOutcome {
+            up_addrs: Vec::new(),
+            found: responded,
+            ..Default::default()
+        });
+    }
+
+    if first_open {
+        let chunks = probe_chunks(targets, num_threads);
+        let mut protocol = "tcp";
+        let mut rtt_timeout = rtt_timeout;
+        let mut error = None;
+        let reports = chunks
+            .iter()
+            .map_while(|chunk| {
+                let chunk_timeout = match &rtt_timeout {
+                    Some(rtt_timeout) => Some(rtt_timeout.timeout()),
+                    None => timeout,
+                };
+                let ret = scan_reports(chunk, probe, chunk_timeout, Some(num_threads));
+                // stop at the first failed chunk
+                let (ret, p) = match ret {
+                    Ok(ret) => ret,
+                    Err(e) => {
+                        error = Some(e);
+                        return None;
+                    }
+                };
+                if let Some(rtt_timeout) = &mut rtt_timeout {
+                    rtt_update(rtt_timeout, &ret.reports);
+                }
+                protocol = p;
+                Some(ret.reports)
+            })
+            .flatten();
+        let report = find_first_open(reports);
+        if let Some(e) = error {
+            return Err(e);
+        }
+
+        let mut emitter = Emitter::stdout(output_format).with_method(ps_method.name());
+        let found = match report {
+            Some(report) => {
+                let context = PortLineContext {
+                    dns_times,
+                    ..Default::default()
+                };
+                let (line, record) = port_output(&report, protocol, true, line_options, &context);
+                emitter.emit(line, &record);
+                1
+            }
+            None => {
+                emitter.text(String::from("no open port found"));
+                0
+            }
+        };
+        let tail = format!(
+            "pslmap done: {} ip addresses ({} ports up) scanned in {:.2} seconds (stopped at the first open port)",
+            targets.len(),
+            found,
+            start.elapsed().as_secs_f64()
+        );
+        emitter.finish(&stats_tail(tail));
+        return Ok(ScanOutcome {
+            up_addrs: Vec::new(),
+            found,
+            ..Default::default()
+        });
+    }
+
+    let mut rtt_timeout = rtt_timeout;
+    let mut protocol = "tcp";
+    let mut scanned = ScanReports::default();
+    if let Some(rtt_timeout) = &mut rtt_timeout {
+        scanned = adaptive_scan(targets, host_parallelism, rtt_timeout, |batch, timeout| {
+            let (ret, p) = scan_reports(batch, probe, Some(timeout), Some(num_threads))?;
+            protocol = p;
+            Ok(ret)
+        })?;
+    } else if ps_method == PortScanningMethod::TcpIdle {
+        // the idle probes of all the hosts share the ip id sequence of one zombie
+        for group in host_groups(targets, host_parallelism) {
+            let (ret, p) = scan_reports(group, probe, timeout, Some(num_threads))?;
+            protocol = p;
+            scanned.extend(ret);
+        }
+    } else {
+        let rets = host_pool(targets, host_parallelism, num_threads, |t, threads| {
+            scan_reports(std::slice::from_ref(t), probe, timeout, Some(threads))
+        });
+        for ret in rets {
+            let (ret, p) = ret?;
+            protocol = p;
+            scanned.extend(ret);
+        }
+    }
+    let ScanReports {
+        reports: port_reports,
+        retries,
+    } = scanned;
+    raw_debug(&port_reports);
+    if let Some(warning) = send_failure_warning(send_failures(&port_reports)) {
+        eprintln!("warning: {}", warning);
+    }
+    let samples = match samples {
+        Some(samples) => rtt_samples(&port_reports, samples, |targets| {
+            let (reports, _) = scan_once(
+                targets,
+                ps_method,
+                zombie_ipv4,
+                zombie_port,
+                timeout,
+                Some(num_threads),
+            )?;
+            Ok(reports)
+        })?,
+        None => BTreeMap::new(),
+    };
+    let context = PortLineContext {
+        dns_times,
+        retries,
+        samples,
     };
 
     // sorted
     let mut btm: BTreeMap<IpAddr, BTreeMap<u16, PortReport>> = BTreeMap::new();
-    for report in ret.port_reports {
+    for report in port_reports {
         if let Some(btm_port) = btm.get_mut(&report.addr) {
             btm_port.insert(report.port, report.clone());
         } else {
@@ -198,37 +1160,637 @@ pub fn port_scanning(
 
     let mut hosts_up = 0;
     let mut hosts_not_up = 0;
-    let mut info = Vec::new();
+    let mut open_filtered = 0;
+    let mut summary_lines = Vec::new();
+    let mut port_status = BTreeMap::new();
+    let no_data = no_data_lines(targets, btm.keys());
+    let mut emitter = Emitter::stdout(output_format).with_method(ps_method.name());
+    if output_format == OutputFormat::Matrix {
+        let lines = port_matrix(&btm).map_err(PslmapError::Unsupported)?;
+        emitter.rows(lines);
+    }
     for (addr, report) in btm {
+        if per_host_summary {
+            summary_lines.push(PortCounts::from_reports(&report).line(addr));
+        }
+        if closed_summary {
+            let ports: Vec<(u16, PortStatus)> =
+                report.values().map(|r| (r.port, r.status)).collect();
+            for (status, ranges) in coalesce_ranges(&ports) {
+                let line = format!(
+                    "{}:{}/{} -> {}",
+                    addr,
+                    ranges_str(&ranges),
+                    protocol,
+                    status
+                );
+                emitter.text(line);
+            }
+        }
+        let capped = match max_open_per_host {
+            Some(max_open) => capped_open_ports(&report, max_open),
+            None => Vec::new(),
+        };
+        let capped_tail = capped_lines(addr, &report, capped.len());
         for (port, report) in report {
+            port_status.insert((addr, port), report.status.to_string());
+            // the capped ports are still in the records and the counts
+            let listed = !capped.contains(&port);
+            let (line, record) = port_output(&report, protocol, listed, line_options, &context);
             match report.status {
-                PortStatus::Open => {
-                    hosts_up += 1;
-                    let line = format!(
-                        "{}:{}/{} -> {} ({:.2}s)",
-                        addr,
-                        port,
-                        protocol,
-                        report.status,
-                        report.cost.as_secs_f64()
-                    );
-                    info.push(line);
-                }
+                PortStatus::Open => hosts_up += 1,
+                PortStatus::OpenOrFiltered if line.is_some() => open_filtered += 1,
                 _ => hosts_not_up += 1,
             }
+            emitter.emit(line, &record);
+        }
+        for line in capped_tail {
+            emitter.text(line);
         }
     }
-    if hosts_not_up > 0 {
+    if hosts_not_up > 0 && !closed_summary {
         let line = format!("other {} ports -> closed", hosts_not_up);
-        info.push(line);
+        emitter.text(line);
+    }
+    for line in summary_lines {
+        emitter.text(line);
+    }
+    if verbose >= 1 {
+        for line in no_data {
+            emitter.text(line);
+        }
     }
 
-    let info = info.join("\n");
+    let ports_up = if open_filtered > 0 {
+        format!("{} ports up, {} open|filtered", hosts_up, open_filtered)
+    } else {
+        format!("{} ports up", hosts_up)
+    };
     let tail = format!(
-        "pslmap done: {} ip addresses ({} ports up) scanned in {:.2} seconds",
+        "pslmap done: {} ip addresses ({}) scanned in {:.2} seconds",
         targets.len(),
-        hosts_up,
+        ports_up,
         start.elapsed().as_secs_f64()
     );
-    InfoShow::print(&info, &tail);
+    emitter.finish(&stats_tail(tail));
+    Ok(ScanOutcome {
+        up_addrs: Vec::new(),
+        found: hosts_up,
+        port_status,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pistol::error::PistolError;
+    use std::cell::Cell;
+    fn port_report(addr: IpAddr, port: u16, status: PortStatus) -> PortReport {
+        PortReport {
+            addr,
+            port,
+            origin: None,
+            status,
+            cost: Duration::from_millis(10),
+        }
+    }
+    #[test]
+    fn test_host_groups() {
+        let targets: Vec<Target> = (1..=5)
+            .map(|i| Target::new(Ipv4Addr::new(192, 168, 5, i).into(), Some(vec![22, 80])))
+            .collect();
+        let groups = host_groups(&targets, 2);
+        let sizes: Vec<usize> = groups.iter().map(|g| g.len()).collect();
+        assert_eq!(sizes, vec![2, 2, 1]);
+        assert_eq!(groups[2][0].addr, targets[4].addr);
+        // the ports of one host are never split across the groups
+        assert!(groups.concat().iter().all(|t| t.ports == vec![22, 80]));
+        assert_eq!(host_groups(&targets, 0).len(), 5);
+    }
+    #[test]
+    fn test_host_pool() {
+        let targets: Vec<Target> = (1..=6)
+            .map(|i| Target::new(Ipv4Addr::new(192, 168, 5, i).into(), Some(vec![22])))
+            .collect();
+        let slow = targets[0].addr;
+        let (tx, rx) = std::sync::mpsc::channel();
+        let rx = Mutex::new(rx);
+        let done = host_pool(&targets, 2, 16, |t, threads| {
+            // 2 workers share the 16 threads
+            assert_eq!(threads, 8);
+            if t.addr == slow {
+                // the first host is still waiting while the other worker scans the fast hosts
+                let rx = rx.lock().unwrap();
+                for _ in 1..targets.len() {
+                    rx.recv().unwrap();
+                }
+            } else {
+                tx.send(t.addr).unwrap();
+            }
+            t.addr
+        });
+        assert_eq!(done.len(), 6);
+        assert_eq!(done[0], targets[1].addr);
+        let slow_done = done.iter().position(|&a| a == slow).unwrap();
+        assert!(slow_done >= 4);
+        // the budget is never below one thread
+        let shares = host_pool(&targets, 4, 2, |_, threads| threads);
+        assert!(shares.iter().all(|&threads| threads == 1));
+    }
+    #[test]
+    fn test_first_open() {
+        let addr = IpAddr::V4(Ipv4Addr::new(192, 168, 5, 5));
+        let stream = vec![
+            port_report(addr, 21, PortStatus::Closed),
+            port_report(addr, 22, PortStatus::Filtered),
+            port_report(addr, 80, PortStatus::Open),
+            port_report(addr, 443, PortStatus::Open),
+            port_report(addr, 8080, PortStatus::Closed),
+        ];
+        let pulled = Cell::new(0);
+        let reports = stream.into_iter().inspect(|_| pulled.set(pulled.get() + 1));
+        let ret = find_first_open(reports).unwrap();
+        assert_eq!(ret.port, 80);
+        // the reports after the first open one are never consumed
+        assert_eq!(pulled.get(), 3);
+
+        let targets = vec![Target::new(addr, Some(vec![21, 22, 80, 443, 8080]))];
+        let chunks = probe_chunks(&targets, 2);
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[2][0].ports, vec![8080]);
+
+        // the first open port gets the same tags as the full scan
+        let mut first = ret;
+        first.origin = Some(String::from("first-open.example.com"));
+        let options = LineOptions {
+            ps_method: PortScanningMethod::TcpSyn,
+            reason: true,
+            show_fingerprint: false,
+            service_names: true,
+            verbose: 0,
+            timeout: None,
+        };
+        let context = PortLineContext {
+            dns_times: HashMap::from([(
+                String::from("first-open.example.com"),
+                Duration::from_millis(300),
+            )]),
+            ..Default::default()
+        };
+        let (line, record) = port_output(&first, "tcp", true, options, &context);
+        assert_eq!(
+            line.as_deref(),
+            Some("192.168.5.5:80/tcp -> open (0.31s incl. dns 0.30s) (syn-ack) http")
+        );
+        assert_eq!(record.reason.as_deref(), Some("syn-ack"));
+        assert_eq!(record.service.as_deref(), Some("http"));
+    }
+    #[test]
+    fn test_closed_summary() {
+        let mut ports: Vec<(u16, PortStatus)> = (1..=79).map(|p| (p, PortStatus::Closed)).collect();
+        ports[21].1 = PortStatus::Open; // 22
+        ports[23].1 = PortStatus::Filtered; // 24
+        // 100 is not contiguous with 79
+        ports.push((100, PortStatus::Closed));
+
+        let ret = coalesce_ranges(&ports);
+        assert_eq!(ret.len(), 2);
+        assert_eq!(ret[0].0, PortStatus::Closed);
+        assert_eq!(ranges_str(&ret[0].1), "1-21,23,25-79,100");
+        assert_eq!(ret[1].0, PortStatus::Filtered);
+        assert_eq!(ranges_str(&ret[1].1), "24");
+    }
+    #[test]
+    fn test_port_matrix() {
+        let addr1 = IpAddr::V4(Ipv4Addr::new(192, 168, 5, 5));
+        let addr2 = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let statuses = [
+            (
+                addr1,
+                [PortStatus::Open, PortStatus::Closed, PortStatus::Filtered],
+            ),
+            (
+                addr2,
+                [
+                    PortStatus::Closed,
+                    PortStatus::Open,
+                    PortStatus::OpenOrFiltered,
+                ],
+            ),
+        ];
+        let mut btm: BTreeMap<IpAddr, BTreeMap<u16, PortReport>> = BTreeMap::new();
+        for (addr, status) in statuses {
+            let reports = [22, 80, 443]
+                .into_iter()
+                .zip(status)
+                .map(|(port, status)| (port, port_report(addr, port, status)))
+                .collect();
+            btm.insert(addr, reports);
+        }
+        let lines = port_matrix(&btm).unwrap();
+        assert_eq!(
+            lines,
+            vec![
+                "host        22 80 443",
+                "10.0.0.1     C  O   F",
+                "192.168.5.5  O  C   F",
+            ]
+        );
+
+        btm.get_mut(&addr1).unwrap().remove(&443);
+        assert!(
+            port_matrix(&btm)
+                .unwrap_err()
+                .contains("192.168.5.5 differ")
+        );
+        let wide: BTreeMap<u16, PortReport> = (1..=17)
+            .map(|port| (port, port_report(addr1, port, PortStatus::Closed)))
+            .collect();
+        btm = BTreeMap::from([(addr1, wide)]);
+        assert!(port_matrix(&btm).is_err());
+    }
+    #[test]
+    fn test_per_host_summary() {
+        let addr1 = IpAddr::V4(Ipv4Addr::new(192, 168, 5, 5));
+        let addr2 = IpAddr::V4(Ipv4Addr::new(192, 168, 5, 6));
+        let reports = [
+            port_report(addr1, 22, PortStatus::Open),
+            port_report(addr1, 80, PortStatus::Open),
+            port_report(addr1, 443, PortStatus::Closed),
+            port_report(addr1, 8080, PortStatus::Filtered),
+            port_report(addr2, 22, PortStatus::Closed),
+            port_report(addr2, 53, PortStatus::OpenOrFiltered),
+            port_report(addr2, 80, PortStatus::Closed),
+        ];
+        let mut btm: BTreeMap<IpAddr, BTreeMap<u16, PortReport>> = BTreeMap::new();
+        for r in reports {
+            btm.entry(r.addr).or_default().insert(r.port, r);
+        }
+        let counts: Vec<PortCounts> = btm.values().map(PortCounts::from_reports).collect();
+        assert_eq!(
+            counts,
+            vec![
+                PortCounts {
+                    open: 2,
+                    open_filtered: 0,
+                    closed: 1,
+                    filtered: 1
+                },
+                PortCounts {
+                    open: 0,
+                    open_filtered: 1,
+                    closed: 2,
+                    filtered: 0
+                },
+            ]
+        );
+        assert_eq!(
+            counts[0].line(addr1),
+            "192.168.5.5: 2 open, 1 closed, 1 filtered"
+        );
+    }
+    #[test]
+    fn test_send_failures() {
+        let addr = IpAddr::V4(Ipv4Addr::new(192, 168, 5, 5));
+        let reports: Vec<PortReport> = (1..=10)
+            .map(|port| PortReport {
+                cost: Duration::ZERO,
+                ..port_report(
+                    addr,
+                    port,
+                    if port % 4 == 0 {
+                        PortStatus::Error
+                    } else {
+                        PortStatus::Closed
+                    },
+                )
+            })
+            .collect();
+        let warning = send_failure_warning(send_failures(&reports)).unwrap();
+        assert!(warning.starts_with("2 probes failed to send (resource limits)"));
+        assert!(warning.contains("lower --nt"));
+        assert_eq!(send_failure_warning(0), None);
+    }
+    #[test]
+    fn test_udp_open_filtered() {
+        let addr = IpAddr::V4(Ipv4Addr::new(192, 168, 5, 5));
+        let reports = [
+            port_report(addr, 53, PortStatus::Open),
+            port_report(addr, 67, PortStatus::OpenOrFiltered),
+            port_report(addr, 123, PortStatus::OpenOrFiltered),
+            port_report(addr, 161, PortStatus::Closed),
+        ];
+        let udp = PortScanningMethod::Udp;
+        let context = PortLineContext::default();
+        assert_eq!(
+            port_line(&reports[0], "udp", udp, true, &context),
+            Some(String::from(
+                "192.168.5.5:53/udp -> open (0.01s) (udp response)"
+            ))
+        );
+        assert_eq!(
+            port_line(&reports[1], "udp", udp, false, &context),
+            Some(String::from("192.168.5.5:67/udp -> open|filtered"))
+        );
+        assert_eq!(
+            port_line(&reports[1], "udp", udp, true, &context),
+            Some(String::from(
+                "192.168.5.5:67/udp -> open|filtered (no response)"
+            ))
+        );
+        assert_eq!(port_line(&reports[3], "udp", udp, true, &context), None);
+        // the tcp fin scan open|filtered ports are not printed
+        let fin = PortScanningMethod::TcpFin;
+        assert_eq!(port_line(&reports[1], "tcp", fin, true, &context), None);
+
+        let btm: BTreeMap<u16, PortReport> = reports.into_iter().map(|r| (r.port, r)).collect();
+        let counts = PortCounts::from_reports(&btm);
+        assert_eq!(counts.open, 1);
+        assert_eq!(counts.open_filtered, 2);
+        assert_eq!(
+            counts.line(addr),
+            "192.168.5.5: 1 open, 2 open|filtered, 1 closed, 0 filtered"
+        );
+    }
+    #[test]
+    fn test_retry_policy() {
+        let addr = IpAddr::V4(Ipv4Addr::new(192, 168, 5, 5));
+        let targets = vec![Target::new(addr, Some(vec![22, 80]))];
+        // port 22 is closed at the first attempt and port 80 never replies
+        let fake_scan = |probes: &mut Vec<u16>, targets: &[Target]| {
+            let mut reports = Vec::new();
+            for t in targets {
+                for &port in &t.ports {
+                    probes.push(port);
+                    let status = if port == 22 {
+                        PortStatus::Closed
+                    } else {
+                        PortStatus::Filtered
+                    };
+                    reports.push(port_report(addr, port, status));
+                }
+            }
+            reports
+        };
+
+        let mut probes = Vec::new();
+        let ret = retry_scan(&targets, RetryPolicy::OnNoResponse, 3, None, |t| {
+            Ok(fake_scan(&mut probes, t))
+        })
+        .unwrap();
+        assert_eq!(ret.reports.len(), 2);
+        assert_eq!(probes.iter().filter(|&&p| p == 22).count(), 1);
+        assert_eq!(probes.iter().filter(|&&p| p == 80).count(), 3);
+
+        let mut probes = Vec::new();
+        let ret = retry_scan(&targets, RetryPolicy::Always, 3, None, |t| {
+            Ok(fake_scan(&mut probes, t))
+        })
+        .unwrap();
+        assert_eq!(ret.reports.len(), 2);
+        assert_eq!(ret.reports[0].status, PortStatus::Closed);
+        assert_eq!(probes.iter().filter(|&&p| p == 22).count(), 3);
+    }
+    #[test]
+    fn test_cost_with_dns() {
+        let addr = IpAddr::V4(Ipv4Addr::new(192, 168, 5, 5));
+        let mut report = PortReport {
+            origin: Some(String::from("dns-cost.example.com")),
+            cost: Duration::from_millis(20),
+            ..port_report(addr, 443, PortStatus::Open)
+        };
+        let context = PortLineContext {
+            dns_times: HashMap::from([(
+                String::from("dns-cost.example.com"),
+                Duration::from_millis(300),
+            )]),
+            ..Default::default()
+        };
+        let line = port_line(&report, "tcp", PortScanningMethod::TcpSyn, false, &context);
+        assert_eq!(
+            line.as_deref(),
+            Some("192.168.5.5:443/tcp -> open (0.32s incl. dns 0.30s)")
+        );
+        // the literal target has only the probe cost
+        report.origin = None;
+        let line = port_line(&report, "tcp", PortScanningMethod::TcpSyn, false, &context);
+        assert_eq!(line.as_deref(), Some("192.168.5.5:443/tcp -> open (0.02s)"));
+    }
+    #[test]
+    fn test_max_open_per_host() {
+        let addr = IpAddr::V4(Ipv4Addr::new(192, 168, 5, 5));
+        let host = |closed: Option<u16>| -> BTreeMap<u16, PortReport> {
+            (1..=10)
+                .map(|port| {
+                    let status = if Some(port) == closed {
+                        PortStatus::Closed
+                    } else {
+                        PortStatus::Open
+                    };
+                    let report = port_report(addr, port, status);
+                    (port, report)
+                })
+                .collect()
+        };
+        // the tarpit answers every port as open
+        let tarpit = host(None);
+        let capped = capped_open_ports(&tarpit, 3);
+        assert_eq!(capped, vec![4, 5, 6, 7, 8, 9, 10]);
+        assert_eq!(
+            capped_lines(addr, &tarpit, capped.len()),
+            vec![
+                "192.168.5.5 ... and 7 more open",
+                "192.168.5.5 every probed port (10) is open, likely a tarpit",
+            ]
+        );
+
+        let normal = host(Some(1));
+        let capped = capped_open_ports(&normal, 3);
+        assert_eq!(
+            capped_lines(addr, &normal, capped.len()),
+            vec!["192.168.5.5 ... and 6 more open"]
+        );
+        assert_eq!(capped_lines(addr, &normal, 0), Vec::<String>::new());
+    }
+    #[test]
+    fn test_sctp_unsupported() {
+        let targets = vec![Target::new(
+            Ipv4Addr::new(192, 168, 5, 5).into(),
+            Some(vec![2905]),
+        )];
+        let err = scan_once(
+            &targets,
+            PortScanningMethod::SctpInit,
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap_err();
+        assert!(matches!(err, PslmapError::Unsupported(_)));
+        assert_eq!(
+            err.to_string(),
+            "sctp init scan (the sctp protocol) is not supported by pistol"
+        );
+        assert_eq!(PortScanningMethod::SctpInit.name(), "sctp_init");
+    }
+    #[test]
+    fn test_host_retries() {
+        let addr = IpAddr::V4(Ipv4Addr::new(192, 168, 7, 2));
+        let targets = vec![Target::new(addr, Some(vec![22]))];
+        let attempt = Cell::new(0);
+        let ret = retry_scan(&targets, RetryPolicy::OnNoResponse, 3, None, |t| {
+            attempt.set(attempt.get() + 1);
+            // answered on the attempt 2
+            let status = if attempt.get() == 2 {
+                PortStatus::Open
+            } else {
+                PortStatus::Filtered
+            };
+            Ok(vec![port_report(addr, t[0].ports[0], status)])
+        })
+        .unwrap();
+        assert_eq!(ret.reports[0].status, PortStatus::Open);
+        assert_eq!(ret.retries[&addr], 1);
+        let context = PortLineContext {
+            retries: ret.retries,
+            ..Default::default()
+        };
+        assert_eq!(
+            retries_tag(context.host_retries(addr)),
+            Some(String::from("(1 retry)"))
+        );
+        assert_eq!(retries_tag(0), None);
+    }
+    #[test]
+    fn test_probe_budget() {
+        let addr = IpAddr::V4(Ipv4Addr::new(192, 168, 5, 5));
+        let targets = vec![Target::new(addr, Some(vec![80]))];
+        // the slow link, every probe of port 80 waits 300ms and gets no reply
+        let mut attempts = 0;
+        let ret = retry_scan(
+            &targets,
+            RetryPolicy::OnNoResponse,
+            5,
+            Some(Duration::from_millis(500)),
+            |t| {
+                attempts += 1;
+                Ok(t[0]
+                    .ports
+                    .iter()
+                    .map(|&port| PortReport {
+                        cost: Duration::from_millis(300),
+                        ..port_report(addr, port, PortStatus::Filtered)
+                    })
+                    .collect())
+            },
+        )
+        .unwrap();
+        assert_eq!(ret.reports.len(), 1);
+        assert_eq!(attempts, 2);
+    }
+    #[test]
+    fn test_scan_error() {
+        let addr = IpAddr::V4(Ipv4Addr::new(192, 168, 5, 5));
+        let targets = vec![Target::new(addr, Some(vec![22, 80]))];
+        // the simulated pistol error is returned instead of the panic and no more attempt is made
+        let mut attempts = 0;
+        let ret = retry_scan(&targets, RetryPolicy::Always, 3, None, |_| {
+            attempts += 1;
+            Err(PslmapError::pistol("tcp syn scan")(
+                PistolError::CanNotFoundInterface,
+            ))
+        });
+        assert_eq!(attempts, 1);
+        match ret {
+            Err(e @ PslmapError::Pistol { scan, .. }) => {
+                assert_eq!(scan, "tcp syn scan");
+                assert!(e.to_string().starts_with("tcp syn scan failed: "));
+            }
+            _ => panic!("expected the pistol error"),
+        }
+    }
+    #[test]
+    fn test_rtt_samples() {
+        let ms = Duration::from_millis;
+        let (median, stddev) = rtt_stats(&[ms(40), ms(10), ms(100), ms(30), ms(20)]).unwrap();
+        assert_eq!(median, ms(30));
+        // mean 40ms, variance (900 + 400 + 100 + 0 + 3600) / 5 = 1000
+        assert!((stddev.as_secs_f64() - 1000f64.sqrt() / 1000.0).abs() < 1e-9);
+        let (median, stddev) = rtt_stats(&[ms(10), ms(30)]).unwrap();
+        assert_eq!(median, ms(20));
+        assert_eq!(stddev, ms(10));
+        assert_eq!(rtt_stats(&[ms(7)]), Some((ms(7), Duration::ZERO)));
+        assert_eq!(rtt_stats(&[]), None);
+
+        let addr = IpAddr::V4(Ipv4Addr::new(192, 168, 9, 9));
+        let report = |port, status, cost| PortReport {
+            cost,
+            ..port_report(addr, port, status)
+        };
+        let reports = vec![
+            report(80, PortStatus::Open, ms(21)),
+            report(81, PortStatus::Closed, ms(5)),
+        ];
+        let mut rounds = 0;
+        let rtts = [ms(18), ms(24)];
+        let sampled = rtt_samples(&reports, 3, |targets| {
+            // only the open ports are probed again
+            assert_eq!(targets[0].ports, vec![80]);
+            rounds += 1;
+            Ok(vec![report(80, PortStatus::Open, rtts[rounds - 1])])
+        })
+        .unwrap();
+        assert_eq!(rounds, 2);
+        assert_eq!(sampled[&(addr, 80)], vec![ms(21), ms(18), ms(24)]);
+        assert!(!sampled.contains_key(&(addr, 81)));
+
+        let context = PortLineContext {
+            samples: sampled,
+            ..Default::default()
+        };
+        let line = port_line(
+            &reports[0],
+            "tcp",
+            PortScanningMethod::TcpSyn,
+            false,
+            &context,
+        );
+        assert_eq!(
+            line.as_deref(),
+            Some("192.168.9.9:80/tcp -> open (median 0.021s ±0.002)")
+        );
+    }
+    #[test]
+    fn test_adaptive_scan() {
+        let ports: Vec<u16> = (1..=1000).collect();
+        let targets: Vec<Target> = (1..=5)
+            .map(|i| Target::new(Ipv4Addr::new(192, 168, 5, i).into(), Some(ports.clone())))
+            .collect();
+        let mut rtt_timeout = RttTimeout::new(1.0, 0.1, 2.0).unwrap();
+        let mut timeouts = Vec::new();
+        let ret = adaptive_scan(&targets, 2, &mut rtt_timeout, |batch, timeout| {
+            timeouts.push(timeout);
+            // the whole hosts of the batch go to one scan
+            assert!(batch.iter().all(|t| t.ports.len() == 1000));
+            let reports = batch
+                .iter()
+                .flat_map(|t| {
+                    t.ports
+                        .iter()
+                        .map(|&port| port_report(t.addr, port, PortStatus::Closed))
+                })
+                .collect();
+            Ok(ScanReports {
+                reports,
+                ..Default::default()
+            })
+        })
+        .unwrap();
+        assert_eq!(ret.reports.len(), 5000);
+        assert_eq!(timeouts.len(), 3);
+        // the fast replies of the first batch shrink the timeout of the next ones
+        assert_eq!(timeouts[0], Duration::from_secs(1));
+        assert!(timeouts[1] < timeouts[0]);
+    }
 }
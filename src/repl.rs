@@ -0,0 +1,141 @@
+/// the interactive prompt (--interactive)
+use std::io::BufRead;
+use std::io::Write;
+
+const PROMPT: &str = "pslmap> ";
+
+const HELP: &str = "commands:
+  target <TARGET>   set the target of the later scans without -t (such as target 192.168.1.0/24)
+  <ARGS>            run one scan with the same args as the command line (such as -p 22,80 ps --syn)
+  help              show this help
+  exit, quit        leave the prompt";
+
+/// Split the command line by the whitespace, the single or double quoted text is kept as one arg.
+fn split_args(line: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut arg = String::new();
+    let mut in_arg = false;
+    let mut quote = None;
+    for c in line.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => arg.push(c),
+            None if c == '"' || c == '\'' => {
+                quote = Some(c);
+                in_arg = true;
+            }
+            None if c.is_whitespace() => {
+                if in_arg {
+                    args.push(std::mem::take(&mut arg));
+                    in_arg = false;
+                }
+            }
+            None => {
+                arg.push(c);
+                in_arg = true;
+            }
+        }
+    }
+    if quote.is_some() {
+        return Err(format!("missing the closing quote in {}", line));
+    }
+    if in_arg {
+        args.push(arg);
+    }
+    Ok(args)
+}
+
+/// The args of one scan, the `target` set before is used when the line has no targets.
+fn command_args(line: &str, target: Option<&str>) -> Result<Vec<String>, String> {
+    let args = split_args(line)?;
+    let has_targets = args.iter().any(|a| {
+        matches!(
+            a.as_str(),
+            "-t" | "--target" | "-f" | "--filename" | "--input" | "--load-targets"
+        )
+    });
+    let mut argv = vec![String::from("pslmap")];
+    if let Some(target) = target
+        && !has_targets
+    {
+        argv.push(String::from("-t"));
+        argv.push(target.to_string());
+    }
+    argv.extend(args);
+    Ok(argv)
+}
+
+/// Read the commands until `exit` or the end of the input, `run` scans with the args of each command.
+/// The failed command only prints the error, the prompt goes on.
+pub fn repl<R, W, F>(input: R, output: &mut W, mut run: F)
+where
+    R: BufRead,
+    W: Write,
+    F: FnMut(Vec<String>) -> Result<(), String>,
+{
+    let mut target: Option<String> = None;
+    let mut lines = input.lines();
+    loop {
+        write!(output, "{}", PROMPT).expect("write prompt failed");
+        output.flush().expect("flush prompt failed");
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => break,
+        };
+        let line = line.trim();
+        let ret = match line.split_once(" ").unwrap_or((line, "")) {
+            ("", _) => Ok(()),
+            ("exit" | "quit", _) => break,
+            ("help", _) => {
+                writeln!(output, "{}", HELP).expect("write help failed");
+                Ok(())
+            }
+            ("target", t) if !t.trim().is_empty() => {
+                target = Some(t.trim().to_string());
+                Ok(())
+            }
+            ("target", _) => Err(String::from("usage: target <TARGET>")),
+            _ => command_args(line, target.as_deref()).and_then(&mut run),
+        };
+        if let Err(e) = ret {
+            writeln!(output, "error: {}", e).expect("write error failed");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_repl() {
+        let script = "target 192.168.5.5\n-p 22,80 ps --syn\n\n-t 10.0.0.1 hd --p1\nbogus\nexit\n-p 443 ps\n";
+        let mut commands = Vec::new();
+        let mut output = Vec::new();
+        repl(script.as_bytes(), &mut output, |argv| {
+            let failed = argv.contains(&String::from("bogus"));
+            commands.push(argv.join(" "));
+            if failed {
+                Err(String::from("unrecognized subcommand 'bogus'"))
+            } else {
+                Ok(())
+            }
+        });
+        // the commands after exit are not run
+        assert_eq!(
+            commands,
+            vec![
+                "pslmap -t 192.168.5.5 -p 22,80 ps --syn",
+                "pslmap -t 10.0.0.1 hd --p1",
+                "pslmap -t 192.168.5.5 bogus",
+            ]
+        );
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("error: unrecognized subcommand 'bogus'"));
+
+        assert_eq!(
+            split_args("-t \"192.168.5.5, 10.0.0.1\" ps").unwrap(),
+            vec!["-t", "192.168.5.5, 10.0.0.1", "ps"]
+        );
+        assert!(split_args("-t 'oops").is_err());
+    }
+}
@@ -0,0 +1,84 @@
+/// adaptive probe timeout (same as nmap --min-rtt-timeout, --max-rtt-timeout and --initial-rtt-timeout)
+use std::time::Duration;
+
+/// Same as nmap, when only some of the bounds are set.
+pub const DEFAULT_MIN_RTT_TIMEOUT: f64 = 0.1;
+pub const DEFAULT_MAX_RTT_TIMEOUT: f64 = 10.0;
+
+/// The timeout is the smoothed rtt plus four times the rtt variation (RFC 6298),
+/// always clamped between the min and max bounds.
+#[derive(Debug, Clone, Copy)]
+pub struct RttTimeout {
+    min: f64,
+    max: f64,
+    srtt: Option<f64>,
+    rttvar: f64,
+    current: f64,
+}
+
+impl RttTimeout {
+    pub fn new(initial: f64, min: f64, max: f64) -> Result<RttTimeout, String> {
+        if min <= 0.0 || min > max {
+            return Err(format!(
+                "invalid rtt timeout bounds: min {}s must be positive and not greater than max {}s",
+                min, max
+            ));
+        }
+        Ok(RttTimeout {
+            min,
+            max,
+            srtt: None,
+            rttvar: 0.0,
+            current: initial.clamp(min, max),
+        })
+    }
+    /// The timeout of the next probes.
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs_f64(self.current)
+    }
+    /// Feed the rtt of one probe that got a response.
+    pub fn update(&mut self, rtt: Duration) {
+        let rtt = rtt.as_secs_f64();
+        match self.srtt {
+            Some(srtt) => {
+                self.rttvar = 0.75 * self.rttvar + 0.25 * (srtt - rtt).abs();
+                self.srtt = Some(0.875 * srtt + 0.125 * rtt);
+            }
+            None => {
+                self.rttvar = rtt / 2.0;
+                self.srtt = Some(rtt);
+            }
+        }
+        let srtt = self.srtt.unwrap_or(rtt);
+        self.current = (srtt + 4.0 * self.rttvar).clamp(self.min, self.max);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_rtt_timeout_bounds() {
+        let min = Duration::from_secs_f64(0.2);
+        let max = Duration::from_secs_f64(2.0);
+        let mut rtt_timeout = RttTimeout::new(5.0, 0.2, 2.0).unwrap();
+        // the initial timeout is clamped too
+        assert_eq!(rtt_timeout.timeout(), max);
+
+        let rtts = [
+            0.001, 0.002, 0.001, 3.0, 9.0, 0.5, 0.001, 0.001, 0.001, 0.001,
+        ];
+        for rtt in rtts {
+            rtt_timeout.update(Duration::from_secs_f64(rtt));
+            let timeout = rtt_timeout.timeout();
+            assert!(timeout >= min && timeout <= max, "{:?}", timeout);
+        }
+        // the fast responses pull the timeout down to the min bound
+        for _ in 0..100 {
+            rtt_timeout.update(Duration::from_secs_f64(0.001));
+        }
+        assert_eq!(rtt_timeout.timeout(), min);
+
+        assert!(RttTimeout::new(1.0, 2.0, 1.0).is_err());
+    }
+}
@@ -0,0 +1,103 @@
+/// saved target list (--save-targets and --load-targets)
+use pistol::Target;
+use serde::Deserialize;
+use serde::Serialize;
+use std::fs;
+use std::net::IpAddr;
+
+/// The version of the saved targets file, any incompatible change bumps it.
+pub const TARGETS_FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct SavedTarget {
+    addr: IpAddr,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    ports: Vec<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    origin: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct SavedTargets {
+    version: u32,
+    targets: Vec<SavedTarget>,
+}
+
+/// The compact (single line) json of the expanded targets.
+fn targets_dump(targets: &[Target]) -> String {
+    let saved = SavedTargets {
+        version: TARGETS_FORMAT_VERSION,
+        targets: targets
+            .iter()
+            .map(|t| SavedTarget {
+                addr: t.addr,
+                ports: t.ports.clone(),
+                origin: t.origin.clone(),
+            })
+            .collect(),
+    };
+    serde_json::to_string(&saved).expect("serialize targets failed")
+}
+
+fn targets_load(content: &str) -> Result<Vec<Target>, String> {
+    let saved: SavedTargets =
+        serde_json::from_str(content).map_err(|e| format!("invalid targets file: {}", e))?;
+    if saved.version != TARGETS_FORMAT_VERSION {
+        return Err(format!(
+            "targets file version {} does not match the supported version {}",
+            saved.version, TARGETS_FORMAT_VERSION
+        ));
+    }
+    Ok(saved
+        .targets
+        .into_iter()
+        .map(|t| Target {
+            addr: t.addr,
+            ports: t.ports,
+            origin: t.origin,
+        })
+        .collect())
+}
+
+pub fn save_targets(filename: &str, targets: &[Target]) -> Result<(), String> {
+    fs::write(filename, targets_dump(targets))
+        .map_err(|e| format!("write {} failed: {}", filename, e))
+}
+
+pub fn load_targets(filename: &str) -> Result<Vec<Target>, String> {
+    let content =
+        fs::read_to_string(filename).map_err(|e| format!("read {} failed: {}", filename, e))?;
+    targets_load(&content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tp::TargetParser;
+    #[test]
+    fn test_save_load_targets() {
+        let mut parser = TargetParser::new();
+        let mut targets = parser.target_from_input("192.168.5.0/30", Some(String::from("22,80")));
+        targets.push(Target::new("fe80::1".parse().unwrap(), None));
+
+        let filename =
+            std::env::temp_dir().join(format!("pslmap_targets_{}.json", std::process::id()));
+        let filename = filename.to_str().unwrap();
+        save_targets(filename, &targets).unwrap();
+        let loaded = load_targets(filename).unwrap();
+        fs::remove_file(filename).unwrap();
+        let fields = |targets: &[Target]| -> Vec<(IpAddr, Vec<u16>, Option<String>)> {
+            targets
+                .iter()
+                .map(|t| (t.addr, t.ports.clone(), t.origin.clone()))
+                .collect()
+        };
+        assert_eq!(fields(&loaded), fields(&targets));
+
+        let content = targets_dump(&targets).replace("\"version\":1", "\"version\":2");
+        assert_eq!(
+            targets_load(&content).unwrap_err(),
+            "targets file version 2 does not match the supported version 1"
+        );
+    }
+}
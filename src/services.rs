@@ -0,0 +1,42 @@
+/// the service names guessed from the port numbers (--service-names)
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+/// (port, protocol) -> name, such as (22, "tcp") -> "ssh".
+static SERVICES: LazyLock<HashMap<(u16, String), String>> = LazyLock::new(|| {
+    let services_txt = include_str!("./db/services.txt");
+    let mut services = HashMap::new();
+    for line in services_txt.lines() {
+        if line.starts_with("#") {
+            continue;
+        }
+        if let Some((name, port_proto)) = line.split_once(" ")
+            && let Some((port, protocol)) = port_proto.split_once("/")
+            && let Ok(port) = port.parse::<u16>()
+        {
+            services
+                .entry((port, protocol.to_string()))
+                .or_insert(name.to_string());
+        }
+    }
+    services
+});
+
+/// Only the port number is used, the service actually running on it may differ.
+pub fn service_name(port: u16, protocol: &str) -> Option<&'static str> {
+    SERVICES
+        .get(&(port, protocol.to_string()))
+        .map(|name| name.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_service_name() {
+        assert_eq!(service_name(22, "tcp"), Some("ssh"));
+        assert_eq!(service_name(80, "tcp"), Some("http"));
+        assert_eq!(service_name(53, "udp"), Some("domain"));
+        assert_eq!(service_name(54532, "tcp"), None);
+    }
+}
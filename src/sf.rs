@@ -0,0 +1,288 @@
+/// custom tcp flags scan (same as nmap --scanflags)
+use pistol::Target;
+use pistol::layer::Layer3Match;
+use pistol::layer::Layer4MatchIcmp;
+use pistol::layer::Layer4MatchTcpUdp;
+use pistol::layer::LayerMatch;
+use pistol::layer::PayloadMatch;
+use pistol::layer::PayloadMatchIp;
+use pistol::layer::PayloadMatchTcpUdp;
+use pistol::layer::infer_addr;
+use pistol::layer::layer3_ipv4_send;
+use pistol::scan::PortReport;
+use pistol::scan::PortStatus;
+use pnet::packet::Packet;
+use pnet::packet::icmp::IcmpPacket;
+use pnet::packet::icmp::IcmpTypes;
+use pnet::packet::ip::IpNextHeaderProtocols;
+use pnet::packet::ipv4::Ipv4Packet;
+use pnet::packet::tcp::TcpFlags;
+use pnet::packet::tcp::TcpPacket;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::SocketAddr;
+use std::sync::LazyLock;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::PROBE_DATA;
+use crate::SRC_ADDR;
+use crate::bs::build_tcp_packet;
+use crate::sport::next_source_port;
+
+const TCP_FLAG_NAMES: [(&str, u8); 8] = [
+    ("CWR", TcpFlags::CWR),
+    ("ECE", TcpFlags::ECE),
+    ("URG", TcpFlags::URG),
+    ("ACK", TcpFlags::ACK),
+    ("PSH", TcpFlags::PSH),
+    ("RST", TcpFlags::RST),
+    ("SYN", TcpFlags::SYN),
+    ("FIN", TcpFlags::FIN),
+];
+
+/// Such as `SYNFIN` or `URGACKPSHRSTSYNFIN` (in any order and case), the number (`9` or `0x09`) is the raw bitmask.
+pub fn scanflags_parser(flags_str: &str) -> Result<u8, String> {
+    let s = flags_str.trim();
+    if let Some(hex) = s.strip_prefix("0x") {
+        return u8::from_str_radix(hex, 16).map_err(|_| format!("invalid tcp flags {}", flags_str));
+    }
+    if let Ok(flags) = s.parse::<u8>() {
+        return Ok(flags);
+    }
+    let upper = s.to_uppercase();
+    let mut flags = 0;
+    let mut rest = upper.as_str();
+    while !rest.is_empty() {
+        match TCP_FLAG_NAMES
+            .iter()
+            .find(|(name, _)| rest.starts_with(name))
+        {
+            Some((name, flag)) => {
+                flags |= flag;
+                rest = &rest[name.len()..];
+            }
+            None => {
+                let names: Vec<&str> = TCP_FLAG_NAMES.iter().map(|(name, _)| *name).collect();
+                return Err(format!(
+                    "unknown tcp flag {} in {} (valid: {})",
+                    rest,
+                    flags_str,
+                    names.join(", ")
+                ));
+            }
+        }
+    }
+    Ok(flags)
+}
+
+/// The icmp destination unreachable of the probes, kept for the --reason of the filtered ports.
+static ICMP_REPLIES: LazyLock<Mutex<HashMap<SocketAddr, (u8, u8)>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// The (type, code) of the icmp unreachable the probe to the port got.
+pub fn icmp_reply(addr: IpAddr, port: u16) -> Option<(u8, u8)> {
+    let replies = ICMP_REPLIES.lock().expect("lock ICMP_REPLIES failed");
+    replies.get(&SocketAddr::new(addr, port)).copied()
+}
+
+/// The name of the icmp destination unreachable code (RFC 792 and RFC 1812),
+/// the prohibited codes mean a firewall dropped the probe.
+pub fn icmp_reason(icmp_type: u8, icmp_code: u8) -> Option<&'static str> {
+    if icmp_type != IcmpTypes::DestinationUnreachable.0 {
+        return None;
+    }
+    match icmp_code {
+        0 => Some("net-unreachable"),
+        1 => Some("host-unreachable"),
+        2 => Some("protocol-unreachable"),
+        3 => Some("port-unreachable"),
+        9 => Some("net-prohibited"),
+        10 => Some("host-prohibited"),
+        13 => Some("admin-prohibited"),
+        _ => None,
+    }
+}
+
+fn icmp_type_code(response: &[u8]) -> Option<(u8, u8)> {
+    let ipv4_packet = Ipv4Packet::new(response)?;
+    if ipv4_packet.get_next_level_protocol() != IpNextHeaderProtocols::Icmp {
+        return None;
+    }
+    let icmp_packet = IcmpPacket::new(ipv4_packet.payload())?;
+    Some((icmp_packet.get_icmp_type().0, icmp_packet.get_icmp_code().0))
+}
+
+/// Same as nmap, the responses are read as the syn scan does:
+/// syn/ack means open, rst means closed, no response or icmp unreachable means filtered.
+fn custom_flags_status(response: &[u8]) -> PortStatus {
+    let ipv4_packet = match Ipv4Packet::new(response) {
+        Some(ipv4_packet) if !response.is_empty() => ipv4_packet,
+        _ => return PortStatus::Filtered,
+    };
+    if ipv4_packet.get_next_level_protocol() == IpNextHeaderProtocols::Tcp
+        && let Some(tcp_packet) = TcpPacket::new(ipv4_packet.payload())
+    {
+        let flags = tcp_packet.get_flags();
+        if flags & TcpFlags::RST == TcpFlags::RST {
+            return PortStatus::Closed;
+        }
+        if flags & (TcpFlags::SYN | TcpFlags::ACK) == TcpFlags::SYN | TcpFlags::ACK {
+            return PortStatus::Open;
+        }
+    }
+    PortStatus::Filtered
+}
+
+fn send_custom_flags_probe(
+    dst_ipv4: Ipv4Addr,
+    dst_port: u16,
+    src_ipv4: Ipv4Addr,
+    flags: u8,
+    timeout: Option<Duration>,
+    payload: &[u8],
+) -> (PortStatus, Duration) {
+    let src_port = next_source_port();
+    let packet = build_tcp_packet(
+        dst_ipv4, dst_port, src_ipv4, src_port, flags, false, payload,
+    );
+
+    let layer3 = Layer3Match {
+        name: "scanflags layer3",
+        layer2: None,
+        src_addr: Some(dst_ipv4.into()),
+        dst_addr: Some(src_ipv4.into()),
+    };
+    let layer4_tcp_udp = Layer4MatchTcpUdp {
+        name: "scanflags tcp_udp",
+        layer3: Some(layer3),
+        src_port: Some(dst_port),
+        dst_port: Some(src_port),
+    };
+    let payload_ip = PayloadMatchIp {
+        src_addr: Some(src_ipv4.into()),
+        dst_addr: Some(dst_ipv4.into()),
+    };
+    let payload_tcpudp = PayloadMatchTcpUdp {
+        layer3: Some(payload_ip),
+        src_port: Some(src_port),
+        dst_port: Some(dst_port),
+    };
+    let layer4_icmp = Layer4MatchIcmp {
+        name: "scanflags icmp",
+        layer3: Some(layer3),
+        icmp_type: None,
+        icmp_code: None,
+        payload: Some(PayloadMatch::PayloadMatchTcpUdp(payload_tcpudp)),
+    };
+    let layer_matchs = vec![
+        LayerMatch::Layer4MatchTcpUdp(layer4_tcp_udp),
+        LayerMatch::Layer4MatchIcmp(layer4_icmp),
+    ];
+
+    match layer3_ipv4_send(dst_ipv4, src_ipv4, &packet, layer_matchs, timeout, true) {
+        Ok((ret, rtt)) => {
+            if let Some(type_code) = icmp_type_code(&ret) {
+                let mut replies = ICMP_REPLIES.lock().expect("lock ICMP_REPLIES failed");
+                replies.insert(SocketAddr::new(dst_ipv4.into(), dst_port), type_code);
+            }
+            (custom_flags_status(&ret), rtt)
+        }
+        Err(_) => (PortStatus::Error, Duration::ZERO),
+    }
+}
+
+/// Send one probe with the `flags` to each port, only IPv4 targets are supported.
+pub fn custom_flags_scan(
+    targets: &[Target],
+    flags: u8,
+    timeout: Option<Duration>,
+    num_threads: usize,
+) -> Vec<PortReport> {
+    let src_addr = *SRC_ADDR.lock().expect("lock SRC_ADDR failed");
+    let payload = PROBE_DATA.lock().expect("lock PROBE_DATA failed").clone();
+    let payload = payload.as_slice();
+    let mut probes = Vec::new();
+    for t in targets {
+        if let IpAddr::V4(dst_ipv4) = t.addr
+            && let Ok(Some(ia)) = infer_addr(t.addr, src_addr)
+            && let IpAddr::V4(src_ipv4) = ia.src_addr
+        {
+            for &port in &t.ports {
+                probes.push((dst_ipv4, port, src_ipv4, t.origin.clone()));
+            }
+        } else {
+            eprintln!("warning: --scanflags only supports IPv4, skip {}", t.addr);
+        }
+    }
+
+    let chunk_size = probes.len().div_ceil(num_threads.max(1)).max(1);
+    std::thread::scope(|s| {
+        let handles: Vec<_> = probes
+            .chunks(chunk_size)
+            .map(|chunk| {
+                s.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|(dst_ipv4, port, src_ipv4, origin)| {
+                            let (status, cost) = send_custom_flags_probe(
+                                *dst_ipv4, *port, *src_ipv4, flags, timeout, payload,
+                            );
+                            PortReport {
+                                addr: (*dst_ipv4).into(),
+                                port: *port,
+                                origin: origin.clone(),
+                                status,
+                                cost,
+                            }
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().expect("scanflags thread panicked"))
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_scanflags_parser() {
+        let flags = scanflags_parser("SYNFIN").unwrap();
+        assert_eq!(flags, 0b0000_0011);
+        assert_eq!(flags, TcpFlags::SYN | TcpFlags::FIN);
+        assert_eq!(scanflags_parser("finsyn"), Ok(flags));
+        assert_eq!(scanflags_parser("URGACKPSHRSTSYNFIN"), Ok(0b0011_1111));
+        assert_eq!(scanflags_parser("9"), Ok(TcpFlags::FIN | TcpFlags::PSH));
+        assert_eq!(scanflags_parser("0x12"), Ok(TcpFlags::SYN | TcpFlags::ACK));
+
+        let err = scanflags_parser("SYNFOO").unwrap_err();
+        assert!(err.starts_with("unknown tcp flag FOO in SYNFOO"));
+
+        let packet = build_tcp_packet(
+            Ipv4Addr::new(192, 168, 5, 5),
+            80,
+            Ipv4Addr::new(192, 168, 5, 3),
+            40000,
+            TcpFlags::SYN | TcpFlags::ACK,
+            false,
+            &[],
+        );
+        assert_eq!(custom_flags_status(&packet), PortStatus::Open);
+        assert_eq!(custom_flags_status(&[]), PortStatus::Filtered);
+        assert_eq!(icmp_type_code(&packet), None);
+    }
+    #[test]
+    fn test_icmp_reason() {
+        assert_eq!(icmp_reason(3, 13), Some("admin-prohibited"));
+        assert_eq!(icmp_reason(3, 3), Some("port-unreachable"));
+        assert_eq!(icmp_reason(3, 4), None);
+        // not the destination unreachable
+        assert_eq!(icmp_reason(11, 0), None);
+    }
+}
@@ -0,0 +1,251 @@
+/// arp discovery with the spoofed source mac (same as nmap --spoof-mac)
+use pistol::Target;
+use pistol::scan::MacReport;
+use pistol::scan::PistolMacScans;
+use pnet::datalink;
+use pnet::datalink::Channel::Ethernet;
+use pnet::datalink::Config;
+use pnet::datalink::MacAddr;
+use pnet::datalink::NetworkInterface;
+use pnet::ipnetwork::IpNetwork;
+use pnet::packet::Packet;
+use pnet::packet::arp::ArpHardwareTypes;
+use pnet::packet::arp::ArpOperations;
+use pnet::packet::arp::ArpPacket;
+use pnet::packet::arp::MutableArpPacket;
+use pnet::packet::ethernet::EtherTypes;
+use pnet::packet::ethernet::EthernetPacket;
+use pnet::packet::ethernet::MutableEthernetPacket;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::time::Duration;
+use std::time::Instant;
+
+const ETHERNET_HEADER_SIZE: usize = 14;
+const ARP_HEADER_SIZE: usize = 28;
+/// The read timeout of the datalink channel, the deadline is checked after each read.
+const READ_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// Such as `00:11:22:33:44:55` or `00-11-22-33-44-55`.
+pub fn mac_parser(mac_str: &str) -> Result<MacAddr, String> {
+    let mac: MacAddr = mac_str.trim().replace("-", ":").parse().map_err(|_| {
+        format!(
+            "invalid source mac {}: expected six hex octets such as 00:11:22:33:44:55",
+            mac_str
+        )
+    })?;
+    if mac.0 & 0x01 == 0x01 {
+        return Err(format!(
+            "invalid source mac {}: the multicast or broadcast mac can not be the source",
+            mac_str
+        ));
+    }
+    if mac == MacAddr::zero() {
+        return Err(format!(
+            "invalid source mac {}: the zero mac can not be the source",
+            mac_str
+        ));
+    }
+    Ok(mac)
+}
+
+/// The IPv4 address of the network that contains `dst_ipv4`, None means the target is routed.
+fn on_link_src(dst_ipv4: Ipv4Addr, ips: &[IpNetwork]) -> Option<Ipv4Addr> {
+    ips.iter().find_map(|ipn| match ipn {
+        IpNetwork::V4(ipv4n) if ipv4n.contains(dst_ipv4) => Some(ipv4n.ip()),
+        _ => None,
+    })
+}
+
+/// The interface and the source address to reach the on-link target,
+/// the spoofed frames never reach the routed targets (the gateway answers the arp instead).
+fn on_link_interface(
+    dst_addr: IpAddr,
+    src_addr: Option<IpAddr>,
+) -> Result<(NetworkInterface, Ipv4Addr), String> {
+    let dst_ipv4 = match dst_addr {
+        IpAddr::V4(dst_ipv4) => dst_ipv4,
+        IpAddr::V6(_) => {
+            return Err(format!(
+                "target {} is IPv6, --source-mac only works with the arp (IPv4) discovery",
+                dst_addr
+            ));
+        }
+    };
+    for interface in datalink::interfaces() {
+        if let Some(src_ipv4) = on_link_src(dst_ipv4, &interface.ips)
+            && (src_addr.is_none() || src_addr == Some(src_ipv4.into()))
+        {
+            return Ok((interface, src_ipv4));
+        }
+    }
+    Err(format!(
+        "target {} is not on-link (routed), --source-mac only works on the local network",
+        dst_addr
+    ))
+}
+
+/// All the targets must be on the local network.
+pub fn source_mac_check(targets: &[Target], src_addr: Option<IpAddr>) -> Result<(), String> {
+    for t in targets {
+        on_link_interface(t.addr, src_addr)?;
+    }
+    Ok(())
+}
+
+fn build_arp_frame(dst_ipv4: Ipv4Addr, src_ipv4: Ipv4Addr, src_mac: MacAddr) -> Vec<u8> {
+    let mut buff = vec![0u8; ETHERNET_HEADER_SIZE + ARP_HEADER_SIZE];
+    let mut ethernet_packet =
+        MutableEthernetPacket::new(&mut buff).expect("build ethernet packet failed");
+    ethernet_packet.set_destination(MacAddr::broadcast());
+    ethernet_packet.set_source(src_mac);
+    ethernet_packet.set_ethertype(EtherTypes::Arp);
+
+    let mut arp_packet =
+        MutableArpPacket::new(&mut buff[ETHERNET_HEADER_SIZE..]).expect("build arp packet failed");
+    arp_packet.set_hardware_type(ArpHardwareTypes::Ethernet);
+    arp_packet.set_protocol_type(EtherTypes::Ipv4);
+    arp_packet.set_hw_addr_len(6);
+    arp_packet.set_proto_addr_len(4);
+    arp_packet.set_operation(ArpOperations::Request);
+    arp_packet.set_sender_hw_addr(src_mac);
+    arp_packet.set_sender_proto_addr(src_ipv4);
+    arp_packet.set_target_hw_addr(MacAddr::zero());
+    arp_packet.set_target_proto_addr(dst_ipv4);
+    buff
+}
+
+/// The mac of the arp reply from `dst_ipv4` to the spoofed `src_mac`.
+fn arp_reply_mac(frame: &[u8], dst_ipv4: Ipv4Addr, src_mac: MacAddr) -> Option<MacAddr> {
+    let ethernet_packet = EthernetPacket::new(frame)?;
+    if ethernet_packet.get_ethertype() != EtherTypes::Arp {
+        return None;
+    }
+    let arp_packet = ArpPacket::new(ethernet_packet.payload())?;
+    if arp_packet.get_operation() == ArpOperations::Reply
+        && arp_packet.get_sender_proto_addr() == dst_ipv4
+        && arp_packet.get_target_hw_addr() == src_mac
+    {
+        Some(arp_packet.get_sender_hw_addr())
+    } else {
+        None
+    }
+}
+
+/// The reply is sent to the spoofed mac, so the channel is opened in promiscuous mode.
+fn send_arp_with_mac(
+    dst_ipv4: Ipv4Addr,
+    src_mac: MacAddr,
+    src_addr: Option<IpAddr>,
+    timeout: Duration,
+) -> (Option<MacAddr>, Duration) {
+    let (interface, src_ipv4) = match on_link_interface(dst_ipv4.into(), src_addr) {
+        Ok(r) => r,
+        Err(_) => return (None, Duration::ZERO),
+    };
+    let config = Config {
+        read_timeout: Some(READ_TIMEOUT.min(timeout)),
+        write_timeout: Some(timeout),
+        promiscuous: true,
+        ..Default::default()
+    };
+    let (mut tx, mut rx) = match datalink::channel(&interface, config) {
+        Ok(Ethernet(tx, rx)) => (tx, rx),
+        _ => return (None, Duration::ZERO),
+    };
+
+    let frame = build_arp_frame(dst_ipv4, src_ipv4, src_mac);
+    let start = Instant::now();
+    match tx.send_to(&frame, None) {
+        Some(Ok(_)) => (),
+        _ => return (None, Duration::ZERO),
+    }
+    while start.elapsed() < timeout {
+        if let Ok(frame) = rx.next()
+            && let Some(mac) = arp_reply_mac(frame, dst_ipv4, src_mac)
+        {
+            return (Some(mac), start.elapsed());
+        }
+    }
+    (None, start.elapsed())
+}
+
+/// Same as the pistol mac scan but the arp requests are sent from `src_mac`,
+/// only the on-link IPv4 targets are supported and the ouis are not looked up.
+pub fn mac_scan_with_source(
+    targets: &[Target],
+    src_mac: MacAddr,
+    num_threads: usize,
+    src_addr: Option<IpAddr>,
+    timeout: Option<Duration>,
+    max_attempts: usize,
+) -> PistolMacScans {
+    let timeout = timeout.unwrap_or(Duration::from_secs(1));
+    let probes: Vec<Ipv4Addr> = targets
+        .iter()
+        .filter_map(|t| match t.addr {
+            IpAddr::V4(dst_ipv4) => Some(dst_ipv4),
+            IpAddr::V6(_) => None,
+        })
+        .collect();
+
+    let chunk_size = probes.len().div_ceil(num_threads.max(1)).max(1);
+    let mac_reports: Vec<MacReport> = std::thread::scope(|s| {
+        let handles: Vec<_> = probes
+            .chunks(chunk_size)
+            .map(|chunk| {
+                s.spawn(move || {
+                    let mut reports = Vec::new();
+                    for &dst_ipv4 in chunk {
+                        let mut ret = (None, Duration::ZERO);
+                        for _ in 0..max_attempts {
+                            ret = send_arp_with_mac(dst_ipv4, src_mac, src_addr, timeout);
+                            if ret.0.is_some() {
+                                break;
+                            }
+                        }
+                        reports.push(MacReport {
+                            addr: dst_ipv4.into(),
+                            mac: ret.0,
+                            ouis: String::from("unknown"),
+                            rtt: ret.1,
+                        });
+                    }
+                    reports
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().expect("arp thread panicked"))
+            .collect()
+    });
+
+    let mut ret = PistolMacScans::new(max_attempts);
+    ret.finish(mac_reports);
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_mac_parser() {
+        let mac = mac_parser("00:0c:29:5b:bd:5c").unwrap();
+        assert_eq!(mac, MacAddr(0x00, 0x0c, 0x29, 0x5b, 0xbd, 0x5c));
+        assert_eq!(mac_parser("00-0C-29-5B-BD-5C").unwrap(), mac);
+
+        let err = mac_parser("00:0c:29:5b:bd").unwrap_err();
+        assert!(err.contains("expected six hex octets"));
+        assert!(mac_parser("00:0c:29:5b:bd:zz").is_err());
+        let err = mac_parser("ff:ff:ff:ff:ff:ff").unwrap_err();
+        assert!(err.contains("multicast or broadcast"));
+
+        let ips: Vec<IpNetwork> = vec!["192.168.5.3/24".parse().unwrap()];
+        assert_eq!(
+            on_link_src(Ipv4Addr::new(192, 168, 5, 5), &ips),
+            Some(Ipv4Addr::new(192, 168, 5, 3))
+        );
+        assert_eq!(on_link_src(Ipv4Addr::new(8, 8, 8, 8), &ips), None);
+    }
+}
@@ -0,0 +1,83 @@
+/// source port cycling of the raw probes (--source-port-range)
+use crate::SOURCE_PORTS;
+
+/// Same as the range of the random source ports before.
+pub const DEFAULT_SOURCE_PORT_RANGE: (u16, u16) = (10000, 65535);
+
+/// Such as `40000-40999`.
+pub fn source_port_range_parser(range_str: &str) -> Result<(u16, u16), String> {
+    let invalid = || {
+        format!(
+            "invalid source port range {}: it must be lo-hi in 1-65535",
+            range_str
+        )
+    };
+    let (lo, hi) = range_str.split_once("-").ok_or_else(invalid)?;
+    let lo: u16 = lo.trim().parse().map_err(|_| invalid())?;
+    let hi: u16 = hi.trim().parse().map_err(|_| invalid())?;
+    if lo == 0 || lo > hi {
+        return Err(invalid());
+    }
+    Ok((lo, hi))
+}
+
+/// Each probe takes the next port of the range (wrapped at the end),
+/// so the consecutive probes never share the same conntrack entry until the range is used up.
+/// The start is random, the repeated runs do not begin with the same ports.
+#[derive(Debug, Clone)]
+pub struct SourcePortCycle {
+    lo: u16,
+    hi: u16,
+    next: u16,
+}
+
+impl SourcePortCycle {
+    pub fn new(lo: u16, hi: u16) -> SourcePortCycle {
+        SourcePortCycle {
+            lo,
+            hi,
+            next: rand::random_range(lo..=hi),
+        }
+    }
+    pub fn next_port(&mut self) -> u16 {
+        let port = self.next;
+        self.next = if port >= self.hi { self.lo } else { port + 1 };
+        port
+    }
+}
+
+impl Default for SourcePortCycle {
+    fn default() -> Self {
+        let (lo, hi) = DEFAULT_SOURCE_PORT_RANGE;
+        SourcePortCycle::new(lo, hi)
+    }
+}
+
+/// The source port of the next raw probe built by pslmap.
+pub fn next_source_port() -> u16 {
+    SOURCE_PORTS
+        .lock()
+        .expect("lock SOURCE_PORTS failed")
+        .next_port()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_source_port_cycle() {
+        let (lo, hi) = source_port_range_parser("40000-40003").unwrap();
+        let mut cycle = SourcePortCycle::new(lo, hi);
+        let ports: Vec<u16> = (0..4).map(|_| cycle.next_port()).collect();
+        assert!(ports.iter().all(|p| (lo..=hi).contains(p)));
+        let mut sorted = ports.clone();
+        sorted.sort();
+        assert_eq!(sorted, vec![40000, 40001, 40002, 40003]);
+        // wrapped at the end of the range
+        assert_eq!(cycle.next_port(), ports[0]);
+
+        assert!(source_port_range_parser("0-100").is_err());
+        assert!(source_port_range_parser("200-100").is_err());
+        assert!(source_port_range_parser("40000").is_err());
+    }
+}
@@ -0,0 +1,172 @@
+/// the estimated packets and bytes of the port scan (--stats)
+use pistol::scan::PortReport;
+use pistol::scan::PortStatus;
+use std::sync::LazyLock;
+use std::sync::Mutex;
+
+use crate::ps::PortScanningMethod;
+
+const IPV4_HEADER_SIZE: usize = 20;
+const IPV6_HEADER_SIZE: usize = 40;
+const TCP_HEADER_SIZE: usize = 20;
+/// The mss option of the raw tcp probes.
+const TCP_RAW_OPTIONS_SIZE: usize = 4;
+/// The mss, sack, timestamp and window scale options of the system connect.
+const TCP_CONNECT_OPTIONS_SIZE: usize = 20;
+const UDP_HEADER_SIZE: usize = 8;
+/// The icmp unreachable header followed by the quoted ip header and 8 bytes of the probe.
+const ICMP_HEADER_SIZE: usize = 8;
+
+/// Pistol does not count the packets, so they are estimated from the reports:
+/// every attempt sends the probe once, and the reply is counted when the status needs one
+/// (such as the syn/ack of open or the rst of closed, the filtered ports got nothing).
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PacketStats {
+    pub sent: usize,
+    pub sent_bytes: usize,
+    pub rcvd: usize,
+    pub rcvd_bytes: usize,
+}
+
+impl PacketStats {
+    pub fn add(&mut self, ps_method: PortScanningMethod, report: &PortReport) {
+        let ip_header = if report.addr.is_ipv6() {
+            IPV6_HEADER_SIZE
+        } else {
+            IPV4_HEADER_SIZE
+        };
+        let tcp_probe = ip_header + TCP_HEADER_SIZE + TCP_RAW_OPTIONS_SIZE;
+        let tcp_reply = ip_header + TCP_HEADER_SIZE;
+        let replied = matches!(
+            report.status,
+            PortStatus::Open | PortStatus::Closed | PortStatus::Unfiltered
+        );
+        // (packets, bytes) of the probe and the reply
+        let (probe, reply) = match ps_method {
+            // the probe was never sent
+            _ if report.status == PortStatus::Error => ((0, 0), (0, 0)),
+            PortScanningMethod::TcpConnect => {
+                let size = ip_header + TCP_HEADER_SIZE + TCP_CONNECT_OPTIONS_SIZE;
+                if replied {
+                    ((1, size), (1, size))
+                } else {
+                    ((1, size), (0, 0))
+                }
+            }
+            PortScanningMethod::Udp => {
+                let size = ip_header + UDP_HEADER_SIZE;
+                let icmp_size = ip_header + ICMP_HEADER_SIZE + size;
+                match report.status {
+                    PortStatus::Open => ((1, size), (1, size)),
+                    PortStatus::Closed => ((1, size), (1, icmp_size)),
+                    _ => ((1, size), (0, 0)),
+                }
+            }
+            // two probes to the zombie around the spoofed syn, the zombie answers both
+            PortScanningMethod::TcpIdle => ((3, 3 * tcp_probe), (2, 2 * tcp_reply)),
+            _ if replied => ((1, tcp_probe), (1, tcp_reply)),
+            _ => ((1, tcp_probe), (0, 0)),
+        };
+        self.sent += probe.0;
+        self.sent_bytes += probe.1;
+        self.rcvd += reply.0;
+        self.rcvd_bytes += reply.1;
+    }
+    /// Such as `Raw packets sent: 1234 (54.3KB) | Rcvd: 567 (22.7KB)`.
+    pub fn line(&self) -> String {
+        format!(
+            "Raw packets sent: {} ({}) | Rcvd: {} ({})",
+            self.sent,
+            bytes_str(self.sent_bytes),
+            self.rcvd,
+            bytes_str(self.rcvd_bytes)
+        )
+    }
+}
+
+fn bytes_str(bytes: usize) -> String {
+    if bytes < 1000 {
+        format!("{}B", bytes)
+    } else if bytes < 1000 * 1000 {
+        format!("{:.1}KB", bytes as f64 / 1000.0)
+    } else {
+        format!("{:.1}MB", bytes as f64 / 1000.0 / 1000.0)
+    }
+}
+
+static PACKET_STATS: LazyLock<Mutex<PacketStats>> =
+    LazyLock::new(|| Mutex::new(PacketStats::default()));
+
+/// Count the reports of one attempt, the retries send the probes again.
+pub fn record_reports(ps_method: PortScanningMethod, reports: &[PortReport]) {
+    let mut stats = PACKET_STATS.lock().expect("lock PACKET_STATS failed");
+    for report in reports {
+        stats.add(ps_method, report);
+    }
+}
+
+/// The stats since the last call, each round of --repeat starts from zero.
+pub fn take_stats() -> PacketStats {
+    std::mem::take(&mut *PACKET_STATS.lock().expect("lock PACKET_STATS failed"))
+}
+
+static STATS: LazyLock<Mutex<bool>> = LazyLock::new(|| Mutex::new(false));
+
+pub fn set_stats(stats: bool) {
+    *STATS.lock().expect("lock STATS failed") = stats;
+}
+
+/// Append the stats line to the summary tail with --stats.
+pub fn stats_tail(tail: String) -> String {
+    let stats = take_stats();
+    if *STATS.lock().expect("lock STATS failed") {
+        format!("{}\n{}", tail, stats.line())
+    } else {
+        tail
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+    use std::time::Duration;
+    #[test]
+    fn test_packet_stats() {
+        let report = |port: u16, status: PortStatus| PortReport {
+            addr: Ipv4Addr::new(192, 168, 5, 5).into(),
+            port,
+            origin: None,
+            status,
+            cost: Duration::from_millis(10),
+        };
+        let mut stats = PacketStats::default();
+        for port in 1..=1000 {
+            let status = if port <= 400 {
+                PortStatus::Closed
+            } else {
+                PortStatus::Filtered
+            };
+            stats.add(PortScanningMethod::TcpSyn, &report(port, status));
+        }
+        assert_eq!(
+            stats,
+            PacketStats {
+                sent: 1000,
+                sent_bytes: 44000,
+                rcvd: 400,
+                rcvd_bytes: 16000,
+            }
+        );
+        assert_eq!(
+            stats.line(),
+            "Raw packets sent: 1000 (44.0KB) | Rcvd: 400 (16.0KB)"
+        );
+
+        let mut stats = PacketStats::default();
+        stats.add(PortScanningMethod::Udp, &report(53, PortStatus::Closed));
+        assert_eq!(stats.sent_bytes, 28);
+        assert_eq!(stats.rcvd_bytes, 56);
+        assert_eq!(stats.line(), "Raw packets sent: 1 (28B) | Rcvd: 1 (56B)");
+    }
+}
@@ -0,0 +1,177 @@
+/// send the results to the local syslog (--syslog)
+use ::syslog::Formatter3164;
+use ::syslog::Logger;
+use clap::ValueEnum;
+use serde_json::Value;
+use std::io::Write;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use crate::output::OutputSink;
+use crate::output::Record;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Facility {
+    User,
+    Daemon,
+    Auth,
+    Local0,
+    Local1,
+    Local2,
+    Local3,
+    Local4,
+    Local5,
+    Local6,
+    Local7,
+}
+
+impl Facility {
+    fn facility(&self) -> ::syslog::Facility {
+        match self {
+            Facility::User => ::syslog::Facility::LOG_USER,
+            Facility::Daemon => ::syslog::Facility::LOG_DAEMON,
+            Facility::Auth => ::syslog::Facility::LOG_AUTH,
+            Facility::Local0 => ::syslog::Facility::LOG_LOCAL0,
+            Facility::Local1 => ::syslog::Facility::LOG_LOCAL1,
+            Facility::Local2 => ::syslog::Facility::LOG_LOCAL2,
+            Facility::Local3 => ::syslog::Facility::LOG_LOCAL3,
+            Facility::Local4 => ::syslog::Facility::LOG_LOCAL4,
+            Facility::Local5 => ::syslog::Facility::LOG_LOCAL5,
+            Facility::Local6 => ::syslog::Facility::LOG_LOCAL6,
+            Facility::Local7 => ::syslog::Facility::LOG_LOCAL7,
+        }
+    }
+    /// The RFC 3164 header, such as `<134>Jan 01 00:00:00 pslmap[1234]: `.
+    fn formatter(&self) -> Formatter3164 {
+        Formatter3164 {
+            facility: self.facility(),
+            hostname: None,
+            process: String::from("pslmap"),
+            pid: std::process::id(),
+        }
+    }
+}
+
+/// Where the syslog messages go, replaced in the tests.
+pub trait SyslogSink: Send {
+    fn send(&mut self, message: &str) -> Result<(), String>;
+}
+
+/// The messages are sent with the informational severity.
+impl<W: Write + Send> SyslogSink for Logger<W, Formatter3164> {
+    fn send(&mut self, message: &str) -> Result<(), String> {
+        self.info(message).map_err(|e| e.to_string())
+    }
+}
+
+struct SyslogState {
+    sink: Box<dyn SyslogSink>,
+    failed: bool,
+}
+
+/// The clones share the same sink, so all the emitters of one run write to one socket.
+#[derive(Clone)]
+pub struct Syslog {
+    /// The results only go to syslog, not stdout.
+    pub only: bool,
+    state: Arc<Mutex<SyslogState>>,
+}
+
+impl Syslog {
+    pub fn new(only: bool, sink: Box<dyn SyslogSink>) -> Syslog {
+        let state = SyslogState {
+            sink,
+            failed: false,
+        };
+        Syslog {
+            only,
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+    pub fn connect(facility: Facility, only: bool) -> Result<Syslog, String> {
+        let logger = ::syslog::unix(facility.formatter()).map_err(|e| e.to_string())?;
+        Ok(Syslog::new(only, Box::new(logger)))
+    }
+    /// Such as `addr=192.168.1.1 port=22 status=open method=tcp_syn`,
+    /// the keys missing from the record (such as the port of the host record) are skipped.
+    fn message(record: &Value) -> String {
+        let mut fields = Vec::new();
+        for key in ["addr", "port", "status", "method"] {
+            match record.get(key) {
+                Some(Value::String(s)) => fields.push(format!("{}={}", key, s)),
+                Some(Value::Number(n)) => fields.push(format!("{}={}", key, n)),
+                _ => (),
+            }
+        }
+        fields.join(" ")
+    }
+    /// The scan goes on when the message can not be sent, the warning is only printed once.
+    pub fn send(&self, record: &Value) {
+        let message = Syslog::message(record);
+        let mut state = self.state.lock().expect("lock syslog failed");
+        if state.failed {
+            return;
+        }
+        if let Err(e) = state.sink.send(&message) {
+            eprintln!("warning: send to syslog failed ({}), stop sending", e);
+            state.failed = true;
+        }
+    }
+}
+
+impl OutputSink for Syslog {
+    fn write_record(&mut self, record: &Record) {
+        self.send(record.value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::Emitter;
+    use crate::output::OutputFormat;
+    use crate::output::PortRecord;
+    struct MockSink {
+        messages: Arc<Mutex<Vec<String>>>,
+    }
+    impl SyslogSink for MockSink {
+        fn send(&mut self, message: &str) -> Result<(), String> {
+            self.messages.lock().unwrap().push(message.to_string());
+            if message.contains("port=80") {
+                Err(String::from("no buffer space"))
+            } else {
+                Ok(())
+            }
+        }
+    }
+    #[test]
+    fn test_syslog() {
+        let messages = Arc::new(Mutex::new(Vec::new()));
+        let sink = MockSink {
+            messages: messages.clone(),
+        };
+        let syslog = Syslog::new(false, Box::new(sink));
+        let mut emitter = Emitter::new(OutputFormat::Text, Vec::new())
+            .with_method("tcp_syn")
+            .with_sink(Box::new(syslog));
+        for (port, status) in [(22, "open"), (80, "closed"), (443, "open")] {
+            let record = PortRecord::new("192.168.5.5", port, status);
+            emitter.emit(None, &record);
+        }
+        // nothing is sent after the first failure
+        assert_eq!(
+            *messages.lock().unwrap(),
+            vec![
+                "addr=192.168.5.5 port=22 status=open method=tcp_syn",
+                "addr=192.168.5.5 port=80 status=closed method=tcp_syn",
+            ]
+        );
+
+        let mut logger = Logger::new(Vec::new(), Facility::Local0.formatter());
+        logger.send("addr=192.168.5.5").unwrap();
+        let message = String::from_utf8(logger.backend).unwrap();
+        assert!(message.starts_with("<134>"));
+        let pid = std::process::id();
+        assert!(message.ends_with(&format!(" pslmap[{}]: addr=192.168.5.5", pid)));
+    }
+}
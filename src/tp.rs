@@ -1,17 +1,29 @@
 /// target parser
+use clap::ValueEnum;
 use pistol::Target;
 use pistol::dns_query;
+use pnet::datalink::interfaces;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
 use std::fs::File;
-use std::io::BufRead;
-use std::io::BufReader;
+use std::io::Read;
 use std::net::IpAddr;
 use std::net::Ipv4Addr;
 use std::net::Ipv6Addr;
+use std::net::SocketAddr;
 use std::str::FromStr;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
 use subnetwork::CrossIpv4Pool;
 use subnetwork::CrossIpv6Pool;
 
 use crate::IPV6_FIRST;
+use crate::dns::dns_query_with_servers;
+use crate::ports::port_category;
 
 // from https://data.iana.org/TLD/tlds-alpha-by-domain.txt (2025-8-8)
 fn get_all_tlds() -> Vec<String> {
@@ -26,14 +38,424 @@ fn get_all_tlds() -> Vec<String> {
     tlds
 }
 
-pub struct TargetParser;
+/// Guess the domain name by the tld, the root dot of the fqdn (such as `example.com.`) is ignored.
+fn is_domain(addr_str: &str) -> bool {
+    let addr_str = addr_str.strip_suffix(".").unwrap_or(addr_str);
+    let domian_guess_split: Vec<&str> = addr_str.split(".").map(|x| x.trim()).collect();
+    let tld = if !domian_guess_split.is_empty() {
+        Some(domian_guess_split[domian_guess_split.len() - 1])
+    } else {
+        None
+    };
+
+    let all_tlds = get_all_tlds();
+    let mut is_domain = false;
+    if let Some(tld) = tld
+        && all_tlds.contains(&tld.to_string())
+    {
+        is_domain = true;
+    }
+    is_domain
+}
+
+fn system_resolver(hostname: &str) -> Result<Vec<IpAddr>, String> {
+    dns_query(hostname).map_err(|e| e.to_string())
+}
+
+/// Returns the link-local IPv6 address of the zone's interface,
+/// the zone can be the interface name (`eth0`) or index (`2`).
+/// Using it as the source address makes pistol send the packets from that interface.
+pub fn zone_src_addr(zone: &str) -> Result<IpAddr, String> {
+    let interface = interfaces()
+        .into_iter()
+        .find(|i| i.name == zone || i.index.to_string() == zone)
+        .ok_or(format!("can not find the interface of zone {}", zone))?;
+    for ipn in &interface.ips {
+        if let IpAddr::V6(ipv6) = ipn.ip()
+            && ipv6.is_unicast_link_local()
+        {
+            return Ok(ipv6.into());
+        }
+    }
+    Err(format!(
+        "interface {} has no link-local IPv6 address",
+        interface.name
+    ))
+}
+
+/// Port 0 is reserved, the packets to it are likely invalid.
+fn port_check(port: u16) -> Result<u16, String> {
+    if port == 0 {
+        Err(String::from("port 0 is not valid"))
+    } else {
+        Ok(port)
+    }
+}
+
+/// Remove the duplicate ports (such as `79-81,80`) and keep the first position of each port.
+fn dedup_ports(mut ports: Vec<u16>) -> Vec<u16> {
+    let mut seen = HashSet::new();
+    ports.retain(|p| seen.insert(*p));
+    ports
+}
+
+/// The line of target file without the `#` comment and the surrounding whitespace.
+fn strip_comment(line: &str) -> &str {
+    match line.split_once("#") {
+        Some((line, _)) => line.trim(),
+        None => line.trim(),
+    }
+}
+
+/// How the target file (-f) is read (--input-format).
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum InputFormat {
+    /// One target per line (same as nmap -iL).
+    Lines,
+    /// The addresses of one column (--csv-column) of the csv.
+    Csv,
+    /// The hosts of the nmap grepable output (-oG), with their open ports when listed.
+    NmapGrep,
+}
+
+/// The comma separated fields, the commas in the double quotes are kept.
+fn csv_fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field).trim().to_string()),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field.trim().to_string());
+    fields
+}
+
+/// The cells of the `column`, the header name (the first row is the header)
+/// or the 1-based column number (no header).
+fn csv_addrs(content: &str, column: &str) -> Result<Vec<String>, String> {
+    let mut rows = content.lines().filter(|l| !l.trim().is_empty());
+    let index = match column.parse::<usize>() {
+        Ok(0) => return Err(String::from("the csv column number starts from 1")),
+        Ok(n) => n - 1,
+        Err(_) => {
+            let header = csv_fields(rows.next().unwrap_or(""));
+            header
+                .iter()
+                .position(|h| h.eq_ignore_ascii_case(column))
+                .ok_or(format!(
+                    "csv column {} not found in [{}]",
+                    column,
+                    header.join(", ")
+                ))?
+        }
+    };
+    Ok(rows
+        .filter_map(|row| csv_fields(row).get(index).cloned())
+        .filter(|cell| !cell.is_empty())
+        .collect())
+}
+
+/// The hosts of the grepable lines such as
+/// `Host: 192.168.1.1 (router)\tPorts: 22/open/tcp//ssh///, 80/closed/tcp//http///`,
+/// the open ports of the host are its ports and the down hosts are skipped.
+fn nmap_grep_targets(content: &str) -> Vec<(String, Option<String>)> {
+    let mut hosts: Vec<(String, Vec<u16>)> = Vec::new();
+    for line in content.lines().filter(|l| l.starts_with("Host:")) {
+        let mut fields = line.split("\t");
+        let addr = match fields.next().and_then(|h| h.split_whitespace().nth(1)) {
+            Some(addr) => addr.to_string(),
+            None => continue,
+        };
+        let mut open = Vec::new();
+        let mut down = false;
+        for field in fields {
+            if let Some(status) = field.strip_prefix("Status:") {
+                down = status.trim() == "Down";
+            } else if let Some(ports) = field.strip_prefix("Ports:") {
+                for port in ports.split(",") {
+                    let parts: Vec<&str> = port.trim().split("/").collect();
+                    if parts.get(1) == Some(&"open")
+                        && let Ok(p) = parts[0].parse::<u16>()
+                    {
+                        open.push(p);
+                    }
+                }
+            }
+        }
+        if down {
+            continue;
+        }
+        // the status and the ports lines of the same host
+        match hosts.iter_mut().find(|(a, _)| *a == addr) {
+            Some((_, ports)) => ports.extend(open),
+            None => hosts.push((addr, open)),
+        }
+    }
+    hosts
+        .into_iter()
+        .map(|(addr, ports)| {
+            let ports: Vec<String> = ports.iter().map(|p| p.to_string()).collect();
+            let ports = if !ports.is_empty() {
+                Some(ports.join(","))
+            } else {
+                None
+            };
+            (addr, ports)
+        })
+        .collect()
+}
+
+/// The default port of the url scheme.
+fn scheme_port(scheme: &str) -> Option<u16> {
+    match scheme.to_lowercase().as_str() {
+        "http" => Some(80),
+        "https" => Some(443),
+        "ftp" => Some(21),
+        _ => None,
+    }
+}
+
+/// Split the url target such as `https://example.com` or `http://[::1]:8080/x` to the host and port,
+/// the scheme and path are ignored, the port is inferred from the scheme when it is not given.
+/// Returns None when it is not a url.
+fn url_parser(addr_str: &str) -> Result<Option<(String, Option<u16>)>, String> {
+    let (scheme, rest) = match addr_str.split_once("://") {
+        Some(split) => split,
+        None => return Ok(None),
+    };
+    let authority = match rest.find(['/', '?', '#']) {
+        Some(i) => &rest[..i],
+        None => rest,
+    };
+    // drop the userinfo such as `user:pass@`
+    let authority = match authority.rsplit_once("@") {
+        Some((_, authority)) => authority,
+        None => authority,
+    };
+    let (host, port_str) = if let Some(rest) = authority.strip_prefix("[") {
+        match rest.split_once("]") {
+            Some((host, suffix)) => (host, suffix.strip_prefix(":")),
+            None => return Err(format!("missing the closing bracket in {}", addr_str)),
+        }
+    } else {
+        match authority.split_once(":") {
+            Some((host, port_str)) => (host, Some(port_str)),
+            None => (authority, None),
+        }
+    };
+    if host.is_empty() {
+        return Err(format!("the host of url {} is empty", addr_str));
+    }
+    let port = match port_str {
+        Some(port_str) => {
+            let port: u16 = port_str
+                .parse()
+                .map_err(|_| format!("invalid port {} in url {}", port_str, addr_str))?;
+            Some(port_check(port)?)
+        }
+        None => scheme_port(scheme),
+    };
+    Ok(Some((host.to_string(), port)))
+}
+
+/// The network and broadcast addresses of the subnets with the prefix up to this are excluded by default.
+const EDGES_EXCLUDED_PREFIX: u8 = 24;
+
+/// The network and broadcast addresses and the prefix length of the IPv4 subnet such as `192.168.1.0/24`.
+fn subnet_edges(subnet: &str) -> Option<(Ipv4Addr, Ipv4Addr, u8)> {
+    let (ip_str, prefix_str) = subnet.split_once("/")?;
+    let ip = Ipv4Addr::from_str(ip_str.trim()).ok()?;
+    let prefix: u8 = prefix_str.trim().parse().ok().filter(|p| *p <= 32)?;
+    let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+    let network = u32::from(ip) & mask;
+    Some((network.into(), (network | !mask).into(), prefix))
+}
+
+/// Same as the hosts number of a /16 subnet.
+pub const DEFAULT_MAX_HOSTS: usize = 65536;
+
+pub const DEFAULT_DNS_CONCURRENCY: usize = 16;
+
+/// Parse the nmap style octet ranges such as `192.168.1.1-254` or `192.168.0-1.1-254`,
+/// returns the (start, end) of each octet, or None when it is not this form.
+fn octet_ranges_parser(addr_str: &str) -> Result<Option<Vec<(u8, u8)>>, String> {
+    let octets: Vec<&str> = addr_str.split(".").map(|x| x.trim()).collect();
+    if octets.len() != 4 || !addr_str.contains("-") {
+        return Ok(None);
+    }
+    let mut ret = Vec::new();
+    for octet in octets {
+        let (start, end) = match octet.split_once("-") {
+            Some((start, end)) => match (start.parse(), end.parse()) {
+                (Ok(start), Ok(end)) => (start, end),
+                _ => return Ok(None),
+            },
+            None => match octet.parse() {
+                Ok(o) => (o, o),
+                Err(_) => return Ok(None),
+            },
+        };
+        if start > end {
+            return Err(format!("{}(start) > {}(end) in {}", start, end, addr_str));
+        }
+        ret.push((start, end));
+    }
+    Ok(Some(ret))
+}
+
+pub struct TargetParser {
+    /// Problems found while parsing the targets (such as unresolvable hostnames),
+    /// these inputs are skipped instead of aborting the whole parse.
+    pub warnings: Vec<String>,
+    resolver: fn(&str) -> Result<Vec<IpAddr>, String>,
+    /// When it is not empty, these servers are used instead of the system resolver.
+    dns_servers: Vec<SocketAddr>,
+    /// The zone (scope id) of the link-local IPv6 targets, such as `eth0` of `fe80::1%eth0`.
+    pub zones: BTreeMap<IpAddr, String>,
+    /// The octet ranges that expand to more hosts than this are skipped.
+    max_hosts: usize,
+    /// Max number of the dns lookups running at the same time.
+    dns_concurrency: usize,
+    /// The results of the prefetched dns lookups.
+    dns_cache: HashMap<String, Result<Vec<IpAddr>, String>>,
+    /// The inputs of the targets merged by `dedup` (such as `example.com` and the literal ip it resolves to).
+    pub origins: BTreeMap<IpAddr, Vec<String>>,
+    /// Keep the network and broadcast addresses of the /24 and larger subnets.
+    network_broadcast: bool,
+    /// The time of the dns lookup of each hostname, the `origin` of the targets it resolved to.
+    pub dns_times: HashMap<String, Duration>,
+    /// How the target file is read.
+    input_format: InputFormat,
+    /// The address column of the csv target file.
+    csv_column: String,
+}
+
+impl Default for TargetParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl TargetParser {
-    fn ports_parser(ports: Option<String>) -> Vec<u16> {
-        // 80,81,443-999
+    pub fn new() -> TargetParser {
+        TargetParser {
+            warnings: Vec::new(),
+            resolver: system_resolver,
+            dns_servers: Vec::new(),
+            zones: BTreeMap::new(),
+            max_hosts: DEFAULT_MAX_HOSTS,
+            dns_concurrency: DEFAULT_DNS_CONCURRENCY,
+            dns_cache: HashMap::new(),
+            origins: BTreeMap::new(),
+            network_broadcast: false,
+            dns_times: HashMap::new(),
+            input_format: InputFormat::Lines,
+            csv_column: String::from("addr"),
+        }
+    }
+    pub fn with_dns_servers(mut self, dns_servers: Vec<SocketAddr>) -> TargetParser {
+        self.dns_servers = dns_servers;
+        self
+    }
+    pub fn with_max_hosts(mut self, max_hosts: usize) -> TargetParser {
+        self.max_hosts = max_hosts;
+        self
+    }
+    pub fn with_network_broadcast(mut self, network_broadcast: bool) -> TargetParser {
+        self.network_broadcast = network_broadcast;
+        self
+    }
+    pub fn with_input_format(
+        mut self,
+        input_format: InputFormat,
+        csv_column: &str,
+    ) -> TargetParser {
+        self.input_format = input_format;
+        self.csv_column = csv_column.to_string();
+        self
+    }
+    pub fn with_dns_concurrency(mut self, dns_concurrency: usize) -> TargetParser {
+        self.dns_concurrency = dns_concurrency.max(1);
+        self
+    }
+    /// Resolve all the domain names in `addrs_list` with a bounded worker pool,
+    /// the later parsing takes the results from the cache in the input order.
+    fn prefetch_dns(&mut self, addrs_list: &[String]) {
+        let mut hostnames = Vec::new();
+        for addrs in addrs_list {
+            for (addr_str, _) in TargetParser::addr_tokens(addrs).into_iter().flatten() {
+                let addr_str = match url_parser(&addr_str) {
+                    Ok(Some((host, _))) => host,
+                    _ => addr_str,
+                };
+                let addr_str = addr_str.as_str();
+                let hostname = addr_str.to_string();
+                if !addr_str.is_empty()
+                    && is_domain(addr_str)
+                    && !self.dns_cache.contains_key(&hostname)
+                    && !hostnames.contains(&hostname)
+                {
+                    hostnames.push(hostname);
+                }
+            }
+        }
+        if hostnames.is_empty() {
+            return;
+        }
+
+        let next = AtomicUsize::new(0);
+        let this = &*self;
+        type Resolved = (usize, (Result<Vec<IpAddr>, String>, Duration));
+        let results: Vec<Resolved> = thread::scope(|s| {
+            let workers: Vec<_> = (0..this.dns_concurrency.min(hostnames.len()))
+                .map(|_| {
+                    s.spawn(|| {
+                        let mut ret = Vec::new();
+                        loop {
+                            let i = next.fetch_add(1, Ordering::SeqCst);
+                            if i >= hostnames.len() {
+                                break;
+                            }
+                            ret.push((i, this.timed_resolve(&hostnames[i])));
+                        }
+                        ret
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|w| w.join().expect("dns worker panicked"))
+                .collect()
+        });
+        for (i, (ret, cost)) in results {
+            self.dns_cache.insert(hostnames[i].clone(), ret);
+            self.dns_times.insert(hostnames[i].clone(), cost);
+        }
+    }
+    fn timed_resolve(&self, hostname: &str) -> (Result<Vec<IpAddr>, String>, Duration) {
+        let start = Instant::now();
+        let ret = self.resolve(hostname);
+        (ret, start.elapsed())
+    }
+    fn resolve(&self, hostname: &str) -> Result<Vec<IpAddr>, String> {
+        if let Some(ret) = self.dns_cache.get(hostname) {
+            return ret.clone();
+        }
+        if !self.dns_servers.is_empty() {
+            dns_query_with_servers(hostname, &self.dns_servers)
+        } else {
+            (self.resolver)(hostname)
+        }
+    }
+    pub fn ports_parser(ports: Option<String>) -> Result<Vec<u16>, String> {
+        // 80,81,443-999,@web
         if let Some(ports) = ports {
-            if ports.trim().len() == 0 {
-                return Vec::new();
+            if ports.trim().is_empty() {
+                return Ok(Vec::new());
             }
 
             let mut ret = Vec::new();
@@ -41,7 +463,7 @@ impl TargetParser {
             if ports.contains(",") {
                 let split_ret: Vec<String> = ports
                     .split(",")
-                    .filter(|x| x.trim().len() > 0)
+                    .filter(|x| !x.trim().is_empty())
                     .map(|x| x.trim().to_string())
                     .collect();
                 ports_split.extend(split_ret);
@@ -50,67 +472,101 @@ impl TargetParser {
             }
 
             for ps in ports_split {
-                if ps.contains("-") {
+                if let Some(name) = ps.trim().strip_prefix("@") {
+                    ret.extend(port_category(name)?);
+                } else if ps.contains("-") {
                     let range_split: Vec<&str> = ps
                         .split("-")
-                        .filter(|x| x.trim().len() > 0)
+                        .filter(|x| !x.trim().is_empty())
                         .map(|x| x.trim())
                         .collect();
                     if range_split.len() == 2 {
                         let start: u16 = range_split[0]
                             .parse()
-                            .expect(&format!("convert {} to u16 failed", range_split[0]));
+                            .map_err(|_| format!("convert {} to u16 failed", range_split[0]))?;
                         let end: u16 = range_split[1]
                             .parse()
-                            .expect(&format!("convert {} to u16 failed", range_split[1]));
-                        if start < end {
-                            for p in start..=end {
-                                ret.push(p);
-                            }
-                        } else {
-                            panic!("{}(start) >= {}(end)", start, end);
+                            .map_err(|_| format!("convert {} to u16 failed", range_split[1]))?;
+                        port_check(start)?;
+                        if start >= end {
+                            return Err(format!("{}(start) >= {}(end)", start, end));
                         }
+                        ret.extend(start..=end);
+                    } else {
+                        return Err(format!("invalid port range {}", ps));
                     }
                 } else {
-                    let p: u16 = ps.parse().expect(&format!("convert {} to u16 failed", ps));
-                    ret.push(p);
+                    let p: u16 = ps
+                        .parse()
+                        .map_err(|_| format!("convert {} to u16 failed", ps))?;
+                    ret.push(port_check(p)?);
                 }
             }
-            ret
+            Ok(dedup_ports(ret))
         } else {
-            Vec::new()
+            Ok(Vec::new())
         }
     }
-    fn parser(addrs: &str, ports: Option<String>) -> Vec<Target> {
-        if addrs.trim().len() == 0 {
+    fn parser(&mut self, addrs: &str, ports: Option<String>) -> Vec<Target> {
+        if addrs.trim().is_empty() {
             return Vec::new();
         }
 
         // parse ports first
-        let ports = Self::ports_parser(ports);
-
-        let addr_parser = |addr_str: &str, ports: Option<Vec<u16>>| -> Vec<Target> {
-            let mut targets = Vec::new();
-            let domian_guess_split: Vec<&str> = addr_str.split(".").map(|x| x.trim()).collect();
-            let tld = if domian_guess_split.len() > 0 {
-                Some(domian_guess_split[domian_guess_split.len() - 1])
-            } else {
-                None
-            };
-
-            let all_tlds = get_all_tlds();
-            let mut is_domain = false;
-            if let Some(tld) = tld {
-                if all_tlds.contains(&tld.to_string()) {
-                    is_domain = true;
-                }
+        let ports = match Self::ports_parser(ports) {
+            Ok(ports) => ports,
+            Err(e) => {
+                self.warnings.push(format!("invalid ports: {}", e));
+                return Vec::new();
             }
+        };
 
-            if !is_domain {
-                if addr_str.contains("-") {
+        let mut addr_parser = |addr_str: &str,
+                               ports: Option<Vec<u16>>|
+         -> Result<Vec<Target>, String> {
+            let mut targets = Vec::new();
+            if !is_domain(addr_str) {
+                if let Some((ip_str, zone)) = addr_str.split_once("%") {
+                    // ipv6 with zone, the interface name may contain the '-'
+                    let ip = Ipv6Addr::from_str(ip_str)
+                        .map_err(|_| format!("can not convert target {} to Ipv6Addr", ip_str))?;
+                    if zone.is_empty() {
+                        return Err(format!("the zone of {} is empty", addr_str));
+                    }
+                    let mut t = Target::new(ip.into(), ports);
+                    t.origin = Some(addr_str.to_string());
+                    self.zones.insert(ip.into(), zone.to_string());
+                    targets.push(t);
+                } else if let Some(ranges) = octet_ranges_parser(addr_str)? {
+                    let hosts_num: usize = ranges
+                        .iter()
+                        .map(|(start, end)| (end - start) as usize + 1)
+                        .product();
+                    if hosts_num > self.max_hosts {
+                        let warning = format!(
+                            "{} expands to {} hosts which exceeds the max hosts {}",
+                            addr_str, hosts_num, self.max_hosts
+                        );
+                        self.warnings.push(warning);
+                        return Ok(targets);
+                    }
+                    // the cartesian product of the octet ranges
+                    for a in ranges[0].0..=ranges[0].1 {
+                        for b in ranges[1].0..=ranges[1].1 {
+                            for c in ranges[2].0..=ranges[2].1 {
+                                for d in ranges[3].0..=ranges[3].1 {
+                                    let ip = Ipv4Addr::new(a, b, c, d);
+                                    let mut t = Target::new(ip.into(), ports.clone());
+                                    t.origin = Some(addr_str.to_string());
+                                    targets.push(t);
+                                }
+                            }
+                        }
+                    }
+                } else if addr_str.contains("-") {
                     let split_ret: Vec<&str> = addr_str
                         .split("-")
-                        .filter(|x| x.trim().len() > 0)
+                        .filter(|x| !x.trim().is_empty())
                         .map(|x| x.trim())
                         .collect();
                     if split_ret.len() == 2 {
@@ -119,13 +575,12 @@ impl TargetParser {
                         let ret = if start_ip.contains(":") || end_ip.contains(":") {
                             // ipv6
                             let start_ipv6 = Ipv6Addr::from_str(start_ip)
-                                .expect(&format!("convert {} to Ipv6Addr failed", start_ip));
+                                .map_err(|_| format!("convert {} to Ipv6Addr failed", start_ip))?;
                             let end_ipv6 = Ipv6Addr::from_str(end_ip)
-                                .expect(&format!("convert {} to Ipv6Addr failed", end_ip));
-                            let ips = CrossIpv6Pool::new(start_ipv6, end_ipv6).expect(&format!(
-                                "get cross ipv6 pool ({}-{}) failed",
-                                start_ipv6, end_ipv6
-                            ));
+                                .map_err(|_| format!("convert {} to Ipv6Addr failed", end_ip))?;
+                            let ips = CrossIpv6Pool::new(start_ipv6, end_ipv6).map_err(|_| {
+                                format!("get cross ipv6 pool ({}-{}) failed", start_ipv6, end_ipv6)
+                            })?;
                             let mut ret = Vec::new();
                             for ip in ips {
                                 let mut t = Target::new(ip.into(), ports.clone());
@@ -137,13 +592,12 @@ impl TargetParser {
                         } else {
                             // ipv4
                             let start_ipv4 = Ipv4Addr::from_str(start_ip)
-                                .expect(&format!("convert {} to Ipv4Addr failed", start_ip));
+                                .map_err(|_| format!("convert {} to Ipv4Addr failed", start_ip))?;
                             let end_ipv4 = Ipv4Addr::from_str(end_ip)
-                                .expect(&format!("convert {} to Ipv4Addr failed", end_ip));
-                            let ips = CrossIpv4Pool::new(start_ipv4, end_ipv4).expect(&format!(
-                                "get cross ipv4 pool ({}-{}) failed",
-                                start_ipv4, end_ipv4
-                            ));
+                                .map_err(|_| format!("convert {} to Ipv4Addr failed", end_ip))?;
+                            let ips = CrossIpv4Pool::new(start_ipv4, end_ipv4).map_err(|_| {
+                                format!("get cross ipv4 pool ({}-{}) failed", start_ipv4, end_ipv4)
+                            })?;
                             let mut ret = Vec::new();
                             for ip in ips {
                                 let mut t = Target::new(ip.into(), ports.clone());
@@ -154,28 +608,64 @@ impl TargetParser {
                             ret
                         };
                         targets.extend(ret);
+                    } else {
+                        return Err(format!("invalid address range {}", addr_str));
                     }
                 } else if addr_str.contains("/") {
-                    let t = Target::from_subnet(addr_str, ports)
-                        .expect(&format!("get subnet target from {} failed", addr_str));
+                    let mut t = Target::from_subnet(addr_str, ports.clone())
+                        .map_err(|_| format!("get subnet target from {} failed", addr_str))?;
+                    // pistol always skips the network and broadcast addresses (all the hosts of /31 and /32)
+                    if let Some((network, broadcast, prefix)) = subnet_edges(addr_str)
+                        && (self.network_broadcast || prefix > EDGES_EXCLUDED_PREFIX)
+                    {
+                        let edge = |ip: Ipv4Addr| {
+                            let mut edge = Target::new(ip.into(), ports.clone());
+                            edge.origin = Some(addr_str.to_string());
+                            edge
+                        };
+                        t.insert(0, edge(network));
+                        if broadcast != network {
+                            t.push(edge(broadcast));
+                        }
+                    }
                     targets.extend(t);
                 } else {
                     let target = if addr_str.contains(":") {
                         // ipv6
-                        let ip = Ipv6Addr::from_str(addrs)
-                            .expect(&format!("can not convert target {} to Ipv4Addr", addrs));
+                        let ip = Ipv6Addr::from_str(addr_str).map_err(|_| {
+                            format!("can not convert target {} to Ipv6Addr", addr_str)
+                        })?;
+                        if ip.is_unicast_link_local() {
+                            let warning = format!(
+                                "{} is a link-local address, a zone is required (such as {}%eth0)",
+                                addr_str, addr_str
+                            );
+                            self.warnings.push(warning);
+                            return Ok(targets);
+                        }
                         Target::new(ip.into(), ports)
                     } else {
                         // ipv4
-                        let ip = Ipv4Addr::from_str(addrs)
-                            .expect(&format!("can not convert target {} to Ipv4Addr", addrs));
+                        let ip = Ipv4Addr::from_str(addr_str).map_err(|_| {
+                            format!("can not convert target {} to Ipv4Addr", addr_str)
+                        })?;
                         Target::new(ip.into(), ports)
                     };
                     targets.push(target);
                 }
             } else {
-                let query_ret =
-                    dns_query(addr_str).expect(&format!("dns query {} failed", addr_str));
+                let (query_ret, cost) = self.timed_resolve(addr_str);
+                // the prefetched lookup keeps its own time
+                self.dns_times.entry(addr_str.to_string()).or_insert(cost);
+                let query_ret = match query_ret {
+                    Ok(query_ret) => query_ret,
+                    Err(e) => {
+                        // skip this hostname and go on with the others
+                        let warning = format!("dns query {} failed: {}", addr_str, e);
+                        self.warnings.push(warning);
+                        return Ok(targets);
+                    }
+                };
                 let mut ret = Vec::new();
                 let ipv6_first = IPV6_FIRST.lock().expect("lock IPV6_FIRST failed");
 
@@ -197,45 +687,213 @@ impl TargetParser {
                         }
                     }
                 }
+                if ret.is_empty() {
+                    // such as the IPv6 only hostname under -4
+                    let family = if *ipv6_first { "IPv6" } else { "IPv4" };
+                    let warning =
+                        format!("hostname {} resolved to no {} addresses", addr_str, family);
+                    self.warnings.push(warning);
+                }
                 targets.extend(ret);
             }
-            targets
+            Ok(targets)
         };
 
         let mut targets = Vec::new();
-        let mut addrs_split = Vec::new();
-        if addrs.contains(",") {
-            let split: Vec<String> = addrs
-                .split(",")
-                .filter(|x| x.trim().len() > 0)
-                .map(|x| x.trim().to_string())
-                .collect();
-            addrs_split.extend(split);
+        // skip the bad inputs and go on with the others
+        let mut errors = Vec::new();
+        for token in Self::addr_tokens(addrs) {
+            let (addr_str, token_ports) = match token {
+                Ok(token) => token,
+                Err(e) => {
+                    errors.push(e);
+                    continue;
+                }
+            };
+            // the ports suffix of this token overrides the global ports
+            let ports = match token_ports {
+                Some(p) => match Self::ports_parser(Some(p)) {
+                    Ok(ports) => ports,
+                    Err(e) => {
+                        errors.push(format!("invalid ports of {}: {}", addr_str, e));
+                        continue;
+                    }
+                },
+                None => ports.clone(),
+            };
+            // the port of the url is scanned with the other ports
+            let (addr_str, ports) = match url_parser(&addr_str) {
+                Ok(Some((host, port))) => {
+                    let mut url_ports = ports.clone();
+                    url_ports.extend(port);
+                    (host, dedup_ports(url_ports))
+                }
+                Ok(None) => (addr_str, ports.clone()),
+                Err(e) => {
+                    errors.push(e);
+                    continue;
+                }
+            };
+            match addr_parser(&addr_str, Some(ports)) {
+                Ok(t) => targets.extend(t),
+                Err(e) => errors.push(e),
+            }
+        }
+        self.warnings.extend(errors);
+        targets
+    }
+    /// Split the comma separated targets into the address and its ports suffix,
+    /// such as `10.0.0.0/24:22,80,192.168.1.1` to `10.0.0.0/24` on `22,80` and `192.168.1.1`,
+    /// the bare ports after a token with the suffix belong to that suffix.
+    fn addr_tokens(addrs: &str) -> Vec<Result<(String, Option<String>), String>> {
+        let mut tokens: Vec<Result<(String, Option<String>), String>> = Vec::new();
+        for token in addrs.split(",").map(|x| x.trim()).filter(|x| !x.is_empty()) {
+            let is_ports = token.chars().all(|c| c.is_ascii_digit() || c == '-');
+            if is_ports && let Some(Ok((_, Some(ports)))) = tokens.last_mut() {
+                ports.push(',');
+                ports.push_str(token);
+                continue;
+            }
+            tokens.push(TargetParser::ports_suffix_parser(token));
+        }
+        tokens
+    }
+    /// Split the optional ports suffix from the line of target file (or one target token),
+    /// such as `192.168.1.10:22,80` or `example.com:443`,
+    /// the IPv6 address must be wrapped in brackets when followed by ports (`[::1]:22`).
+    fn ports_suffix_parser(line: &str) -> Result<(String, Option<String>), String> {
+        let line = line.trim();
+        if line.contains("://") {
+            // the port of the url is parsed later
+            Ok((line.to_string(), None))
+        } else if let Some(rest) = line.strip_prefix("[") {
+            match rest.split_once("]") {
+                Some((addr, suffix)) => {
+                    let ports = suffix.strip_prefix(":").map(|p| p.trim().to_string());
+                    Ok((addr.trim().to_string(), ports))
+                }
+                None => Err(format!("missing the closing bracket in {}", line)),
+            }
+        } else if line.matches(":").count() == 1 {
+            // ipv4 or domain with ports, the bare ipv6 address always contains more than one colon
+            let (addr, ports) = line.split_once(":").expect("split ports suffix failed");
+            Ok((addr.trim().to_string(), Some(ports.trim().to_string())))
         } else {
-            addrs_split.push(addrs.to_string());
+            Ok((line.to_string(), None))
+        }
+    }
+    /// Keep the lines with the valid ports suffix, the others are skipped with a warning.
+    fn suffix_lines<'a>(
+        &mut self,
+        lines: impl Iterator<Item = &'a str>,
+    ) -> Vec<(String, Option<String>)> {
+        let mut ret = Vec::new();
+        for line in lines {
+            match TargetParser::ports_suffix_parser(line) {
+                Ok(line) => ret.push(line),
+                Err(e) => self.warnings.push(format!("skip the line {}: {}", line, e)),
+            }
         }
+        ret
+    }
+    pub fn target_from_file(
+        &mut self,
+        filename: &str,
+        target_ports: Option<String>,
+    ) -> Vec<Target> {
+        let mut fp =
+            File::open(filename).unwrap_or_else(|_| panic!("can not open file [{}]", filename));
+        let mut content = String::new();
+        fp.read_to_string(&mut content)
+            .unwrap_or_else(|_| panic!("can not read file [{}]", filename));
+        let lines = self.file_lines(&content).expect("parse target file failed");
+        let addrs_list: Vec<String> = lines.iter().map(|(addrs, _)| addrs.clone()).collect();
+        self.prefetch_dns(&addrs_list);
 
-        for addr_str in addrs_split {
-            let t = addr_parser(&addr_str, Some(ports.clone()));
+        let mut targets = Vec::new();
+        for (addrs, line_ports) in lines {
+            let ports = match line_ports {
+                Some(p) => Some(p),
+                None => target_ports.clone(),
+            };
+            let t = self.parser(&addrs, ports);
             targets.extend(t);
         }
         targets
     }
-    pub fn target_from_file(filename: &str, target_ports: Option<String>) -> Vec<Target> {
-        let fp = File::open(filename).expect(&format!("can not open file [{}]", filename));
-        let reader = BufReader::new(fp);
-
+    /// The addresses with their optional ports of the target file in the input format,
+    /// the ports suffix of the line (or of the csv cell) overrides the global ports.
+    fn file_lines(&mut self, content: &str) -> Result<Vec<(String, Option<String>)>, String> {
+        let lines = match self.input_format {
+            InputFormat::Lines => {
+                let lines = content
+                    .lines()
+                    .map(strip_comment)
+                    .filter(|line| !line.is_empty());
+                self.suffix_lines(lines)
+            }
+            InputFormat::Csv => {
+                let cells = csv_addrs(content, &self.csv_column)?;
+                self.suffix_lines(cells.iter().map(|cell| cell.as_str()))
+            }
+            InputFormat::NmapGrep => nmap_grep_targets(content),
+        };
+        Ok(lines)
+    }
+    pub fn target_from_input(
+        &mut self,
+        target_addr: &str,
+        target_ports: Option<String>,
+    ) -> Vec<Target> {
+        self.prefetch_dns(&[target_addr.to_string()]);
+        self.parser(target_addr, target_ports)
+    }
+    /// The targets of both the inline input and the file, the same address of them
+    /// is merged later by `dedup` with the ports of both.
+    pub fn target_from_sources(
+        &mut self,
+        target_addr: Option<&str>,
+        filename: Option<&str>,
+        target_ports: Option<String>,
+    ) -> Vec<Target> {
         let mut targets = Vec::new();
-        for line in reader.lines() {
-            let line = line.expect("can not read line");
-            // ignore the port here
-            let t = TargetParser::parser(&line, target_ports.clone());
-            targets.extend(t);
+        if let Some(target_addr) = target_addr {
+            targets.extend(self.target_from_input(target_addr, target_ports.clone()));
+        }
+        if let Some(filename) = filename {
+            targets.extend(self.target_from_file(filename, target_ports));
         }
         targets
     }
-    pub fn target_from_input(target_addr: &str, target_ports: Option<String>) -> Vec<Target> {
-        TargetParser::parser(target_addr, target_ports)
+    /// Scan each address once, the ports of the same address are merged
+    /// and the different inputs of it are kept in `origins` (the literal ip is `literal`).
+    pub fn dedup(&mut self, targets: Vec<Target>) -> Vec<Target> {
+        let mut index: HashMap<IpAddr, usize> = HashMap::new();
+        let mut origins: BTreeMap<IpAddr, Vec<String>> = BTreeMap::new();
+        let mut ret: Vec<Target> = Vec::new();
+        for t in targets {
+            let origin = t.origin.clone().unwrap_or(String::from("literal"));
+            let addr_origins = origins.entry(t.addr).or_default();
+            if !addr_origins.contains(&origin) {
+                addr_origins.push(origin);
+            }
+            match index.get(&t.addr) {
+                Some(&i) => {
+                    for port in t.ports {
+                        if !ret[i].ports.contains(&port) {
+                            ret[i].ports.push(port);
+                        }
+                    }
+                }
+                None => {
+                    index.insert(t.addr, ret.len());
+                    ret.push(t);
+                }
+            }
+        }
+        origins.retain(|_, o| o.len() > 1);
+        self.origins.extend(origins);
+        ret
     }
 }
 
@@ -249,10 +907,373 @@ mod tests {
 
         for t in &test_targets {
             for p in &test_ports {
-                let ret = TargetParser::target_from_input(t, Some(p.to_string()));
+                let ret = TargetParser::new().target_from_input(t, Some(p.to_string()));
                 println!("{:?}", ret);
                 println!(">>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>");
             }
         }
     }
+    #[test]
+    fn test_port_categories() {
+        let ret = TargetParser::ports_parser(Some(String::from("@web"))).unwrap();
+        assert_eq!(ret, vec![80, 443, 8080, 8443]);
+        let ret = TargetParser::ports_parser(Some(String::from("@web,9000"))).unwrap();
+        assert_eq!(ret, vec![80, 443, 8080, 8443, 9000]);
+
+        let err = port_category("game").unwrap_err();
+        assert_eq!(err, "unknown port category @game (valid: @web, @db, @mail)");
+    }
+    #[test]
+    fn test_ports_check() {
+        let ret = TargetParser::ports_parser(Some(String::from("79-81,80"))).unwrap();
+        assert_eq!(ret, vec![79, 80, 81]);
+        let ret = TargetParser::ports_parser(Some(String::from("443,22,443,20-23"))).unwrap();
+        assert_eq!(ret, vec![443, 22, 20, 21, 23]);
+
+        assert_eq!(port_check(22), Ok(22));
+        let err = Err(String::from("port 0 is not valid"));
+        assert_eq!(TargetParser::ports_parser(Some(String::from("0"))), err);
+        assert_eq!(TargetParser::ports_parser(Some(String::from("0-80"))), err);
+        let ret = TargetParser::ports_parser(Some(String::from("80-22")));
+        assert_eq!(ret, Err(String::from("80(start) >= 22(end)")));
+        assert!(TargetParser::ports_parser(Some(String::from("70000"))).is_err());
+        assert!(TargetParser::ports_parser(Some(String::from("@game"))).is_err());
+    }
+    #[test]
+    fn test_file_ports_suffix() {
+        let filename = std::env::temp_dir().join("pslmap_test_file_ports_suffix.txt");
+        let lines = ["192.168.1.10:22,80", "[::1]:22", "[::2:22", "192.168.1.11"];
+        std::fs::write(&filename, lines.join("\n")).unwrap();
+
+        let filename = filename.to_string_lossy();
+        let mut parser = TargetParser::new();
+        let ret = parser.target_from_file(&filename, Some(String::from("443")));
+        // the line without the closing bracket is skipped
+        assert_eq!(
+            parser.warnings,
+            vec!["skip the line [::2:22: missing the closing bracket in [::2:22"]
+        );
+        assert_eq!(ret.len(), 3);
+        assert_eq!(ret[0].addr, IpAddr::V4(Ipv4Addr::new(192, 168, 1, 10)));
+        assert_eq!(ret[0].ports, vec![22, 80]);
+        assert_eq!(ret[1].addr, IpAddr::V6(Ipv6Addr::LOCALHOST));
+        assert_eq!(ret[1].ports, vec![22]);
+        assert_eq!(ret[2].addr, IpAddr::V4(Ipv4Addr::new(192, 168, 1, 11)));
+        assert_eq!(ret[2].ports, vec![443]);
+    }
+    #[test]
+    fn test_input_formats() {
+        let csv = "name,addr,owner\nrouter,192.168.1.1,net\n\"web, main\",192.168.1.10:443,ops\n";
+        let mut parser = TargetParser::new().with_input_format(InputFormat::Csv, "addr");
+        assert_eq!(
+            parser.file_lines(csv).unwrap(),
+            vec![
+                (String::from("192.168.1.1"), None),
+                (String::from("192.168.1.10"), Some(String::from("443"))),
+            ]
+        );
+        let mut parser = TargetParser::new().with_input_format(InputFormat::Csv, "host");
+        assert!(parser.file_lines(csv).is_err());
+        // the column number reads every row
+        assert_eq!(
+            csv_addrs("10.0.0.1,a\n10.0.0.2,b", "1").unwrap(),
+            vec!["10.0.0.1", "10.0.0.2"]
+        );
+
+        let grep = "# Nmap 7.94 scan initiated as: nmap -oG - 192.168.1.0/24
+Host: 192.168.1.1 (router)\tStatus: Up
+Host: 192.168.1.1 (router)\tPorts: 22/open/tcp//ssh///, 80/closed/tcp//http///, 443/open/tcp//https///
+Host: 192.168.1.2 ()\tStatus: Down
+Host: 192.168.1.3 ()\tStatus: Up
+# Nmap done at Mon Jan  1 00:00:00 2026 -- 256 IP addresses (2 hosts up) scanned in 2.00 seconds
+";
+        let mut parser = TargetParser::new().with_input_format(InputFormat::NmapGrep, "addr");
+        let lines = parser.file_lines(grep).unwrap();
+        assert_eq!(
+            lines,
+            vec![
+                (String::from("192.168.1.1"), Some(String::from("22,443"))),
+                (String::from("192.168.1.3"), None),
+            ]
+        );
+        let targets: Vec<(IpAddr, Vec<u16>)> = lines
+            .into_iter()
+            .flat_map(|(addr, ports)| parser.parser(&addr, ports.or(Some(String::from("80")))))
+            .map(|t| (t.addr, t.ports))
+            .collect();
+        assert_eq!(
+            targets,
+            vec![
+                (Ipv4Addr::new(192, 168, 1, 1).into(), vec![22, 443]),
+                (Ipv4Addr::new(192, 168, 1, 3).into(), vec![80]),
+            ]
+        );
+    }
+    #[test]
+    fn test_token_ports_suffix() {
+        let mut parser = TargetParser::new();
+        let ret = parser.target_from_input("10.0.0.0/24:22,80", Some(String::from("443")));
+        assert_eq!(ret.len(), 254);
+        assert!(ret.iter().all(|t| t.ports == vec![22, 80]));
+
+        let ret = parser.target_from_input(
+            "192.168.1.1-3:8080-8081,[::1]:22,192.168.1.10",
+            Some(String::from("443")),
+        );
+        let ret: Vec<(IpAddr, Vec<u16>)> = ret.into_iter().map(|t| (t.addr, t.ports)).collect();
+        assert_eq!(
+            ret,
+            vec![
+                (Ipv4Addr::new(192, 168, 1, 1).into(), vec![8080, 8081]),
+                (Ipv4Addr::new(192, 168, 1, 2).into(), vec![8080, 8081]),
+                (Ipv4Addr::new(192, 168, 1, 3).into(), vec![8080, 8081]),
+                (Ipv6Addr::LOCALHOST.into(), vec![22]),
+                (Ipv4Addr::new(192, 168, 1, 10).into(), vec![443]),
+            ]
+        );
+        assert_eq!(parser.warnings.len(), 0);
+    }
+    #[test]
+    fn test_merged_sources() {
+        let filename = std::env::temp_dir().join("pslmap_test_merged_sources.txt");
+        let lines = ["192.168.1.10:443", "192.168.1.11"];
+        std::fs::write(&filename, lines.join("\n")).unwrap();
+
+        let filename = filename.to_string_lossy();
+        let mut parser = TargetParser::new();
+        let targets = parser.target_from_sources(
+            Some("192.168.1.10"),
+            Some(&filename),
+            Some(String::from("80")),
+        );
+        assert_eq!(targets.len(), 3);
+        let ret = parser.dedup(targets);
+        assert_eq!(ret.len(), 2);
+        assert_eq!(ret[0].addr, IpAddr::V4(Ipv4Addr::new(192, 168, 1, 10)));
+        assert_eq!(ret[0].ports, vec![80, 443]);
+        assert_eq!(ret[1].ports, vec![80]);
+    }
+    #[test]
+    fn test_file_comments() {
+        let filename = std::env::temp_dir().join("pslmap_test_file_comments.txt");
+        let lines = [
+            "# lab hosts",
+            "",
+            "   ",
+            "192.168.1.10   # the gateway",
+            "  192.168.1.11:22  ",
+            "# 192.168.1.12",
+            "\t[::1]:80 # loopback",
+        ];
+        std::fs::write(&filename, lines.join("\n")).unwrap();
+
+        let filename = filename.to_string_lossy();
+        let mut parser = TargetParser::new();
+        let ret = parser.target_from_file(&filename, Some(String::from("443")));
+        let ret: Vec<(IpAddr, Vec<u16>)> = ret.into_iter().map(|t| (t.addr, t.ports)).collect();
+        assert_eq!(
+            ret,
+            vec![
+                (Ipv4Addr::new(192, 168, 1, 10).into(), vec![443]),
+                (Ipv4Addr::new(192, 168, 1, 11).into(), vec![22]),
+                (Ipv6Addr::LOCALHOST.into(), vec![80]),
+            ]
+        );
+        assert_eq!(parser.warnings.len(), 0);
+    }
+    #[test]
+    fn test_dns_failure() {
+        let mut parser = TargetParser::new();
+        parser.resolver = |hostname| match hostname {
+            "good.example.com" => Ok(vec![IpAddr::V4(Ipv4Addr::new(192, 168, 5, 5))]),
+            _ => Err(String::from("no such host")),
+        };
+        let ret = parser.target_from_input("good.example.com,bogus.example.com", None);
+        assert_eq!(ret.len(), 1);
+        assert_eq!(ret[0].origin, Some(String::from("good.example.com")));
+        assert_eq!(parser.warnings.len(), 1);
+        assert!(parser.warnings[0].contains("bogus.example.com"));
+    }
+    #[test]
+    fn test_no_family_addresses() {
+        let mut parser = TargetParser::new();
+        parser.resolver = |hostname| match hostname {
+            "v6.example.com" => Ok(vec![IpAddr::V6(Ipv6Addr::LOCALHOST)]),
+            _ => Err(String::from("no such host")),
+        };
+        // -4 is the default
+        let ret = parser.target_from_input("v6.example.com", None);
+        assert_eq!(ret.len(), 0);
+        assert_eq!(
+            parser.warnings,
+            vec!["hostname v6.example.com resolved to no IPv4 addresses"]
+        );
+    }
+    #[test]
+    fn test_dns_times() {
+        let mut parser = TargetParser::new();
+        parser.resolver = |hostname| match hostname {
+            "example.com" => {
+                std::thread::sleep(Duration::from_millis(50));
+                Ok(vec![IpAddr::V4(Ipv4Addr::new(192, 168, 5, 5))])
+            }
+            _ => Err(String::from("no such host")),
+        };
+        let ret = parser.target_from_input("example.com,192.168.5.6", Some(String::from("80")));
+        assert_eq!(ret.len(), 2);
+        // the targets find the time by their origin
+        let origin = ret[0].origin.clone().unwrap();
+        assert!(parser.dns_times[&origin] >= Duration::from_millis(50));
+        assert_eq!(parser.dns_times.len(), 1);
+    }
+    #[test]
+    fn test_url_targets() {
+        let mut parser = TargetParser::new();
+        parser.resolver = |hostname| match hostname {
+            "example.com" => Ok(vec![IpAddr::V4(Ipv4Addr::new(192, 168, 5, 5))]),
+            _ => Err(String::from("no such host")),
+        };
+        let ret = parser.target_from_input("https://example.com", None);
+        assert_eq!(ret.len(), 1);
+        assert_eq!(ret[0].addr, IpAddr::V4(Ipv4Addr::new(192, 168, 5, 5)));
+        assert_eq!(ret[0].ports, vec![443]);
+        assert_eq!(ret[0].origin, Some(String::from("example.com")));
+
+        let ret = parser.target_from_input("http://example.com:8080/x", Some(String::from("22")));
+        assert_eq!(ret[0].ports, vec![22, 8080]);
+        let ret = parser.target_from_input("ftp://[::1]/pub", None);
+        assert_eq!(ret[0].addr, IpAddr::V6(Ipv6Addr::LOCALHOST));
+        assert_eq!(ret[0].ports, vec![21]);
+
+        // the bare hostname is not changed
+        let ret = parser.target_from_input("example.com", Some(String::from("80")));
+        assert_eq!(ret[0].ports, vec![80]);
+        assert_eq!(ret[0].origin, Some(String::from("example.com")));
+
+        assert!(url_parser("http://example.com:0").is_err());
+        assert_eq!(url_parser("example.com"), Ok(None));
+    }
+    #[test]
+    fn test_fqdn_root_dot() {
+        assert!(is_domain("example.com."));
+        assert!(!is_domain("example.com.."));
+        let mut parser = TargetParser::new();
+        parser.resolver = |hostname| match hostname {
+            "example.com." => Ok(vec![IpAddr::V4(Ipv4Addr::new(192, 168, 5, 5))]),
+            _ => Err(String::from("no such host")),
+        };
+        let ret = parser.target_from_input("example.com.", Some(String::from("80")));
+        assert_eq!(ret.len(), 1);
+        assert_eq!(ret[0].addr, IpAddr::V4(Ipv4Addr::new(192, 168, 5, 5)));
+        assert_eq!(ret[0].origin, Some(String::from("example.com.")));
+        assert_eq!(parser.warnings.len(), 0);
+    }
+    #[test]
+    fn test_dedup_origins() {
+        let mut parser = TargetParser::new();
+        parser.resolver = |hostname| match hostname {
+            "example.com" => Ok(vec![IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4))]),
+            _ => Err(String::from("no such host")),
+        };
+        let ret = parser.target_from_input("example.com,1.2.3.4,1.2.3.5", Some(String::from("80")));
+        assert_eq!(ret.len(), 3);
+        let ret = parser.dedup(ret);
+        let ip = IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4));
+        assert_eq!(ret.len(), 2);
+        assert_eq!(ret[0].addr, ip);
+        assert_eq!(ret[0].ports, vec![80]);
+        assert_eq!(
+            parser.origins.get(&ip),
+            Some(&vec![String::from("example.com"), String::from("literal")])
+        );
+        // the address from only one input is not recorded
+        assert_eq!(parser.origins.len(), 1);
+    }
+    #[test]
+    fn test_zone() {
+        let mut parser = TargetParser::new();
+        let ret = parser.target_from_input("fe80::1%eth0", Some(String::from("22")));
+        let ip = IpAddr::V6(Ipv6Addr::from_str("fe80::1").unwrap());
+        assert_eq!(ret.len(), 1);
+        assert_eq!(ret[0].addr, ip);
+        assert_eq!(ret[0].ports, vec![22]);
+        assert_eq!(parser.zones.get(&ip), Some(&String::from("eth0")));
+        assert_eq!(parser.warnings.len(), 0);
+
+        let mut parser = TargetParser::new();
+        let ret = parser.target_from_input("fe80::1", None);
+        assert_eq!(ret.len(), 0);
+        assert_eq!(parser.warnings.len(), 1);
+        assert!(parser.warnings[0].contains("zone is required"));
+    }
+    #[test]
+    fn test_network_broadcast() {
+        let network = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 0));
+        let broadcast = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 255));
+        let mut parser = TargetParser::new();
+        let ret = parser.target_from_input("192.168.1.0/24", None);
+        assert_eq!(ret.len(), 254);
+        assert!(ret.iter().all(|t| t.addr != network && t.addr != broadcast));
+
+        let mut parser = TargetParser::new().with_network_broadcast(true);
+        let ret = parser.target_from_input("192.168.1.0/24", None);
+        assert_eq!(ret.len(), 256);
+        assert_eq!(ret[0].addr, network);
+        assert_eq!(ret[255].addr, broadcast);
+
+        // the smaller subnets keep all the addresses
+        let mut parser = TargetParser::new();
+        assert_eq!(parser.target_from_input("192.168.1.0/30", None).len(), 4);
+        assert_eq!(parser.target_from_input("192.168.1.5/32", None).len(), 1);
+    }
+    #[test]
+    fn test_octet_ranges() {
+        let mut parser = TargetParser::new();
+        let ret = parser.target_from_input("192.168.1.1-10", None);
+        assert_eq!(ret.len(), 10);
+        assert_eq!(ret[0].addr, IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)));
+        assert_eq!(ret[9].addr, IpAddr::V4(Ipv4Addr::new(192, 168, 1, 10)));
+
+        let ret = parser.target_from_input("10.0-1.0.1", None);
+        assert_eq!(ret.len(), 2);
+        assert_eq!(ret[0].addr, IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)));
+        assert_eq!(ret[1].addr, IpAddr::V4(Ipv4Addr::new(10, 1, 0, 1)));
+
+        // the full address range is not changed
+        let ret = parser.target_from_input("192.168.5.5-192.168.5.10", None);
+        assert_eq!(ret.len(), 6);
+
+        let mut parser = TargetParser::new().with_max_hosts(100);
+        let ret = parser.target_from_input("10.0-1.0-255.1", None);
+        assert_eq!(ret.len(), 0);
+        assert_eq!(parser.warnings.len(), 1);
+        assert!(parser.warnings[0].contains("512 hosts"));
+    }
+    #[test]
+    fn test_dns_concurrency() {
+        static RUNNING: AtomicUsize = AtomicUsize::new(0);
+        static MAX_RUNNING: AtomicUsize = AtomicUsize::new(0);
+        let mut parser = TargetParser::new().with_dns_concurrency(4);
+        parser.resolver = |hostname| {
+            let running = RUNNING.fetch_add(1, Ordering::SeqCst) + 1;
+            MAX_RUNNING.fetch_max(running, Ordering::SeqCst);
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            RUNNING.fetch_sub(1, Ordering::SeqCst);
+            // host7.example.com -> 10.0.0.7
+            let i: u8 = hostname
+                .trim_start_matches("host")
+                .trim_end_matches(".example.com")
+                .parse()
+                .unwrap();
+            Ok(vec![IpAddr::V4(Ipv4Addr::new(10, 0, 0, i))])
+        };
+        let hostnames: Vec<String> = (0..20).map(|i| format!("host{}.example.com", i)).collect();
+        let ret = parser.target_from_input(&hostnames.join(","), None);
+        assert_eq!(ret.len(), 20);
+        for (i, t) in ret.iter().enumerate() {
+            assert_eq!(t.addr, IpAddr::V4(Ipv4Addr::new(10, 0, 0, i as u8)));
+        }
+        let max_running = MAX_RUNNING.load(Ordering::SeqCst);
+        assert!(max_running <= 4 && max_running > 1);
+    }
 }
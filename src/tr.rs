@@ -0,0 +1,193 @@
+/// traceroute
+use pistol::Target;
+use pistol::layer::infer_addr;
+use pistol::trace::HopStatus;
+use pistol::trace::icmp::send_icmp_trace_packet;
+use pistol::trace::icmpv6::send_icmpv6_trace_packet;
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::SRC_ADDR;
+use crate::output::Emitter;
+use crate::output::HopRecord;
+use crate::output::OutputFormat;
+use crate::output::TraceRecord;
+
+const MAX_HOPS: u8 = 30;
+/// The method name of the output records.
+pub const TRACEROUTE_METHOD: &str = "traceroute";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hop {
+    pub ttl: u8,
+    /// None means no response for this ttl.
+    pub addr: Option<IpAddr>,
+    pub rtt: Duration,
+}
+
+/// Send the icmp probes with increasing ttl (hop limit) until the target replies.
+fn trace_hops(dst_addr: IpAddr, timeout: Option<Duration>) -> Vec<Hop> {
+    let src_addr = *SRC_ADDR.lock().expect("lock SRC_ADDR failed");
+    let ia = match infer_addr(dst_addr, src_addr) {
+        Ok(Some(ia)) => ia,
+        _ => return Vec::new(),
+    };
+
+    let mut hops = Vec::new();
+    let icmp_id = rand::random();
+    let ip_id_base: u16 = rand::random::<u16>() % (u16::MAX - MAX_HOPS as u16);
+    for ttl in 1..=MAX_HOPS {
+        let ip_id = ip_id_base + ttl as u16;
+        let ret = match (ia.dst_addr, ia.src_addr) {
+            (IpAddr::V4(dst_ipv4), IpAddr::V4(src_ipv4)) => {
+                send_icmp_trace_packet(dst_ipv4, src_ipv4, ip_id, ttl, icmp_id, ttl as u16, timeout)
+            }
+            (IpAddr::V6(dst_ipv6), IpAddr::V6(src_ipv6)) => {
+                send_icmpv6_trace_packet(dst_ipv6, src_ipv6, ttl, icmp_id, ttl as u16, timeout)
+            }
+            _ => break,
+        };
+
+        let (hop_status, rtt) = match ret {
+            Ok(r) => r,
+            Err(_) => (HopStatus::NoResponse, Duration::ZERO),
+        };
+        let (addr, done) = match hop_status {
+            HopStatus::TimeExceeded(addr) => (Some(addr), false),
+            HopStatus::Unreachable(addr) => (Some(addr), true),
+            HopStatus::RecvReply(addr) => (Some(addr), true),
+            HopStatus::NoResponse => (None, false),
+        };
+        hops.push(Hop { ttl, addr, rtt });
+        if done {
+            break;
+        }
+    }
+    hops
+}
+
+/// Like nmap, when the first hops of a path are the same as the path of a host already printed,
+/// only print `hops 1-n are the same as for <host>` and the rest hops.
+/// Returns the (addr, shared hops number, the host shares the path) of each path.
+fn dedup_paths(paths: &BTreeMap<IpAddr, Vec<Hop>>) -> Vec<(IpAddr, usize, Option<IpAddr>)> {
+    let mut ret = Vec::new();
+    let mut printed: Vec<(IpAddr, &Vec<Hop>)> = Vec::new();
+    for (addr, hops) in paths {
+        let mut best: Option<(usize, IpAddr)> = None;
+        for (prev_addr, prev_hops) in &printed {
+            let shared = hops
+                .iter()
+                .zip(prev_hops.iter())
+                .take_while(|(a, b)| a.addr.is_some() && a.addr == b.addr)
+                .count();
+            // do not hide the last hop (the target itself)
+            let shared = shared.min(hops.len().saturating_sub(1));
+            if shared > 0 && best.is_none_or(|(n, _)| shared > n) {
+                best = Some((shared, *prev_addr));
+            }
+        }
+        match best {
+            Some((n, prev_addr)) => ret.push((*addr, n, Some(prev_addr))),
+            None => ret.push((*addr, 0, None)),
+        }
+        printed.push((*addr, hops));
+    }
+    ret
+}
+
+fn hop_line(hop: &Hop) -> String {
+    match hop.addr {
+        Some(addr) => format!("  {:<4}{:.2}s  {}", hop.ttl, hop.rtt.as_secs_f64(), addr),
+        None => format!("  {:<4}...", hop.ttl),
+    }
+}
+
+fn trace_lines(paths: &BTreeMap<IpAddr, Vec<Hop>>) -> Vec<(IpAddr, String)> {
+    let mut ret = Vec::new();
+    for (addr, shared, prev_addr) in dedup_paths(paths) {
+        let hops = &paths[&addr];
+        let mut lines = vec![format!("traceroute to {} ({} hops)", addr, hops.len())];
+        if let Some(prev_addr) = prev_addr {
+            lines.push(format!(
+                "  hops 1-{} are the same as for {}",
+                shared, prev_addr
+            ));
+        }
+        for hop in hops.iter().skip(shared) {
+            lines.push(hop_line(hop));
+        }
+        ret.push((addr, lines.join("\n")));
+    }
+    ret
+}
+
+pub fn traceroute(targets: &[Target], timeout: f64, output_format: OutputFormat) {
+    let start = Instant::now();
+    let timeout = Some(Duration::from_secs_f64(timeout));
+
+    // sorted
+    let mut btm = BTreeMap::new();
+    for target in targets {
+        let hops = trace_hops(target.addr, timeout);
+        btm.insert(target.addr, hops);
+    }
+
+    let mut emitter = Emitter::stdout(output_format).with_method(TRACEROUTE_METHOD);
+    for (addr, line) in trace_lines(&btm) {
+        let hops = btm[&addr]
+            .iter()
+            .map(|h| HopRecord {
+                ttl: h.ttl,
+                addr: h.addr.map(|a| a.to_string()),
+                cost: h.rtt.as_secs_f64(),
+            })
+            .collect();
+        let record = TraceRecord {
+            addr: addr.to_string(),
+            hops,
+        };
+        emitter.emit(Some(line), &record);
+    }
+
+    let tail = format!(
+        "pslmap done: {} ip addresses traced in {:.2} seconds",
+        targets.len(),
+        start.elapsed().as_secs_f64()
+    );
+    emitter.finish(&tail);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+    #[test]
+    fn test_dedup_paths() {
+        let hop = |ttl: u8, last: u8| Hop {
+            ttl,
+            addr: Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, last))),
+            rtt: Duration::from_millis(ttl as u64),
+        };
+        let host_1 = IpAddr::V4(Ipv4Addr::new(192, 168, 5, 1));
+        let host_2 = IpAddr::V4(Ipv4Addr::new(192, 168, 5, 2));
+        let mut paths = BTreeMap::new();
+        // inserted out of order, the output is sorted by address
+        paths.insert(host_2, vec![hop(1, 1), hop(2, 2), hop(3, 4)]);
+        paths.insert(host_1, vec![hop(1, 1), hop(2, 2), hop(3, 3)]);
+
+        let ret = dedup_paths(&paths);
+        assert_eq!(ret, vec![(host_1, 0, None), (host_2, 2, Some(host_1))]);
+
+        let lines = trace_lines(&paths);
+        assert_eq!(lines[0].0, host_1);
+        assert!(
+            lines[1]
+                .1
+                .contains("hops 1-2 are the same as for 192.168.5.1")
+        );
+        assert!(lines[1].1.contains("10.0.0.4"));
+        assert!(!lines[1].1.contains("10.0.0.2"));
+    }
+}
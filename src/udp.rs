@@ -0,0 +1,171 @@
+/// udp probes in batches under the icmp rate limit of the targets (--udp-batch)
+use pistol::Target;
+use pistol::scan::PortReport;
+use pistol::scan::PortStatus;
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::sync::LazyLock;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::error::PslmapError;
+
+/// Linux sends about one icmp port unreachable per second to each host (net.ipv4.icmp_ratelimit).
+const UDP_BATCH_INTERVAL: Duration = Duration::from_secs(1);
+/// The interval stops doubling here.
+const UDP_BATCH_MAX_INTERVAL: Duration = Duration::from_secs(8);
+
+static UDP_BATCH: LazyLock<Mutex<Option<usize>>> = LazyLock::new(|| Mutex::new(None));
+
+pub fn set_udp_batch(batch: Option<usize>) {
+    *UDP_BATCH.lock().expect("lock UDP_BATCH failed") = batch;
+}
+
+pub fn udp_batch() -> Option<usize> {
+    *UDP_BATCH.lock().expect("lock UDP_BATCH failed")
+}
+
+/// The i-th batch holds the i-th `batch` ports of every target.
+fn udp_batches(targets: &[Target], batch: usize) -> Vec<Vec<Target>> {
+    let mut batches: Vec<Vec<Target>> = Vec::new();
+    for t in targets {
+        for (i, ports) in t.ports.chunks(batch.max(1)).enumerate() {
+            if batches.len() <= i {
+                batches.push(Vec::new());
+            }
+            let mut bt = t.clone();
+            bt.ports = ports.to_vec();
+            batches[i].push(bt);
+        }
+    }
+    batches
+}
+
+fn closed_hosts(reports: &[PortReport]) -> HashSet<IpAddr> {
+    reports
+        .iter()
+        .filter(|r| r.status == PortStatus::Closed)
+        .map(|r| r.addr)
+        .collect()
+}
+
+/// Scan the batches one interval apart. When a host answered the previous batch with
+/// port unreachable but is silent now, the responses most likely ceased because of the rate limit,
+/// so the interval is doubled and the batch of that host is probed again.
+pub fn udp_batch_scan<F, S>(
+    targets: &[Target],
+    batch: usize,
+    mut scan: F,
+    mut sleep: S,
+) -> Result<Vec<PortReport>, PslmapError>
+where
+    F: FnMut(&[Target]) -> Result<Vec<PortReport>, PslmapError>,
+    S: FnMut(Duration),
+{
+    let mut interval = UDP_BATCH_INTERVAL;
+    let mut replied = HashSet::new();
+    let mut reports = Vec::new();
+    for (i, batch_targets) in udp_batches(targets, batch).into_iter().enumerate() {
+        if i > 0 {
+            sleep(interval);
+        }
+        let mut ret = scan(&batch_targets)?;
+        let closed = closed_hosts(&ret);
+        let ceased: Vec<IpAddr> = batch_targets
+            .iter()
+            .map(|t| t.addr)
+            .filter(|addr| replied.contains(addr) && !closed.contains(addr))
+            .collect();
+        if !ceased.is_empty() && interval < UDP_BATCH_MAX_INTERVAL {
+            interval = (interval * 2).min(UDP_BATCH_MAX_INTERVAL);
+            let addrs: Vec<String> = ceased.iter().map(|a| a.to_string()).collect();
+            eprintln!(
+                "warning: the icmp responses of [{}] ceased (rate limited), the udp batches are slowed down to {:.1}s",
+                addrs.join(", "),
+                interval.as_secs_f64()
+            );
+            sleep(interval);
+            let retry: Vec<Target> = batch_targets
+                .into_iter()
+                .filter(|t| ceased.contains(&t.addr))
+                .collect();
+            ret.retain(|r| !ceased.contains(&r.addr));
+            ret.extend(scan(&retry)?);
+        }
+        replied = closed_hosts(&ret);
+        reports.extend(ret);
+    }
+    Ok(reports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+    fn report(addr: IpAddr, port: u16, status: PortStatus) -> PortReport {
+        PortReport {
+            addr,
+            port,
+            origin: None,
+            status,
+            cost: Duration::ZERO,
+        }
+    }
+    #[test]
+    fn test_udp_batch_scan() {
+        let addr = IpAddr::V4(Ipv4Addr::new(192, 168, 5, 5));
+        let targets = vec![Target::new(addr, Some((1..=100).collect()))];
+        let mut slept = Duration::ZERO;
+        let mut sent = 0;
+        let ret = udp_batch_scan(
+            &targets,
+            10,
+            |targets| {
+                let reports: Vec<PortReport> = targets
+                    .iter()
+                    .flat_map(|t| {
+                        t.ports
+                            .iter()
+                            .map(|&p| report(t.addr, p, PortStatus::Closed))
+                    })
+                    .collect();
+                sent += reports.len();
+                Ok(reports)
+            },
+            |d| slept += d,
+        )
+        .unwrap();
+        assert_eq!(ret.len(), 100);
+        // 10 batches one second apart, at most 100 probes in 9 seconds
+        let rate = sent as f64 / slept.as_secs_f64();
+        assert!(rate < 12.0, "udp probe rate {} per second", rate);
+
+        // the icmp responses cease at the second batch and come back after slowing down
+        let mut scans = 0;
+        let mut sleeps = Vec::new();
+        let targets = vec![Target::new(addr, Some((1..=4).collect()))];
+        let ret = udp_batch_scan(
+            &targets,
+            2,
+            |targets| {
+                scans += 1;
+                let status = if scans == 2 {
+                    PortStatus::OpenOrFiltered
+                } else {
+                    PortStatus::Closed
+                };
+                Ok(targets[0]
+                    .ports
+                    .iter()
+                    .map(|&p| report(addr, p, status))
+                    .collect())
+            },
+            |d| sleeps.push(d),
+        )
+        .unwrap();
+        assert_eq!(scans, 3);
+        assert_eq!(sleeps, vec![Duration::from_secs(1), Duration::from_secs(2)]);
+        assert_eq!(ret.len(), 4);
+        assert!(ret.iter().all(|r| r.status == PortStatus::Closed));
+    }
+}
@@ -0,0 +1,163 @@
+/// repeat the same scan at a fixed interval (--repeat and --interval)
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::net::IpAddr;
+use std::time::Duration;
+
+/// The rounds of the repeated scan, `repeat` 0 means running until killed.
+#[derive(Debug, Clone, Copy)]
+pub struct Repeat {
+    repeat: usize,
+    interval: Duration,
+    round: usize,
+}
+
+impl Repeat {
+    pub fn new(repeat: usize, interval: f64) -> Result<Repeat, String> {
+        if !(interval >= 0.0 && interval.is_finite()) {
+            return Err(format!(
+                "invalid interval {}s: it must be a non-negative number of seconds",
+                interval
+            ));
+        }
+        Ok(Repeat {
+            repeat,
+            interval: Duration::from_secs_f64(interval),
+            round: 0,
+        })
+    }
+    /// Such as `# round 2/5 2026-01-01T00:00:00+08:00`.
+    pub fn round_line(&self, now: &str) -> String {
+        if self.repeat == 0 {
+            format!("# round {} {}", self.round, now)
+        } else {
+            format!("# round {}/{} {}", self.round, self.repeat, now)
+        }
+    }
+    /// Start the next round, the interval is slept between the rounds.
+    pub fn next_round(&mut self) -> bool {
+        if self.repeat > 0 && self.round >= self.repeat {
+            return false;
+        }
+        if self.round > 0 {
+            std::thread::sleep(self.interval);
+        }
+        self.round += 1;
+        true
+    }
+}
+
+/// The ports newly opened or closed since the previous round,
+/// such as `192.168.1.1:22 closed -> open`.
+pub fn port_diff(
+    prev: &BTreeMap<(IpAddr, u16), String>,
+    curr: &BTreeMap<(IpAddr, u16), String>,
+) -> Vec<String> {
+    let mut lines = Vec::new();
+    for ((addr, port), status) in curr {
+        if let Some(prev_status) = prev.get(&(*addr, *port))
+            && prev_status != status
+            && (prev_status == "open" || status == "open")
+        {
+            lines.push(format!("{}:{} {} -> {}", addr, port, prev_status, status));
+        }
+    }
+    lines
+}
+
+/// The open ports of all the rounds (--union-summary).
+#[derive(Debug, Clone, Default)]
+pub struct UnionSummary {
+    rounds: Vec<BTreeSet<(IpAddr, u16)>>,
+}
+
+impl UnionSummary {
+    pub fn new() -> UnionSummary {
+        UnionSummary::default()
+    }
+    pub fn add_round(&mut self, port_status: &BTreeMap<(IpAddr, u16), String>) {
+        let open = port_status
+            .iter()
+            .filter(|(_, status)| *status == "open")
+            .map(|(key, _)| *key)
+            .collect();
+        self.rounds.push(open);
+    }
+    /// Such as `192.168.1.1:22 open in 2/3 rounds, flaps 2`,
+    /// the flaps count the rounds where the port changed between open and not open.
+    pub fn lines(&self) -> Vec<String> {
+        let union: BTreeSet<&(IpAddr, u16)> = self.rounds.iter().flatten().collect();
+        let mut lines = Vec::new();
+        for key in union {
+            let open: Vec<bool> = self.rounds.iter().map(|r| r.contains(key)).collect();
+            let open_rounds = open.iter().filter(|o| **o).count();
+            let flaps = open.windows(2).filter(|w| w[0] != w[1]).count();
+            lines.push(format!(
+                "{}:{} open in {}/{} rounds, flaps {}",
+                key.0,
+                key.1,
+                open_rounds,
+                open.len(),
+                flaps
+            ));
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+    #[test]
+    fn test_port_diff() {
+        let addr = IpAddr::V4(Ipv4Addr::new(192, 168, 5, 5));
+        let round = |statuses: [&str; 3]| -> BTreeMap<(IpAddr, u16), String> {
+            [22, 80, 443]
+                .into_iter()
+                .zip(statuses)
+                .map(|(port, status)| ((addr, port), status.to_string()))
+                .collect()
+        };
+        let first = round(["open", "closed", "filtered"]);
+        let second = round(["open", "open", "closed"]);
+        // filtered -> closed is not a change of the open ports
+        assert_eq!(
+            port_diff(&first, &second),
+            vec!["192.168.5.5:80 closed -> open"]
+        );
+        assert_eq!(port_diff(&second, &second), Vec::<String>::new());
+
+        let mut repeat = Repeat::new(2, 0.0).unwrap();
+        assert!(repeat.next_round());
+        assert_eq!(repeat.round_line("now"), "# round 1/2 now");
+        assert!(repeat.next_round());
+        assert!(!repeat.next_round());
+        assert!(Repeat::new(0, -1.0).is_err());
+    }
+    #[test]
+    fn test_union_summary() {
+        let addr = IpAddr::V4(Ipv4Addr::new(192, 168, 5, 5));
+        let mut summary = UnionSummary::new();
+        for statuses in [
+            ["open", "open", "closed"],
+            ["closed", "open", "filtered"],
+            ["open", "open", "closed"],
+        ] {
+            let round: BTreeMap<(IpAddr, u16), String> = [22, 80, 443]
+                .into_iter()
+                .zip(statuses)
+                .map(|(port, status)| ((addr, port), status.to_string()))
+                .collect();
+            summary.add_round(&round);
+        }
+        // 443 is never open
+        assert_eq!(
+            summary.lines(),
+            vec![
+                "192.168.5.5:22 open in 2/3 rounds, flaps 2",
+                "192.168.5.5:80 open in 3/3 rounds, flaps 0",
+            ]
+        );
+    }
+}
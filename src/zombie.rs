@@ -0,0 +1,185 @@
+/// the ip id sequence check of the idle scan zombie (same as nmap -sI)
+use pistol::layer::Layer3Match;
+use pistol::layer::Layer4MatchTcpUdp;
+use pistol::layer::LayerMatch;
+use pistol::layer::infer_addr;
+use pistol::layer::layer3_ipv4_send;
+use pnet::packet::Packet;
+use pnet::packet::ip::IpNextHeaderProtocols;
+use pnet::packet::ipv4::Ipv4Packet;
+use pnet::packet::tcp::TcpFlags;
+use pnet::packet::tcp::TcpPacket;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use crate::SRC_ADDR;
+use crate::bs::build_tcp_packet;
+use crate::sport::next_source_port;
+
+/// Same as nmap, six syn/ack probes are sent to the zombie before the scan.
+const ZOMBIE_PROBES: usize = 6;
+/// The largest step between two ip ids that still counts as incremental.
+const INCREMENTAL_STEP: u16 = 10;
+/// The largest step of the byte swapped (little endian) counters.
+const BROKEN_INCREMENTAL_STEP: u16 = 5120;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IpIdClass {
+    Incremental,
+    BrokenIncremental,
+    Zero,
+    Constant,
+    Random,
+    Unknown,
+}
+
+impl IpIdClass {
+    /// Only a global counter leaks the packets the zombie sends to the target.
+    pub fn suitable(&self) -> bool {
+        matches!(self, IpIdClass::Incremental | IpIdClass::BrokenIncremental)
+    }
+}
+
+/// Classify the ip ids of the rst replies in the order they were received.
+pub fn ipid_class(ipids: &[u16]) -> IpIdClass {
+    if ipids.len() < 2 {
+        return IpIdClass::Unknown;
+    }
+    if ipids.iter().all(|&id| id == 0) {
+        return IpIdClass::Zero;
+    }
+    let steps: Vec<u16> = ipids.windows(2).map(|w| w[1].wrapping_sub(w[0])).collect();
+    if steps.iter().all(|&s| s == 0) {
+        IpIdClass::Constant
+    } else if steps.iter().all(|&s| s > 0 && s <= INCREMENTAL_STEP) {
+        IpIdClass::Incremental
+    } else if steps
+        .iter()
+        .all(|&s| s > 0 && s % 256 == 0 && s <= BROKEN_INCREMENTAL_STEP)
+    {
+        IpIdClass::BrokenIncremental
+    } else {
+        IpIdClass::Random
+    }
+}
+
+/// Refuse the unsuitable zombie, or only warn about it when `force` is set.
+pub fn zombie_check(
+    zombie_ipv4: Ipv4Addr,
+    class: IpIdClass,
+    force: bool,
+) -> Result<Option<String>, String> {
+    if class.suitable() {
+        return Ok(None);
+    }
+    let reason = match class {
+        IpIdClass::Unknown => format!("zombie {} did not answer the ip id probes", zombie_ipv4),
+        _ => format!(
+            "zombie {} ip id sequence is {:?}, the idle scan needs an incremental one",
+            zombie_ipv4, class
+        ),
+    };
+    if force {
+        Ok(Some(format!("{}, the results are unreliable", reason)))
+    } else {
+        Err(format!("{} (use --force-zombie to scan anyway)", reason))
+    }
+}
+
+fn send_ipid_probe(
+    zombie_ipv4: Ipv4Addr,
+    zombie_port: u16,
+    src_ipv4: Ipv4Addr,
+    timeout: Option<Duration>,
+) -> Option<u16> {
+    let src_port = next_source_port();
+    let flags = TcpFlags::SYN | TcpFlags::ACK;
+    let packet = build_tcp_packet(
+        zombie_ipv4,
+        zombie_port,
+        src_ipv4,
+        src_port,
+        flags,
+        false,
+        &[],
+    );
+
+    let layer3 = Layer3Match {
+        name: "zombie layer3",
+        layer2: None,
+        src_addr: Some(zombie_ipv4.into()),
+        dst_addr: Some(src_ipv4.into()),
+    };
+    let layer4_tcp_udp = Layer4MatchTcpUdp {
+        name: "zombie tcp_udp",
+        layer3: Some(layer3),
+        src_port: Some(zombie_port),
+        dst_port: Some(src_port),
+    };
+    let layer_matchs = vec![LayerMatch::Layer4MatchTcpUdp(layer4_tcp_udp)];
+
+    let (ret, _) =
+        layer3_ipv4_send(zombie_ipv4, src_ipv4, &packet, layer_matchs, timeout, true).ok()?;
+    let ipv4_packet = Ipv4Packet::new(&ret)?;
+    if ipv4_packet.get_next_level_protocol() != IpNextHeaderProtocols::Tcp {
+        return None;
+    }
+    let tcp_packet = TcpPacket::new(ipv4_packet.payload())?;
+    if tcp_packet.get_flags() & TcpFlags::RST == TcpFlags::RST {
+        Some(ipv4_packet.get_identification())
+    } else {
+        None
+    }
+}
+
+/// The ip ids of the rst replies the zombie sent to the unexpected syn/ack probes.
+pub fn zombie_ipids(
+    zombie_ipv4: Ipv4Addr,
+    zombie_port: u16,
+    timeout: Option<Duration>,
+) -> Vec<u16> {
+    let src_addr = *SRC_ADDR.lock().expect("lock SRC_ADDR failed");
+    let src_ipv4 = match infer_addr(zombie_ipv4.into(), src_addr) {
+        Ok(Some(ia)) => match ia.src_addr {
+            IpAddr::V4(src_ipv4) => src_ipv4,
+            IpAddr::V6(_) => return Vec::new(),
+        },
+        _ => return Vec::new(),
+    };
+    (0..ZOMBIE_PROBES)
+        .filter_map(|_| send_ipid_probe(zombie_ipv4, zombie_port, src_ipv4, timeout))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_zombie_check() {
+        assert_eq!(
+            ipid_class(&[100, 101, 102, 104, 105, 106]),
+            IpIdClass::Incremental
+        );
+        assert_eq!(ipid_class(&[65534, 65535, 0, 1]), IpIdClass::Incremental);
+        assert_eq!(ipid_class(&[256, 512, 768]), IpIdClass::BrokenIncremental);
+        assert_eq!(ipid_class(&[0, 0, 0]), IpIdClass::Zero);
+        assert_eq!(ipid_class(&[7, 7, 7]), IpIdClass::Constant);
+        assert_eq!(ipid_class(&[100]), IpIdClass::Unknown);
+        let random = ipid_class(&[41233, 902, 17755, 60021, 3318, 29470]);
+        assert_eq!(random, IpIdClass::Random);
+
+        let zombie = Ipv4Addr::new(192, 168, 1, 5);
+        assert_eq!(
+            zombie_check(zombie, IpIdClass::Incremental, false),
+            Ok(None)
+        );
+        let err = zombie_check(zombie, random, false).unwrap_err();
+        assert!(err.contains("192.168.1.5 ip id sequence is Random"));
+        assert!(err.contains("--force-zombie"));
+        let warning = zombie_check(zombie, random, true).unwrap().unwrap();
+        assert!(warning.contains("unreliable"));
+        let err = zombie_check(zombie, IpIdClass::Unknown, false).unwrap_err();
+        assert!(err.contains("did not answer"));
+    }
+}